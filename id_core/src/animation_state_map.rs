@@ -1,7 +1,7 @@
 use std::collections::HashMap;
 
 use id_game_config::GameConfig;
-use id_map_format::{Texture, Wad};
+use id_map_format::{AnimatedDef, AnimatedKind, Texture, Wad, WadError};
 
 use indexmap::IndexMap;
 
@@ -10,18 +10,73 @@ use crate::{
     helpers::ChangedSet,
 };
 
+/// Vanilla animates every flat and texture at the same speed, in tics.
+const DEFAULT_ANIMATION_SPEED: u32 = 8;
+
+#[derive(Debug, Clone)]
+struct AnimationStep {
+    next: CTexture,
+    /// How many tics each frame of the sequence is shown for.
+    speed: u32,
+}
+
 pub struct AnimationStateMap {
-    states: HashMap<CTexture, CTexture>,
+    states: HashMap<CTexture, AnimationStep>,
+    /// Maps each switch texture to its other state, in both directions.
+    switches: HashMap<String, String>,
 }
 
 impl AnimationStateMap {
+    /// Builds the animations from the game config, then the Boom ANIMATED and
+    /// SWITCHES lumps of each WAD in load order. Later definitions win.
     pub fn from_game_config(
         game_config: &GameConfig,
         wad: &Wad,
         pwad: &[Wad],
         textures: &IndexMap<String, Texture>,
     ) -> Self {
-        let mut states = HashMap::<CTexture, CTexture>::new();
+        let mut sequences = Vec::new();
+        for (start, end) in &game_config.flats {
+            sequences.push(AnimatedDef {
+                kind: AnimatedKind::Flat,
+                first: start.clone(),
+                last: end.clone(),
+                speed: DEFAULT_ANIMATION_SPEED,
+            });
+        }
+        for (start, end) in &game_config.walls {
+            sequences.push(AnimatedDef {
+                kind: AnimatedKind::Texture,
+                first: start.clone(),
+                last: end.clone(),
+                speed: DEFAULT_ANIMATION_SPEED,
+            });
+        }
+
+        let mut switches = HashMap::new();
+        for (off, on) in &game_config.switches {
+            switches.insert(off.clone(), on.clone());
+            switches.insert(on.clone(), off.clone());
+        }
+
+        for wad in std::iter::once(wad).chain(pwad) {
+            match wad.parse_animated() {
+                Ok(animated) => sequences.extend(animated),
+                Err(WadError::MissingLump(_)) => {}
+                Err(e) => eprintln!("Failed to parse ANIMATED: {}", e),
+            }
+
+            match wad.parse_switches() {
+                Ok(wad_switches) => {
+                    for switch in wad_switches {
+                        switches.insert(switch.off.clone(), switch.on.clone());
+                        switches.insert(switch.on, switch.off);
+                    }
+                }
+                Err(WadError::MissingLump(_)) => {}
+                Err(e) => eprintln!("Failed to parse SWITCHES: {}", e),
+            }
+        }
 
         // Animation state transitions are defined as pointers in the WAD lump.
         // Their names are only by convention.
@@ -33,59 +88,43 @@ impl AnimationStateMap {
         for pwad in pwad {
             lump_names_in_order.extend_from_slice(&pwad.lump_names_in_order);
         }
-
-        for (start, end) in &game_config.flats {
-            // Flats are stored in the wad as individual lumps.
-            let start_idx = lump_names_in_order.iter().position(|name| name == start);
-            let end_idx = lump_names_in_order.iter().position(|name| name == end);
-
-            if let (Some(start_idx), Some(end_idx)) = (start_idx, end_idx) {
-                // Create state transitions for every flat between start_idx and end_idx.
-                for i in start_idx..end_idx + 1 {
-                    let start_name = &lump_names_in_order[i];
-                    let end_name =
-                        &lump_names_in_order[if i + 1 > end_idx { start_idx } else { i + 1 }];
-
-                    states.insert(
-                        CTexture::Flat(start_name.clone()),
-                        CTexture::Flat(end_name.clone()),
-                    );
+        // Textures are stored in the texture lump.
+        let texture_names = textures.keys().cloned().collect::<Vec<_>>();
+
+        let mut states = HashMap::<CTexture, AnimationStep>::new();
+        for sequence in &sequences {
+            let (names, c_texture): (_, fn(String) -> CTexture) = match sequence.kind {
+                // Flats are stored in the wad as individual lumps.
+                AnimatedKind::Flat => (&lump_names_in_order, CTexture::Flat),
+                AnimatedKind::Texture => (&texture_names, CTexture::Texture),
+            };
+
+            let start_idx = names.iter().position(|name| *name == sequence.first);
+            let end_idx = names.iter().position(|name| *name == sequence.last);
+
+            match (start_idx, end_idx) {
+                (Some(start_idx), Some(end_idx)) if start_idx <= end_idx => {
+                    // Create state transitions for every name between start_idx and end_idx.
+                    for i in start_idx..end_idx + 1 {
+                        let next_idx = if i + 1 > end_idx { start_idx } else { i + 1 };
+
+                        states.insert(
+                            c_texture(names[i].clone()),
+                            AnimationStep {
+                                next: c_texture(names[next_idx].clone()),
+                                speed: sequence.speed,
+                            },
+                        );
+                    }
                 }
-            } else {
-                eprintln!(
-                    "Failed to find animation states for flats: {} -> {}",
-                    start, end
-                );
+                _ => eprintln!(
+                    "Failed to find animation states for {:?}: {} -> {}",
+                    sequence.kind, sequence.first, sequence.last
+                ),
             }
         }
 
-        for (start, end) in &game_config.walls {
-            let keys = textures.keys().collect::<Vec<_>>();
-
-            // Textures are stored in the texture lump.
-            let start_idx = keys.iter().position(|name| *name == start);
-            let end_idx = keys.iter().position(|name| *name == end);
-
-            if let (Some(start_idx), Some(end_idx)) = (start_idx, end_idx) {
-                // Create state transitions for every wall between start_idx and end_idx.
-                for i in start_idx..end_idx + 1 {
-                    let start_name = keys[i];
-                    let end_name = keys[if i + 1 > end_idx { start_idx } else { i + 1 }];
-
-                    states.insert(
-                        CTexture::Texture(start_name.clone()),
-                        CTexture::Texture(end_name.clone()),
-                    );
-                }
-            } else {
-                eprintln!(
-                    "Failed to find animation states for walls: {} -> {}",
-                    start, end
-                );
-            }
-        }
-
-        Self { states }
+        Self { states, switches }
     }
 
     pub fn contains_key(&self, c_texture: &CTexture) -> bool {
@@ -93,15 +132,28 @@ impl AnimationStateMap {
     }
 
     pub fn get(&self, c_texture: &CTexture) -> Option<CTexture> {
-        self.states.get(c_texture).cloned()
+        self.states.get(c_texture).map(|step| step.next.clone())
+    }
+
+    /// Returns the other state of a switch texture, e.g. `SW2BRCOM` for `SW1BRCOM`.
+    pub fn switch_texture(&self, name: &str) -> Option<&str> {
+        self.switches.get(name).map(String::as_str)
+    }
+
+    /// Whether a texture moves on to its next frame on game tic `tic`.
+    fn advances_at(&self, c_texture: &CTexture, tic: u32) -> Option<CTexture> {
+        let step = self.states.get(c_texture)?;
+        tic.is_multiple_of(step.speed).then(|| step.next.clone())
     }
 
     pub fn keys(&self) -> impl Iterator<Item = &CTexture> {
         self.states.keys()
     }
 
+    /// Advances the textures whose sequence is due on game tic `tic`.
     pub fn animate_world(
         &self,
+        tic: u32,
         changed_set: &mut ChangedSet<hecs::Entity>,
         world: &mut hecs::World,
     ) {
@@ -109,7 +161,7 @@ impl AnimationStateMap {
         for (id, (c_texture, _c_texture_anim)) in
             world.query_mut::<(&mut CTexture, &CTextureAnimated)>()
         {
-            if let Some(next) = self.get(c_texture) {
+            if let Some(next) = self.advances_at(c_texture, tic) {
                 *c_texture = next;
                 changed_set.change(id);
             }
@@ -119,7 +171,7 @@ impl AnimationStateMap {
         for (id, (c_texture, _c_texture_anim)) in
             world.query_mut::<(&mut CTextureFloor, &CTextureAnimated)>()
         {
-            if let Some(next) = self.get(&c_texture.0) {
+            if let Some(next) = self.advances_at(&c_texture.0, tic) {
                 c_texture.0 = next;
                 changed_set.change(id);
             }
@@ -0,0 +1,283 @@
+use std::collections::HashSet;
+
+use id_map_format::{Blockmap, Map, BLOCKMAP_BLOCK_SIZE};
+use ultraviolet::Vec2;
+
+/// A linedef crossed by [BlockmapAccel::query_ray].
+#[derive(Debug, Clone, Copy)]
+pub struct BlockmapRayHit {
+    pub linedef_index: usize,
+    /// How far along the ray the hit is, between 0 and 1.
+    pub fraction: f32,
+    pub point: Vec2,
+}
+
+/// [BlockmapAccel] answers "which linedefs are near here", for collision,
+/// hitscan and use-line checks.
+///
+/// It's backed by the map's BLOCKMAP, or one we generate if the map doesn't
+/// have one.
+pub struct BlockmapAccel {
+    blockmap: Blockmap,
+    /// Start and end vertex of each linedef, indexed like `Map::linedefs`.
+    ///
+    /// [None] for linedefs with a vertex that doesn't exist.
+    lines: Vec<Option<(Vec2, Vec2)>>,
+}
+
+impl BlockmapAccel {
+    pub fn new(map: &Map) -> Self {
+        let blockmap = match &map.blockmap {
+            Some(blockmap) => blockmap.clone(),
+            None => Blockmap::generate(map),
+        };
+
+        let lines = map
+            .linedefs
+            .iter()
+            .map(|linedef| {
+                let start = map.vertices.get(linedef.start_vertex_idx as usize)?;
+                let end = map.vertices.get(linedef.end_vertex_idx as usize)?;
+                Some((
+                    Vec2::new(start.x as f32, start.y as f32),
+                    Vec2::new(end.x as f32, end.y as f32),
+                ))
+            })
+            .collect();
+
+        Self { blockmap, lines }
+    }
+
+    /// Calls `callback` once for every linedef listed in a block overlapping
+    /// the box.
+    ///
+    /// This is a broad phase: the linedef itself may not touch the box.
+    pub fn query_box<F: FnMut(usize)>(&self, min: Vec2, max: Vec2, mut callback: F) {
+        let (min_column, min_row) = self.clamped_block(min);
+        let (max_column, max_row) = self.clamped_block(max);
+
+        let mut visited = HashSet::new();
+        for row in min_row..=max_row {
+            for column in min_column..=max_column {
+                for linedef_index in self.blockmap.lines_in_block(column, row) {
+                    if visited.insert(*linedef_index) {
+                        callback(*linedef_index as usize);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns every linedef crossing the segment from `start` to `end`,
+    /// nearest first.
+    pub fn query_ray(&self, start: Vec2, end: Vec2) -> Vec<BlockmapRayHit> {
+        let mut hits = Vec::new();
+        let mut visited = HashSet::new();
+
+        self.for_each_block_along_ray(start, end, |column, row| {
+            for linedef_index in self.blockmap.lines_in_block(column, row) {
+                if !visited.insert(*linedef_index) {
+                    continue;
+                }
+
+                let (line_start, line_end) = match self.lines.get(*linedef_index as usize) {
+                    Some(Some(line)) => *line,
+                    _ => continue,
+                };
+
+                if let Some(fraction) = segment_intersection(start, end, line_start, line_end) {
+                    hits.push(BlockmapRayHit {
+                        linedef_index: *linedef_index as usize,
+                        fraction,
+                        point: start + (end - start) * fraction,
+                    });
+                }
+            }
+        });
+
+        hits.sort_by(|a, b| a.fraction.total_cmp(&b.fraction));
+        hits
+    }
+
+    /// Steps through the grid cells the segment passes through, in order.
+    fn for_each_block_along_ray<F: FnMut(u16, u16)>(
+        &self,
+        start: Vec2,
+        end: Vec2,
+        mut callback: F,
+    ) {
+        if self.blockmap.num_columns == 0 || self.blockmap.num_rows == 0 {
+            return;
+        }
+
+        let block_size = BLOCKMAP_BLOCK_SIZE as f32;
+        let origin = Vec2::new(self.blockmap.x_origin as f32, self.blockmap.y_origin as f32);
+
+        // Work in block units, relative to the grid.
+        let from = (start - origin) / block_size;
+        let to = (end - origin) / block_size;
+        let delta = to - from;
+
+        let mut column = from.x.floor() as i64;
+        let mut row = from.y.floor() as i64;
+        let end_column = to.x.floor() as i64;
+        let end_row = to.y.floor() as i64;
+
+        let step_column: i64 = if delta.x > 0. { 1 } else { -1 };
+        let step_row: i64 = if delta.y > 0. { 1 } else { -1 };
+
+        // Distance along the ray (in 0..1) to the next column/row boundary, and
+        // between boundaries.
+        let next_boundary = |pos: f32, step: i64| match step > 0 {
+            true => pos.floor() + 1. - pos,
+            false => pos - pos.floor(),
+        };
+        let mut t_max_x = if delta.x == 0. {
+            f32::INFINITY
+        } else {
+            next_boundary(from.x, step_column) / delta.x.abs()
+        };
+        let mut t_max_y = if delta.y == 0. {
+            f32::INFINITY
+        } else {
+            next_boundary(from.y, step_row) / delta.y.abs()
+        };
+        let t_delta_x = 1. / delta.x.abs();
+        let t_delta_y = 1. / delta.y.abs();
+
+        let num_columns = self.blockmap.num_columns as i64;
+        let num_rows = self.blockmap.num_rows as i64;
+
+        // The number of cells crossed is bounded, which also guards against NaNs.
+        let max_steps = (end_column - column).abs() + (end_row - row).abs() + 1;
+        for _ in 0..max_steps {
+            if (0..num_columns).contains(&column) && (0..num_rows).contains(&row) {
+                callback(column as u16, row as u16);
+            }
+
+            if column == end_column && row == end_row {
+                break;
+            }
+
+            if t_max_x < t_max_y {
+                t_max_x += t_delta_x;
+                column += step_column;
+            } else {
+                t_max_y += t_delta_y;
+                row += step_row;
+            }
+        }
+    }
+
+    fn clamped_block(&self, point: Vec2) -> (u16, u16) {
+        let block_size = BLOCKMAP_BLOCK_SIZE as f32;
+        let column = ((point.x - self.blockmap.x_origin as f32) / block_size).floor();
+        let row = ((point.y - self.blockmap.y_origin as f32) / block_size).floor();
+
+        (
+            column.clamp(0., self.blockmap.num_columns.saturating_sub(1) as f32) as u16,
+            row.clamp(0., self.blockmap.num_rows.saturating_sub(1) as f32) as u16,
+        )
+    }
+}
+
+/// Returns how far along `a` it intersects `b`, if the segments cross.
+fn segment_intersection(a_start: Vec2, a_end: Vec2, b_start: Vec2, b_end: Vec2) -> Option<f32> {
+    let a = a_end - a_start;
+    let b = b_end - b_start;
+
+    let denom = a.x * b.y - a.y * b.x;
+    if denom == 0. {
+        // Parallel (or degenerate) segments never count as crossing.
+        return None;
+    }
+
+    let offset = b_start - a_start;
+    let t = (offset.x * b.y - offset.y * b.x) / denom;
+    let u = (offset.x * a.y - offset.y * a.x) / denom;
+
+    if (0. ..=1.).contains(&t) && (0. ..=1.).contains(&u) {
+        Some(t)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use id_map_format::{Linedef, NodeFormat, Reject, Vertex};
+
+    use super::*;
+
+    /// Three vertical walls at x = 100, 300 and 500, each 64 units tall.
+    fn walls() -> Map {
+        let mut vertices = Vec::new();
+        let mut linedefs = Vec::new();
+        for (i, x) in [100, 300, 500].into_iter().enumerate() {
+            vertices.push(Vertex { x, y: 0 });
+            vertices.push(Vertex { x, y: 64 });
+            linedefs.push(Linedef {
+                start_vertex_idx: i as u16 * 2,
+                end_vertex_idx: i as u16 * 2 + 1,
+                flags: 0,
+                line_type: 0,
+                sector_tag: 0,
+                right_sidedef_idx: None,
+                left_sidedef_idx: None,
+            });
+        }
+
+        Map {
+            name: "MAP01".to_string(),
+            things: vec![],
+            sectors: vec![],
+            sidedefs: vec![],
+            linedefs,
+            vertices,
+            segs: None,
+            subsectors: None,
+            nodes: None,
+            node_vertices: vec![],
+            node_format: NodeFormat::Vanilla,
+            blockmap: None,
+            reject: Reject::all_visible(0),
+            udmf: None,
+        }
+    }
+
+    #[test]
+    fn ray_hits_are_sorted() {
+        let accel = BlockmapAccel::new(&walls());
+
+        // Cast right-to-left, so the hits come out in reverse linedef order.
+        let hits = accel.query_ray(Vec2::new(600., 32.), Vec2::new(0., 32.));
+        let hit_lines: Vec<usize> = hits.iter().map(|hit| hit.linedef_index).collect();
+        assert_eq!(hit_lines, vec![2, 1, 0]);
+        assert!((hits[0].point.x - 500.).abs() < 0.001);
+
+        // Passing above the walls hits nothing.
+        assert!(accel
+            .query_ray(Vec2::new(0., 100.), Vec2::new(600., 100.))
+            .is_empty());
+
+        // A linedef with a missing vertex is skipped, even if BLOCKMAP lists it.
+        let mut map = walls();
+        map.blockmap = Some(Blockmap::generate(&map));
+        map.linedefs[1].end_vertex_idx = 99;
+        let accel = BlockmapAccel::new(&map);
+        let hits = accel.query_ray(Vec2::new(600., 32.), Vec2::new(0., 32.));
+        let hit_lines: Vec<usize> = hits.iter().map(|hit| hit.linedef_index).collect();
+        assert_eq!(hit_lines, vec![2, 0]);
+    }
+
+    #[test]
+    fn box_query() {
+        let accel = BlockmapAccel::new(&walls());
+
+        let mut lines = Vec::new();
+        accel.query_box(Vec2::new(250., 0.), Vec2::new(350., 10.), |idx| {
+            lines.push(idx)
+        });
+        assert_eq!(lines, vec![1]);
+    }
+}
@@ -11,7 +11,7 @@ pub enum CTexture {
     Flat(String),
     /// Used for walls.
     Texture(String),
-    // Used for things.
+    /// Used for things, by sprite lump name.
     Sprite(String),
 }
 
@@ -25,7 +25,8 @@ pub struct CTextureAnimated {}
 /// [CTextureOrdinal] is a hint to our ECS system that the texture
 /// should be changed based on player's ordinal direction.
 ///
-/// Normally, an entity would add this after consulting [AnimationStateMap].
+/// Normally, an entity would add this after consulting the sprite's
+/// [id_map_format::SpriteFrame::is_rotated].
 ///
 /// This only has an effect for sprites.
 #[derive(Debug)]
@@ -19,6 +19,21 @@ pub struct CThing {
 
     pub radius: u32,
     pub height: u32,
+
+    /// Where the thing is facing, in degrees counter-clockwise from East.
+    pub angle: u16,
+}
+
+/// [CThingSprite] is the sprite frame a thing shows.
+///
+/// With [super::CTextureOrdinal], [super::CTexture] holds the rotation of the
+/// frame that faces the player.
+#[derive(Debug)]
+pub struct CThingSprite {
+    pub sprite: String,
+    pub frame: char,
+    /// Whether the current rotation is drawn flipped horizontally.
+    pub mirrored: bool,
 }
 
 /// [CWorldPos] is an "entity" in the world.
@@ -1,16 +1,18 @@
 use std::collections::HashMap;
 
+use hecs::EntityBuilder;
 use id_game_config::GameConfig;
-use id_map_format::Map;
+use id_map_format::{Map, SpriteDefs};
 
 use crate::{
-    components::{CThing, CWorldPos},
+    components::{CTexture, CTextureOrdinal, CThing, CThingSprite, CWorldPos},
     SectorAccel,
 };
 
 pub fn init_thing_entities(
     world: &mut hecs::World,
     game_config: &GameConfig,
+    sprite_defs: &SpriteDefs,
     sector_accel: &SectorAccel,
     map: &Map,
 ) {
@@ -30,9 +32,39 @@ pub fn init_thing_entities(
 
                 radius: thing_config.radius,
                 height: thing_config.height,
+
+                angle: thing.angle,
             };
 
-            world.spawn((c_thing, CWorldPos::from_thing(thing, world, sector_accel)));
+            let mut builder = EntityBuilder::new();
+            builder.add(c_thing);
+            builder.add(CWorldPos::from_thing(thing, world, sector_accel));
+
+            // Start on the first frame of the thing's sequence, facing its front.
+            let frame_letter = thing_config
+                .sequence
+                .sequence
+                .first()
+                .and_then(|frame| frame.chars().next())
+                .unwrap_or('A');
+            let frame = sprite_defs
+                .get(&thing_config.sprite)
+                .and_then(|sprite_def| sprite_def.frames.get(&frame_letter));
+            if let Some(frame) = frame {
+                if let Some(rotation) = frame.rotations.iter().flatten().next() {
+                    builder.add(CTexture::Sprite(rotation.lump_name.clone()));
+                    builder.add(CThingSprite {
+                        sprite: thing_config.sprite.clone(),
+                        frame: frame_letter,
+                        mirrored: rotation.mirrored,
+                    });
+                    if frame.is_rotated() {
+                        builder.add(CTextureOrdinal {});
+                    }
+                }
+            }
+
+            world.spawn(builder.build());
         }
     }
 }
@@ -7,6 +7,7 @@ pub mod renderer;
 pub mod world;
 
 mod animation_state_map;
+mod blockmap_accel;
 mod sector_accel;
 
 pub mod components;
@@ -17,4 +18,5 @@ pub(crate) mod helpers;
 pub use helpers::Stopwatch;
 
 pub use animation_state_map::AnimationStateMap;
+pub use blockmap_accel::{BlockmapAccel, BlockmapRayHit};
 pub use sector_accel::SectorAccel;
@@ -6,10 +6,10 @@ use lazy_static::lazy_static;
 use regex::Regex;
 use wgpu::BufferUsages;
 
-use id_map_format::{LumpNamespace, Map, Patch};
+use id_map_format::{Flat, Map, Patch};
 
 use crate::{
-    components::{CTexture, CTextureFloor},
+    components::{CTexture, CTextureFloor, CThingSprite},
     renderer::helpers::gpu::GpuU8StorageBuffer,
     world::World,
 };
@@ -54,19 +54,14 @@ impl PaletteImageData {
 
             let mut palette_image = match c_texture {
                 CTexture::Flat(texture_name) => {
-                    world.with_lump(&LumpNamespace::Flat, texture_name, |lump| {
-                        // Fill the rest of the buffer with zeros, if it's not a multiple of 4096.
-                        let bytes = &mut lump.bytes().to_vec();
-                        if bytes.len() < 4096 {
-                            let remaining = 4096 - bytes.len();
-                            bytes.append(&mut vec![0u8; remaining]);
-                        }
-
-                        PaletteImage::from_flat(bytes)
-                    })?
+                    world.with_flat(texture_name, |flat| PaletteImage::from_flat(&flat))?
                 }
 
-                CTexture::Texture(texture_name) | CTexture::Sprite(texture_name) => {
+                CTexture::Sprite(lump_name) => {
+                    world.with_sprite(lump_name, |patch| PaletteImage::from_patch(&patch))?
+                }
+
+                CTexture::Texture(texture_name) => {
                     let texture = match textures.get(&texture_name.to_uppercase()) {
                         Some(texture) => texture,
                         None => return Err(anyhow::anyhow!("Texture not found: {}", texture_name)),
@@ -134,6 +129,36 @@ impl PaletteImageData {
         for c_texture in world.animations.keys() {
             parse_texture(c_texture)?;
         }
+        // Parse the other state of switches, so they can be flipped.
+        let switch_textures = world
+            .world
+            .query::<&CTexture>()
+            .iter()
+            .filter_map(|(_id, c_texture)| match c_texture {
+                CTexture::Texture(texture_name) => world
+                    .animations
+                    .switch_texture(&texture_name.to_uppercase()),
+                _ => None,
+            })
+            .filter(|texture_name| textures.contains_key(*texture_name))
+            .map(|texture_name| CTexture::Texture(texture_name.to_string()))
+            .collect::<Vec<_>>();
+        for c_texture in &switch_textures {
+            parse_texture(c_texture)?;
+        }
+        // Parse every rotation of rotated sprites.
+        for (_id, c_sprite) in &mut world.world.query::<&CThingSprite>() {
+            let frame = world
+                .sprites
+                .get(&c_sprite.sprite)
+                .and_then(|sprite_def| sprite_def.frames.get(&c_sprite.frame));
+            for rotation in frame
+                .iter()
+                .flat_map(|frame| frame.rotations.iter().flatten())
+            {
+                parse_texture(&CTexture::Sprite(rotation.lump_name.clone()))?;
+            }
+        }
 
         Ok(PaletteImageData {
             image_storage_buf: GpuU8StorageBuffer::new_vec(
@@ -216,12 +241,14 @@ impl PaletteImage {
         image
     }
 
-    pub fn from_flat(flat: &[u8]) -> PaletteImage {
+    /// The sector shader tiles flats every 64 units, so bigger flats (like
+    /// hi-res PNGs) are cropped and smaller ones repeated.
+    pub fn from_flat(flat: &Flat) -> PaletteImage {
         let mut image = PaletteImage::new(64, 64);
         // Maybe there's a faster way of doing this.
         for i in 0..64 {
             for j in 0..64 {
-                image.set(i, j, flat[i + j * 64]);
+                image.set(i, j, flat.pixel(i as u32, j as u32));
             }
         }
 
@@ -7,7 +7,7 @@ use ultraviolet::Vec2;
 use wgpu::BufferUsages;
 
 use crate::{
-    components::{CThing, CWorldPos},
+    components::{CThing, CThingSprite, CWorldPos},
     renderer::helpers::gpu::{GpuStorageBuffer, GpuVertexBuffer, LenOrData},
     world::World,
 };
@@ -27,8 +27,9 @@ pub struct ThingStorageData {
     pub radius: u32,
     pub height: u32,
 
-    // Currently unused.
     pub palette_image_index: u32,
+    /// Whether the sprite is drawn flipped horizontally.
+    pub mirrored: u32,
 }
 
 /// Things (monsters, entities, etc...) are rendered totally instanced:
@@ -82,7 +83,7 @@ impl ThingData {
         &mut self,
         queue: &wgpu::Queue,
         world: &World,
-        _palette_image_data: &PaletteImageData,
+        palette_image_data: &PaletteImageData,
     ) -> Result<()> {
         // First handle removed, so the allocator can free up space.
         for id in world.changed_set.removed() {
@@ -123,8 +124,10 @@ impl ThingData {
                 continue;
             }
 
-            let mut query = world.world.query_one::<(&CThing, &CWorldPos)>(*id)?;
-            let (c_thing, c_world_pos) = query.get().unwrap();
+            let mut query = world
+                .world
+                .query_one::<(&CThing, &CWorldPos, Option<&CThingSprite>)>(*id)?;
+            let (c_thing, c_world_pos, c_sprite) = query.get().unwrap();
 
             let thing = ThingStorageData {
                 thing_type: c_thing.thing_type as u32,
@@ -139,8 +142,8 @@ impl ThingData {
                 radius: c_thing.radius,
                 height: c_thing.height,
 
-                // TODO: Replace this with something.
-                palette_image_index: 0,
+                palette_image_index: palette_image_data.lookup_texture(world, *id)?,
+                mirrored: c_sprite.is_some_and(|c_sprite| c_sprite.mirrored) as u32,
             };
 
             let alloc = self
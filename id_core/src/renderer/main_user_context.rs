@@ -97,7 +97,7 @@ impl UserContext for MainUserContext {
         let world = self.world.clone();
 
         // Start by letting the world think.
-        world.borrow_mut().think()?;
+        world.borrow_mut().think(delta)?;
 
         // Update egui if necessary.
         self.egui_user_context.think(context, delta)?;
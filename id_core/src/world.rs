@@ -1,20 +1,27 @@
-use id_game_config::{Game, GameConfig};
-use id_map_format::{lump_from_namespace, Lump, LumpNamespace, Patch, Texture, Wad};
+use id_game_config::{Dehacked, Game, GameConfig};
+use id_map_format::{
+    lump_from_namespace, Endoom, Flat, Font, IndexedImage, Lump, LumpNamespace, MapInfo,
+    NodeBuilderOptions, NodeFormat, Palette, Patch, SpriteDefs, Texture, Wad,
+};
+
+use std::time::Duration;
 
 use anyhow::Result;
 use indexmap::IndexMap;
 use ultraviolet::Vec3;
 
 use crate::{
-    components::CWorldPos,
+    components::{CSector, CTexture, CTextureOrdinal, CThing, CThingSprite, CWorldPos},
     cvars::{CVarsMap, DEFAULT_CVARS},
     entities::{
         init_player_entities, init_sector_entities, init_thing_entities, init_wall_entities,
     },
     helpers::ChangedSet,
-    AnimationStateMap, SectorAccel, Stopwatch,
+    AnimationStateMap, BlockmapAccel, SectorAccel, Stopwatch,
 };
 
+const TIC_DURATION: Duration = Duration::from_nanos(1_000_000_000 / 35);
+
 pub struct World {
     iwad: Wad,
     pwad: Vec<Wad>,
@@ -23,9 +30,11 @@ pub struct World {
     pub game_config: GameConfig,
 
     pub map: id_map_format::Map,
+    pub map_info: MapInfo,
     pub palette: Vec<Vec3>,
     pub colormap: Vec<u8>,
     pub textures: IndexMap<String, Texture>,
+    pub sprites: SpriteDefs,
 
     /// Actual game state is maintained in the ECS "world".
     pub world: hecs::World,
@@ -39,26 +48,49 @@ pub struct World {
     pub changed_set: ChangedSet<hecs::Entity>,
 
     pub sector_accel: SectorAccel,
+    pub blockmap_accel: BlockmapAccel,
     pub animations: AnimationStateMap,
     pub cvars: CVarsMap,
 
-    frame_count_mod_20: u32,
+    /// Game tics since the map started, at 35 per second like vanilla.
+    tic: u32,
+    /// Time that hasn't added up to a whole tic yet.
+    tic_remainder: Duration,
 }
 
 impl World {
+    /// PK3s can be passed in `pwad` as well, see [Wad::from_pk3].
     pub fn new(iwad: Wad, pwad: Vec<Wad>, map_name: &str) -> Result<Self> {
         let game = Game::from_wad(&iwad).ok_or(anyhow::anyhow!(
             "Game detection failed: is this DOOM/DOOM2/Heretic?"
         ))?;
-        let game_config = GameConfig::from_game(game)?;
+        let mut game_config = GameConfig::from_game(game)?;
+
+        // Apply DEHACKED lumps in load order, IWAD first, so later PWADs win.
+        for patch in std::iter::once(&iwad)
+            .chain(&pwad)
+            .filter_map(Dehacked::from_wad)
+        {
+            game_config.apply_dehacked(&patch);
+        }
+        let map_info = Self::load_map_info(game, &iwad, &pwad)?;
 
         // If the map is in the PWAD, use that.
-        let map = pwad
+        let mut map = pwad
             .iter()
             .rev()
             .find_map(|pwad| pwad.parse_map(map_name).ok())
             .unwrap_or_else(|| iwad.parse_map(map_name).unwrap());
 
+        // Maps without nodes get built ones. Extended nodes leave the map's
+        // vertices alone.
+        if map.nodes.is_none() {
+            map.build_nodes(&NodeBuilderOptions {
+                format: NodeFormat::Extended { compressed: false },
+                ..Default::default()
+            });
+        }
+
         // If the palette is in the PWAD, use that.
         let palette = pwad
             .iter()
@@ -92,6 +124,12 @@ impl World {
             textures
         };
 
+        // PWAD sprites replace single frames or rotations of IWAD sprites.
+        let mut sprites = iwad.parse_sprite_defs();
+        for pwad in pwad.iter() {
+            pwad.extend_sprite_defs(&mut sprites);
+        }
+
         let mut world = hecs::World::new();
         let mut changed_set = ChangedSet::<hecs::Entity>::default();
 
@@ -107,10 +145,12 @@ impl World {
 
         // Build acceleration structure for sectors.
         let sector_accel = SectorAccel::new(&world);
+        // Build acceleration structure for linedefs.
+        let blockmap_accel = BlockmapAccel::new(&map);
 
         // Add things to the world.
         // Requires we've already initialized sector accel.
-        init_thing_entities(&mut world, &game_config, &sector_accel, &map);
+        init_thing_entities(&mut world, &game_config, &sprites, &sector_accel, &map);
         let player = init_player_entities(&mut world, &sector_accel, &map)?;
 
         let setup_time = stopwatch.lap();
@@ -131,28 +171,106 @@ impl World {
             game_config,
 
             map,
+            map_info,
             palette,
             colormap,
             textures,
+            sprites,
 
             world,
             player,
             changed_set,
 
             sector_accel,
+            blockmap_accel,
             animations,
             cvars: DEFAULT_CVARS.iter().copied().collect::<CVarsMap>(),
 
-            frame_count_mod_20: 0,
+            tic: 0,
+            tic_remainder: Duration::ZERO,
         })
     }
 
-    pub fn think(&mut self) -> Result<()> {
-        // Every 20 frames, animate the textures.
-        self.frame_count_mod_20 = (self.frame_count_mod_20 + 1) % 20;
-        if self.frame_count_mod_20 == 19 {
+    /// The built-in map metadata for the game, with the UMAPINFO or MAPINFO of
+    /// each WAD layered on top, in load order.
+    pub fn load_map_info(game: Game, iwad: &Wad, pwad: &[Wad]) -> Result<MapInfo> {
+        let mut map_info = match game {
+            // Chex Quest reuses DOOM's map slots, but not its names.
+            Game::Chex => MapInfo::default(),
+            _ => MapInfo::builtin(iwad),
+        };
+
+        for wad in std::iter::once(iwad).chain(pwad) {
+            if let Some(wad_map_info) = wad.parse_map_info()? {
+                map_info.merge(&wad_map_info);
+            }
+        }
+
+        Ok(map_info)
+    }
+
+    /// The map's name for display, like "MAP01: Entryway".
+    pub fn level_title(&self) -> String {
+        match self
+            .map_info
+            .level(&self.map.name)
+            .and_then(|level| level.level_name.as_ref())
+        {
+            Some(level_name) => format!("{}: {}", self.map.name, level_name),
+            None => self.map.name.clone(),
+        }
+    }
+
+    pub fn think(&mut self, delta: Duration) -> Result<()> {
+        self.tic_remainder += delta;
+        while self.tic_remainder >= TIC_DURATION {
+            self.tic_remainder -= TIC_DURATION;
+            self.tic = self.tic.wrapping_add(1);
+
+            // Each animated texture advances at the speed of its sequence.
             self.animations
-                .animate_world(&mut self.changed_set, &mut self.world);
+                .animate_world(self.tic, &mut self.changed_set, &mut self.world);
+        }
+
+        self.rotate_sprites()?;
+
+        Ok(())
+    }
+
+    /// Points each rotated sprite at the rotation that faces the player.
+    fn rotate_sprites(&mut self) -> Result<()> {
+        let viewer = self.world.get::<&CWorldPos>(self.player)?.pos;
+
+        for (id, (c_thing, c_world_pos, c_sprite, c_texture, _)) in self.world.query_mut::<(
+            &CThing,
+            &CWorldPos,
+            &mut CThingSprite,
+            &mut CTexture,
+            &CTextureOrdinal,
+        )>() {
+            let Some(frame) = self
+                .sprites
+                .get(&c_sprite.sprite)
+                .and_then(|sprite_def| sprite_def.frames.get(&c_sprite.frame))
+            else {
+                continue;
+            };
+
+            // World positions keep the map's Y in Z.
+            let viewer_to_thing = (c_world_pos.pos.z - viewer.z)
+                .atan2(c_world_pos.pos.x - viewer.x)
+                .to_degrees();
+            let Some(rotation) = frame.rotation_for_angle(viewer_to_thing, c_thing.angle as f32)
+            else {
+                continue;
+            };
+
+            let next = CTexture::Sprite(rotation.lump_name.clone());
+            if *c_texture != next || c_sprite.mirrored != rotation.mirrored {
+                *c_texture = next;
+                c_sprite.mirrored = rotation.mirrored;
+                self.changed_set.change(id);
+            }
         }
 
         Ok(())
@@ -172,6 +290,14 @@ impl World {
         Ok(callback(player_pos))
     }
 
+    /// Returns whether sector `a` may be able to see sector `b`, according to
+    /// the map's REJECT table.
+    ///
+    /// This is a conservative check: `true` doesn't mean there's line of sight.
+    pub fn can_sectors_see(&self, a: &CSector, b: &CSector) -> bool {
+        self.map.reject.can_see(a.sector_index, b.sector_index)
+    }
+
     pub fn with_lump<RT, F: FnOnce(&Lump) -> RT>(
         &self,
         namespace: &LumpNamespace,
@@ -192,22 +318,115 @@ impl World {
         Err(anyhow::anyhow!("Lump not found: {}", lump_name))
     }
 
+    /// The palette PNG graphics are quantized to.
+    fn playpal(&self) -> Palette {
+        std::array::from_fn(|i| {
+            let color = self.palette[i];
+            (color.x as u8, color.y as u8, color.z as u8)
+        })
+    }
+
+    pub fn with_sprite<RT, F: FnOnce(Patch) -> RT>(
+        &self,
+        lump_name: &str,
+        callback: F,
+    ) -> Result<RT> {
+        let playpal = self.playpal();
+
+        // PWAD sprites take precedence.
+        for pwad in self.pwad.iter().rev() {
+            if let Ok(patch) = pwad.parse_sprite_with_palette(lump_name, &playpal) {
+                return Ok(callback(patch));
+            }
+        }
+
+        Ok(callback(
+            self.iwad.parse_sprite_with_palette(lump_name, &playpal)?,
+        ))
+    }
+
     pub fn with_patch<RT, F: FnOnce(Patch) -> RT>(
         &self,
         patch_name: &str,
         callback: F,
     ) -> Result<RT> {
+        let playpal = self.playpal();
+
         // If the map is in the PWAD, use that.
         for pwad in self.pwad.iter().rev() {
-            if let Ok(patch) = pwad.parse_patch(patch_name) {
+            if let Ok(patch) = pwad.parse_patch_with_palette(patch_name, &playpal) {
                 return Ok(callback(patch));
             }
         }
 
-        if let Ok(patch) = self.iwad.parse_patch(patch_name) {
+        if let Ok(patch) = self.iwad.parse_patch_with_palette(patch_name, &playpal) {
             return Ok(callback(patch));
         }
 
         Err(anyhow::anyhow!("Patch not found: {}", patch_name))
     }
+
+    pub fn with_flat<RT, F: FnOnce(Flat) -> RT>(&self, flat_name: &str, callback: F) -> Result<RT> {
+        let playpal = self.playpal();
+
+        for pwad in self.pwad.iter().rev() {
+            if let Ok(flat) = pwad.parse_flat_with_palette(flat_name, &playpal) {
+                return Ok(callback(flat));
+            }
+        }
+
+        Ok(callback(
+            self.iwad.parse_flat_with_palette(flat_name, &playpal)?,
+        ))
+    }
+
+    /// Composites a texture on the CPU, with patches from any WAD, the same
+    /// way the renderer does.
+    pub fn texture_image(&self, texture_name: &str) -> Result<IndexedImage> {
+        let texture = self
+            .textures
+            .get(&texture_name.to_uppercase())
+            .ok_or_else(|| anyhow::anyhow!("Texture not found: {}", texture_name))?;
+        IndexedImage::from_texture(texture, |patch_name| {
+            self.with_patch(patch_name, |patch| patch)
+        })
+    }
+
+    /// Like [Self::with_patch], for full-screen graphics that may be raw
+    /// 320x200 screens.
+    pub fn with_graphic<RT, F: FnOnce(Patch) -> RT>(
+        &self,
+        lump_name: &str,
+        callback: F,
+    ) -> Result<RT> {
+        for pwad in self.pwad.iter().rev() {
+            if let Ok(graphic) = pwad.parse_graphic(lump_name) {
+                return Ok(callback(graphic.into_patch()));
+            }
+        }
+
+        Ok(callback(self.iwad.parse_graphic(lump_name)?.into_patch()))
+    }
+
+    /// The quit screen, from the last WAD that has one.
+    pub fn endoom(&self) -> Result<Endoom> {
+        for pwad in self.pwad.iter().rev() {
+            if let Ok(endoom) = pwad.parse_endoom() {
+                return Ok(endoom);
+            }
+        }
+
+        Ok(self.iwad.parse_endoom()?)
+    }
+
+    /// Loads a font, taking it from the last WAD that has one.
+    pub fn font(&self, prefix: &str) -> Result<Font> {
+        for pwad in self.pwad.iter().rev() {
+            if let Ok(font) = pwad.parse_font(prefix) {
+                return Ok(font);
+            }
+        }
+
+        Ok(self.iwad.parse_font(prefix)?)
+    }
 }
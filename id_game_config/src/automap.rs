@@ -0,0 +1,70 @@
+use std::collections::HashMap;
+
+use id_map_format::{AutomapThingInfo, AutomapThingKind};
+
+use crate::{GameConfig, ThingFlags};
+
+/// Thing types of the player starts, which aren't in the thing configs.
+pub(crate) const PLAYER_STARTS: [u16; 5] = [1, 2, 3, 4, 11];
+
+impl GameConfig {
+    /// How each thing type is drawn on an [id_map_format::Automap], from its
+    /// flags and radius, plus the player starts.
+    pub fn automap_things(&self) -> HashMap<u16, AutomapThingInfo> {
+        let mut thing_types: HashMap<u16, AutomapThingInfo> = self
+            .things
+            .iter()
+            .filter_map(|thing| {
+                let thing_type = u16::try_from(thing.thing_type).ok()?;
+                let info = AutomapThingInfo {
+                    kind: thing_kind(thing.flags),
+                    radius: thing.radius,
+                };
+                Some((thing_type, info))
+            })
+            .collect();
+
+        for thing_type in PLAYER_STARTS {
+            thing_types.insert(
+                thing_type,
+                AutomapThingInfo {
+                    kind: AutomapThingKind::Player,
+                    radius: 16,
+                },
+            );
+        }
+
+        thing_types
+    }
+}
+
+fn thing_kind(flags: ThingFlags) -> AutomapThingKind {
+    if flags.contains(ThingFlags::Monster) {
+        AutomapThingKind::Monster
+    } else if flags.contains(ThingFlags::Weapon) {
+        AutomapThingKind::Weapon
+    } else if flags.intersects(ThingFlags::Pickup | ThingFlags::Aritfact) {
+        AutomapThingKind::Pickup
+    } else if flags.contains(ThingFlags::Obstacle) {
+        AutomapThingKind::Obstacle
+    } else {
+        AutomapThingKind::Decoration
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Game;
+
+    #[test]
+    fn automap_things() {
+        let thing_types = GameConfig::from_game(Game::Doom).unwrap().automap_things();
+
+        let kind = |thing_type| thing_types.get(&thing_type).map(|info| info.kind);
+        assert_eq!(kind(1), Some(AutomapThingKind::Player));
+        assert_eq!(kind(3001), Some(AutomapThingKind::Monster));
+        assert_eq!(kind(2001), Some(AutomapThingKind::Weapon));
+        assert_eq!(kind(9999), None);
+    }
+}
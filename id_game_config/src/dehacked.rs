@@ -0,0 +1,566 @@
+use std::collections::BTreeMap;
+
+use id_map_format::{lump_from_namespace, LumpNamespace, Wad};
+
+use crate::{GameConfig, ThingConfig, ThingFlags, ThingSequence};
+
+/// The `Key = Value` pairs of a Thing, Frame, Weapon or Ammo block.
+///
+/// Keys are lowercased, values are kept as written.
+pub type DehackedProperties = BTreeMap<String, String>;
+
+/// A par time from a BEX `[PARS]` section.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DehackedPar {
+    /// `None` for DOOM II style `MAPxx` maps.
+    pub episode: Option<u32>,
+    pub map: u32,
+    pub seconds: u32,
+}
+
+/// A parsed DeHackEd or BEX patch, from a `.deh`/`.bex` file or a DEHACKED lump.
+///
+/// Parsing is lenient like the original tools: lines and blocks we don't
+/// understand (Sound, Sprite, Misc, Cheat, Pointer, ...) are skipped.
+///
+/// References:
+/// - https://doomwiki.org/wiki/DeHackEd
+/// - https://doomwiki.org/wiki/Boom_extensions_to_DeHackEd
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Dehacked {
+    pub doom_version: Option<u32>,
+    pub patch_format: Option<u32>,
+
+    /// Keyed by DeHackEd thing number, which starts at 1.
+    pub things: BTreeMap<usize, DehackedProperties>,
+    /// Keyed by state number, which starts at 0.
+    pub frames: BTreeMap<usize, DehackedProperties>,
+    pub weapons: BTreeMap<usize, DehackedProperties>,
+    pub ammo: BTreeMap<usize, DehackedProperties>,
+
+    /// Text replacements, as `(original, replacement)`.
+    pub texts: Vec<(String, String)>,
+    /// `[STRINGS]`, keyed by uppercased mnemonic.
+    pub strings: BTreeMap<String, String>,
+    /// `[CODEPTR]`, state number to action name.
+    pub code_pointers: BTreeMap<usize, String>,
+    /// `[PARS]`.
+    pub pars: Vec<DehackedPar>,
+}
+
+#[derive(Clone, Copy)]
+enum Section {
+    None,
+    Thing(usize),
+    Frame(usize),
+    Weapon(usize),
+    Ammo(usize),
+    Strings,
+    CodePointers,
+    Pars,
+    Ignored,
+}
+
+impl Dehacked {
+    /// Patches are usually plain ASCII, but not always valid UTF-8, so bytes
+    /// are read as Latin-1.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Self::parse(&bytes.iter().map(|&b| b as char).collect::<String>())
+    }
+
+    /// Reads the DEHACKED lump of a WAD, if it has one.
+    pub fn from_wad(wad: &Wad) -> Option<Self> {
+        let lump = lump_from_namespace(&LumpNamespace::Global, "DEHACKED", wad).ok()?;
+        Some(Self::from_bytes(lump.bytes()))
+    }
+
+    pub fn parse(text: &str) -> Self {
+        let text = text.replace("\r\n", "\n");
+        let mut patch = Self::default();
+        let mut section = Section::None;
+
+        let mut rest = text.as_str();
+        while let Some(raw_line) = next_line(&mut rest) {
+            let line = raw_line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            // BEX sections.
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                section = match name.trim().to_ascii_uppercase().as_str() {
+                    "STRINGS" => Section::Strings,
+                    "CODEPTR" => Section::CodePointers,
+                    "PARS" => Section::Pars,
+                    _ => Section::Ignored,
+                };
+                continue;
+            }
+
+            if let Section::Pars = section {
+                if let Some(par) = parse_par(line) {
+                    patch.pars.push(par);
+                    continue;
+                }
+            }
+
+            if let Some((key, value)) = line.split_once('=') {
+                let (key, value) = (key.trim(), value.trim());
+                match section {
+                    Section::None => match key.to_ascii_lowercase().as_str() {
+                        "doom version" => patch.doom_version = value.parse().ok(),
+                        "patch format" => patch.patch_format = value.parse().ok(),
+                        _ => {}
+                    },
+                    Section::Thing(n) => insert_property(&mut patch.things, n, key, value),
+                    Section::Frame(n) => insert_property(&mut patch.frames, n, key, value),
+                    Section::Weapon(n) => insert_property(&mut patch.weapons, n, key, value),
+                    Section::Ammo(n) => insert_property(&mut patch.ammo, n, key, value),
+                    Section::Strings => {
+                        // Long strings continue on the next line after a trailing backslash.
+                        let mut value = value.to_string();
+                        while value.ends_with('\\') {
+                            value.pop();
+                            match next_line(&mut rest) {
+                                Some(next) => value.push_str(next.trim()),
+                                None => break,
+                            }
+                        }
+                        patch
+                            .strings
+                            .insert(key.to_ascii_uppercase(), unescape(&value));
+                    }
+                    Section::CodePointers => {
+                        let mut words = key.split_whitespace();
+                        if words
+                            .next()
+                            .is_some_and(|w| w.eq_ignore_ascii_case("frame"))
+                        {
+                            if let Some(frame) = words.next().and_then(|n| n.parse().ok()) {
+                                patch.code_pointers.insert(frame, value.to_string());
+                            }
+                        }
+                    }
+                    Section::Pars | Section::Ignored => {}
+                }
+                continue;
+            }
+
+            // Block headers, like `Thing 12 (Imp)` or `Text 4 4`.
+            let mut words = line.split_whitespace();
+            let kind = words.next().unwrap_or_default().to_ascii_lowercase();
+            let number = words.next().and_then(|n| n.parse::<usize>().ok());
+            section = match (kind.as_str(), number) {
+                ("thing", Some(n)) => Section::Thing(n),
+                ("frame", Some(n)) => Section::Frame(n),
+                ("weapon", Some(n)) => Section::Weapon(n),
+                ("ammo", Some(n)) => Section::Ammo(n),
+                // `Patch File for DeHackEd v3.0`, followed by the version lines.
+                ("patch", _) => Section::None,
+                ("text", Some(original_len)) => {
+                    let replacement_len = words.next().and_then(|n| n.parse().ok()).unwrap_or(0);
+                    let original = take_chars(&mut rest, original_len);
+                    let replacement = take_chars(&mut rest, replacement_len);
+                    patch.texts.push((original, replacement));
+                    Section::None
+                }
+                _ => Section::Ignored,
+            };
+        }
+
+        patch
+    }
+}
+
+fn next_line<'a>(rest: &mut &'a str) -> Option<&'a str> {
+    if rest.is_empty() {
+        return None;
+    }
+
+    let (line, remaining) = rest.split_once('\n').unwrap_or((rest, ""));
+    *rest = remaining;
+    Some(line)
+}
+
+/// Text blocks are counted in characters, newlines included, and may span lines.
+fn take_chars(rest: &mut &str, count: usize) -> String {
+    let end = rest
+        .char_indices()
+        .nth(count)
+        .map_or(rest.len(), |(i, _)| i);
+    let (taken, remaining) = rest.split_at(end);
+    *rest = remaining;
+    taken.to_string()
+}
+
+fn insert_property(
+    blocks: &mut BTreeMap<usize, DehackedProperties>,
+    number: usize,
+    key: &str,
+    value: &str,
+) {
+    blocks
+        .entry(number)
+        .or_default()
+        .insert(key.to_ascii_lowercase(), value.to_string());
+}
+
+fn unescape(value: &str) -> String {
+    value.replace("\\n", "\n")
+}
+
+/// `par <episode> <map> <seconds>` or `par <map> <seconds>`.
+fn parse_par(line: &str) -> Option<DehackedPar> {
+    let mut words = line.split_whitespace();
+    if !words.next()?.eq_ignore_ascii_case("par") {
+        return None;
+    }
+
+    let numbers = words
+        .map(|w| w.parse::<u32>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    match numbers[..] {
+        [episode, map, seconds] => Some(DehackedPar {
+            episode: Some(episode),
+            map,
+            seconds,
+        }),
+        [map, seconds] => Some(DehackedPar {
+            episode: None,
+            map,
+            seconds,
+        }),
+        _ => None,
+    }
+}
+
+/// Doomednums of the vanilla things, in DeHackEd order (`Thing 1` is the
+/// player). Things that can't be placed in a map are `-1`.
+const VANILLA_DOOMEDNUMS: [i32; 137] = [
+    -1, 3004, 9, 64, -1, 66, -1, -1, 67, -1, 65, 3001, 3002, 58, 3005, 3003, -1, 69, 3006, 7, 68,
+    16, 71, 84, 72, 88, 89, 87, -1, -1, 2035, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, 14, -1, 2018,
+    2019, 2014, 2015, 5, 13, 6, 39, 38, 40, 2011, 2012, 2013, 2022, 2023, 2024, 2025, 2026, 2045,
+    83, 2007, 2048, 2010, 2046, 2047, 17, 2008, 2049, 8, 2006, 2002, 2005, 2003, 2004, 2001, 82,
+    85, 86, 2028, 30, 31, 32, 33, 37, 36, 41, 42, 43, 44, 45, 46, 55, 56, 57, 47, 48, 34, 35, 49,
+    50, 51, 52, 53, 59, 60, 61, 62, 63, 22, 15, 18, 21, 23, 20, 19, 10, 12, 28, 24, 27, 29, 25, 26,
+    54, 70, 73, 74, 75, 76, 77, 78, 79, 80, 81,
+];
+
+/// Vanilla sprite names, indexed by the "Sprite number" of a Frame block.
+const VANILLA_SPRITES: [&str; 138] = [
+    "TROO", "SHTG", "PUNG", "PISG", "PISF", "SHTF", "SHT2", "CHGG", "CHGF", "MISG", "MISF", "SAWG",
+    "PLSG", "PLSF", "BFGG", "BFGF", "BLUD", "PUFF", "BAL1", "BAL2", "PLSS", "PLSE", "MISL", "BFS1",
+    "BFE1", "BFE2", "TFOG", "IFOG", "PLAY", "POSS", "SPOS", "VILE", "FIRE", "FATB", "FBXP", "SKEL",
+    "MANF", "FATT", "CPOS", "SARG", "HEAD", "BAL7", "BOSS", "BOS2", "SKUL", "SPID", "BSPI", "APLS",
+    "APBX", "CYBR", "PAIN", "SSWV", "KEEN", "BBRN", "BOSF", "ARM1", "ARM2", "BAR1", "BEXP", "FCAN",
+    "BON1", "BON2", "BKEY", "RKEY", "YKEY", "BSKU", "RSKU", "YSKU", "STIM", "MEDI", "SOUL", "PINV",
+    "PSTR", "PINS", "MEGA", "SUIT", "PMAP", "PVIS", "CLIP", "AMMO", "ROCK", "BROK", "CELL", "CELP",
+    "SHEL", "SBOX", "BPAK", "BFUG", "MGUN", "CSAW", "LAUN", "PLAS", "SHOT", "SGN2", "COLU", "SMT2",
+    "GOR1", "POL2", "POL5", "POL4", "POL3", "POL1", "POL6", "GOR2", "GOR3", "GOR4", "GOR5", "SMIT",
+    "COL1", "COL2", "COL3", "COL4", "CAND", "CBRA", "COL6", "TRE1", "TRE2", "ELEC", "CEYE", "FSKU",
+    "COL5", "TBLU", "TGRN", "TRED", "SMBT", "SMGT", "SMRT", "HDB1", "HDB2", "HDB3", "HDB4", "HDB5",
+    "HDB6", "POB1", "POB2", "BRS1", "TLMP", "TLP2",
+];
+
+mod mobj_flags {
+    pub(crate) const SPECIAL: u32 = 0x1;
+    pub(crate) const SOLID: u32 = 0x2;
+    pub(crate) const SHOOTABLE: u32 = 0x4;
+    pub(crate) const SPAWNCEILING: u32 = 0x100;
+    pub(crate) const FLOAT: u32 = 0x4000;
+    pub(crate) const COUNTKILL: u32 = 0x400000;
+    pub(crate) const COUNTITEM: u32 = 0x800000;
+
+    /// BEX lets "Bits" be written as mnemonics, like `SOLID+SHOOTABLE`.
+    pub(crate) const MNEMONICS: [(&str, u32); 29] = [
+        ("SPECIAL", 0x1),
+        ("SOLID", 0x2),
+        ("SHOOTABLE", 0x4),
+        ("NOSECTOR", 0x8),
+        ("NOBLOCKMAP", 0x10),
+        ("AMBUSH", 0x20),
+        ("JUSTHIT", 0x40),
+        ("JUSTATTACKED", 0x80),
+        ("SPAWNCEILING", 0x100),
+        ("NOGRAVITY", 0x200),
+        ("DROPOFF", 0x400),
+        ("PICKUP", 0x800),
+        ("NOCLIP", 0x1000),
+        ("SLIDE", 0x2000),
+        ("FLOAT", 0x4000),
+        ("TELEPORT", 0x8000),
+        ("MISSILE", 0x10000),
+        ("DROPPED", 0x20000),
+        ("SHADOW", 0x40000),
+        ("NOBLOOD", 0x80000),
+        ("CORPSE", 0x100000),
+        ("INFLOAT", 0x200000),
+        ("COUNTKILL", 0x400000),
+        ("COUNTITEM", 0x800000),
+        ("SKULLFLY", 0x1000000),
+        ("NOTDMATCH", 0x2000000),
+        ("TRANSLATION1", 0x4000000),
+        ("TRANSLATION2", 0x8000000),
+        ("TRANSLUCENT", 0x80000000),
+    ];
+}
+
+fn parse_bits(value: &str) -> Option<u32> {
+    if let Ok(bits) = value.parse::<i64>() {
+        return Some(bits as u32);
+    }
+
+    value
+        .split(|c: char| c == '+' || c == '|' || c == ',' || c.is_whitespace())
+        .filter(|word| !word.is_empty())
+        .try_fold(0, |bits, word| {
+            let word = word.to_ascii_uppercase();
+            let (_, bit) = mobj_flags::MNEMONICS
+                .iter()
+                .find(|(name, _)| *name == word)?;
+            Some(bits | bit)
+        })
+}
+
+/// Returns the mobj flags as [ThingFlags]. `Weapon` isn't a mobj flag, so it's
+/// carried over from `previous`.
+fn thing_flags_from_bits(bits: u32, previous: ThingFlags) -> ThingFlags {
+    use mobj_flags::*;
+
+    let mut flags = previous & ThingFlags::Weapon;
+    let has = |flag: u32| bits & flag != 0;
+    flags.set(ThingFlags::Pickup, has(SPECIAL));
+    flags.set(ThingFlags::Obstacle, has(SOLID));
+    flags.set(ThingFlags::Shootable, has(SHOOTABLE));
+    flags.set(ThingFlags::Monster, has(COUNTKILL));
+    flags.set(ThingFlags::Aritfact, has(COUNTITEM));
+    flags.set(
+        ThingFlags::UpperPegged,
+        has(SPAWNCEILING) || (has(FLOAT) && has(COUNTKILL)),
+    );
+    flags
+}
+
+fn parse_number(properties: &DehackedProperties, key: &str) -> Option<i64> {
+    properties.get(key)?.parse().ok()
+}
+
+impl GameConfig {
+    /// Applies a DeHackEd patch onto the thing configs: doomednums, radius,
+    /// height, flags and sprites.
+    ///
+    /// We don't have the vanilla state table, so a thing's sprite only changes
+    /// when its "Initial frame" points at a Frame block of the same patch that
+    /// sets a "Sprite number", or when a Text block renames its sprite.
+    pub fn apply_dehacked(&mut self, patch: &Dehacked) {
+        // Sprite renames, like `Text 4 4` `TROOBOSS`.
+        let mut sprite_names = VANILLA_SPRITES.map(|name| name.to_string());
+        for (original, replacement) in &patch.texts {
+            if original.len() != 4 || replacement.len() != 4 {
+                continue;
+            }
+            if let Some(name) = sprite_names.iter_mut().find(|name| *name == original) {
+                *name = replacement.clone();
+                for thing in self.things.iter_mut().filter(|t| t.sprite == *original) {
+                    thing.sprite = replacement.clone();
+                }
+            }
+        }
+
+        // Resolve every thing before renumbering, so swapped IDs don't collide.
+        let mut updates = Vec::new();
+        for (&number, properties) in &patch.things {
+            let Some(&doomednum) = number
+                .checked_sub(1)
+                .and_then(|i| VANILLA_DOOMEDNUMS.get(i))
+            else {
+                continue;
+            };
+
+            let new_doomednum = parse_number(properties, "id #").unwrap_or(doomednum as i64);
+            let idx = self
+                .things
+                .iter()
+                .position(|t| doomednum >= 0 && t.thing_type == doomednum as u32);
+            updates.push((idx, new_doomednum, properties));
+        }
+
+        for (idx, new_doomednum, properties) in updates {
+            let Ok(new_doomednum) = u32::try_from(new_doomednum) else {
+                // The thing can no longer be placed in a map.
+                if let Some(idx) = idx {
+                    self.things[idx].thing_type = u32::MAX;
+                }
+                continue;
+            };
+
+            let thing = match idx {
+                Some(idx) => &mut self.things[idx],
+                None => {
+                    // A non-placeable thing was given a doomednum.
+                    self.things.push(ThingConfig {
+                        thing_type: new_doomednum,
+                        flags: ThingFlags::empty(),
+                        radius: 20,
+                        height: 16,
+                        sprite: String::new(),
+                        sequence: ThingSequence {
+                            sequence: vec!["A".to_string()],
+                            has_gameplay_frame: false,
+                        },
+                        description: "DeHackEd thing".to_string(),
+                    });
+                    self.things.last_mut().unwrap()
+                }
+            };
+            thing.thing_type = new_doomednum;
+
+            // Width and Height are 16.16 fixed point.
+            if let Some(width) = parse_number(properties, "width") {
+                thing.radius = (width >> 16).max(0) as u32;
+            }
+            if let Some(height) = parse_number(properties, "height") {
+                thing.height = (height >> 16).max(0) as u32;
+            }
+            if let Some(bits) = properties.get("bits").and_then(|b| parse_bits(b)) {
+                thing.flags = thing_flags_from_bits(bits, thing.flags);
+            }
+
+            let frame = parse_number(properties, "initial frame")
+                .and_then(|frame| patch.frames.get(&(frame as usize)));
+            let sprite = frame.and_then(|frame| {
+                let sprite = parse_number(frame, "sprite number")?;
+                sprite_names.get(usize::try_from(sprite).ok()?)
+            });
+            if let (Some(frame), Some(sprite)) = (frame, sprite) {
+                // The high bit of the subnumber marks the frame as fullbright.
+                let letter = parse_number(frame, "sprite subnumber").unwrap_or(0) & 0x7FFF;
+                thing.sprite = sprite.clone();
+                thing.sequence = ThingSequence {
+                    sequence: vec![char::from(b'A' + letter.min(28) as u8).to_string()],
+                    has_gameplay_frame: false,
+                };
+            }
+        }
+
+        self.things.retain(|t| t.thing_type != u32::MAX);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Game;
+
+    const PATCH: &str = "Patch File for DeHackEd v3.0\r
+# A comment\r
+Doom version = 21\r
+Patch format = 6\r
+\r
+Thing 12 (Imp)\r
+ID # = 3001\r
+Width = 1310720\r
+Height = 4194304\r
+Bits = SOLID+SHOOTABLE+COUNTKILL\r
+Initial frame = 100\r
+\r
+Thing 31 (Barrel)\r
+ID # = 5555\r
+Bits = 0\r
+\r
+Frame 100\r
+Sprite number = 40\r
+Sprite subnumber = 32769\r
+\r
+Text 4 4\r
+BAR1BARL\r
+Text 9 6\r
+two\r
+lines.hello\r
+[STRINGS]\r
+GOTARMOR = You got \\\r
+  the armor!\r
+[CODEPTR]\r
+FRAME 100 = Look\r
+[PARS]\r
+par 1 2 90\r
+par 7 120\r
+";
+
+    #[test]
+    fn parse_patch() {
+        let patch = Dehacked::parse(PATCH);
+        assert_eq!(patch.doom_version, Some(21));
+        assert_eq!(patch.patch_format, Some(6));
+
+        assert_eq!(patch.things.len(), 2);
+        assert_eq!(patch.things[&12]["id #"], "3001");
+        assert_eq!(patch.things[&12]["bits"], "SOLID+SHOOTABLE+COUNTKILL");
+        assert_eq!(patch.frames[&100]["sprite number"], "40");
+
+        assert_eq!(
+            patch.texts,
+            vec![
+                ("BAR1".to_string(), "BARL".to_string()),
+                ("two\nlines".to_string(), ".hello".to_string()),
+            ]
+        );
+        assert_eq!(patch.strings["GOTARMOR"], "You got the armor!");
+        assert_eq!(patch.code_pointers[&100], "Look");
+        assert_eq!(
+            patch.pars,
+            vec![
+                DehackedPar {
+                    episode: Some(1),
+                    map: 2,
+                    seconds: 90
+                },
+                DehackedPar {
+                    episode: None,
+                    map: 7,
+                    seconds: 120
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn vanilla_tables_match_config() {
+        let config = GameConfig::from_game(Game::Doom).unwrap();
+        for thing in &config.things {
+            // Player starts and deathmatch starts aren't mobjs.
+            if matches!(thing.thing_type, 1..=4 | 11) {
+                continue;
+            }
+            assert!(
+                VANILLA_DOOMEDNUMS.contains(&(thing.thing_type as i32)),
+                "{} isn't a vanilla thing",
+                thing.thing_type
+            );
+            assert!(
+                VANILLA_SPRITES.contains(&thing.sprite.as_str()) || thing.sprite.is_empty(),
+                "{} isn't a vanilla sprite",
+                thing.sprite
+            );
+        }
+    }
+
+    #[test]
+    fn apply_patch() {
+        let mut config = GameConfig::from_game(Game::Doom).unwrap();
+        config.apply_dehacked(&Dehacked::parse(PATCH));
+
+        let imp = config.things.iter().find(|t| t.thing_type == 3001).unwrap();
+        assert_eq!((imp.radius, imp.height), (20, 64));
+        assert_eq!(imp.sprite, "HEAD");
+        assert_eq!(imp.sequence.sequence, vec!["B".to_string()]);
+        assert!(imp
+            .flags
+            .contains(ThingFlags::Monster | ThingFlags::Obstacle));
+
+        assert!(config.things.iter().all(|t| t.thing_type != 2035));
+        let barrel = config.things.iter().find(|t| t.thing_type == 5555).unwrap();
+        assert_eq!(barrel.sprite, "BARL");
+        assert!(barrel.flags.is_empty());
+    }
+}
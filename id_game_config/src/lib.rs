@@ -1,5 +1,11 @@
 use id_map_format::Wad;
 
+mod automap;
+mod dehacked;
+pub use dehacked::{Dehacked, DehackedPar, DehackedProperties};
+mod lint;
+pub use lint::{Diagnostic, DiagnosticKind, DiagnosticLocation, Linter, Severity};
+
 use bitflags::bitflags;
 use serde::Deserialize;
 use sha2::Digest;
@@ -57,6 +63,9 @@ impl Game {
 pub struct GameConfig {
     pub walls: Vec<(String, String)>,
     pub flats: Vec<(String, String)>,
+    /// Switch textures, as `(off, on)`.
+    #[serde(default)]
+    pub switches: Vec<(String, String)>,
     pub things: Vec<ThingConfig>,
 }
 
@@ -0,0 +1,572 @@
+use std::{collections::HashSet, fmt};
+
+use id_map_format::{LumpNamespace, Map, MergedWads, ResourceKey, Wad};
+
+use crate::{automap::PLAYER_STARTS, GameConfig};
+
+/// Things that aren't in the thing configs because they have no sprite:
+/// teleport destinations, and the boss brain's spawn spots and shooter.
+const EDITOR_THINGS: [u32; 4] = [14, 87, 88, 89];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// Odd, but the game copes.
+    Warning,
+    /// The game will refuse to load the map, crash, or draw garbage.
+    Error,
+}
+
+/// Where a problem is.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum DiagnosticLocation {
+    Lump(String),
+    Texture(String),
+    Map(String),
+    Thing { map: String, index: usize },
+    Linedef { map: String, index: usize },
+    Sidedef { map: String, index: usize },
+    Sector { map: String, index: usize },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum DiagnosticKind {
+    /// A lump couldn't be parsed at all.
+    Unreadable(String),
+    MissingTexture(String),
+    MissingFlat(String),
+    MissingPatch(String),
+    InvalidSector(u16),
+    InvalidSidedef(u16),
+    InvalidVertex(u16),
+    /// Every linedef needs a front side.
+    MissingFrontSidedef,
+    /// The sector's lines don't form closed loops. The vertex is where one
+    /// of them ends.
+    UnclosedSector {
+        vertex: u16,
+    },
+    ZeroLengthLine,
+    /// The player start for player 1 to 4.
+    MissingPlayerStart(u8),
+    UnknownThingType(u16),
+}
+
+/// A problem found by [Linter].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub location: DiagnosticLocation,
+    pub kind: DiagnosticKind,
+}
+
+impl fmt::Display for DiagnosticLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiagnosticLocation::Lump(name) => write!(f, "lump {name}"),
+            DiagnosticLocation::Texture(name) => write!(f, "texture {name}"),
+            DiagnosticLocation::Map(map) => write!(f, "{map}"),
+            DiagnosticLocation::Thing { map, index } => write!(f, "{map} thing {index}"),
+            DiagnosticLocation::Linedef { map, index } => write!(f, "{map} linedef {index}"),
+            DiagnosticLocation::Sidedef { map, index } => write!(f, "{map} sidedef {index}"),
+            DiagnosticLocation::Sector { map, index } => write!(f, "{map} sector {index}"),
+        }
+    }
+}
+
+impl fmt::Display for DiagnosticKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiagnosticKind::Unreadable(error) => write!(f, "unreadable: {error}"),
+            DiagnosticKind::MissingTexture(name) => write!(f, "missing texture {name}"),
+            DiagnosticKind::MissingFlat(name) => write!(f, "missing flat {name}"),
+            DiagnosticKind::MissingPatch(name) => write!(f, "missing patch {name}"),
+            DiagnosticKind::InvalidSector(index) => write!(f, "invalid sector {index}"),
+            DiagnosticKind::InvalidSidedef(index) => write!(f, "invalid sidedef {index}"),
+            DiagnosticKind::InvalidVertex(index) => write!(f, "invalid vertex {index}"),
+            DiagnosticKind::MissingFrontSidedef => write!(f, "no front sidedef"),
+            DiagnosticKind::UnclosedSector { vertex } => {
+                write!(f, "not closed, open at vertex {vertex}")
+            }
+            DiagnosticKind::ZeroLengthLine => write!(f, "zero length"),
+            DiagnosticKind::MissingPlayerStart(player) => {
+                write!(f, "no start for player {player}")
+            }
+            DiagnosticKind::UnknownThingType(thing_type) => {
+                write!(f, "unknown thing type {thing_type}")
+            }
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{severity}: {}: {}", self.location, self.kind)
+    }
+}
+
+/// Checks maps and textures for broken references and geometry, against the
+/// resources of an IWAD and its PWADs loaded together.
+pub struct Linter<'a> {
+    wads: Vec<&'a Wad>,
+    textures: HashSet<String>,
+    flats: HashSet<String>,
+    /// [None] if the config doesn't list things, so any type is allowed.
+    thing_types: Option<HashSet<u32>>,
+}
+
+impl<'a> Linter<'a> {
+    /// `wads` is the IWAD followed by the PWADs, in load order. Without a
+    /// `config`, thing types aren't checked.
+    pub fn new(wads: &[&'a Wad], config: Option<&GameConfig>) -> Self {
+        let mut textures = HashSet::new();
+        for wad in wads {
+            if let Ok(parsed) = wad
+                .parse_patch_names()
+                .and_then(|patch_names| wad.parse_textures(&patch_names))
+            {
+                textures.extend(parsed.keys().map(|name| name.to_uppercase()));
+            }
+        }
+
+        let flats = wads
+            .iter()
+            .flat_map(|wad| &wad.lumps_in_order)
+            .filter(|lump| lump.namespace == LumpNamespace::Flat)
+            .map(|lump| lump.name.to_uppercase())
+            .collect();
+
+        let thing_types = config
+            .filter(|config| !config.things.is_empty())
+            .map(|config| {
+                config
+                    .things
+                    .iter()
+                    .map(|thing| thing.thing_type)
+                    .chain(PLAYER_STARTS.iter().map(|thing_type| *thing_type as u32))
+                    .chain(EDITOR_THINGS)
+                    .collect()
+            });
+
+        Self {
+            wads: wads.to_vec(),
+            textures,
+            flats,
+            thing_types,
+        }
+    }
+
+    /// Lints the textures, and every map in the version that would be loaded.
+    pub fn lint(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = self.lint_textures();
+
+        let merged = MergedWads::new(&self.wads);
+        for (key, resource) in &merged.resources {
+            let ResourceKey::Map(map_name) = key else {
+                continue;
+            };
+            match self.wads[resource.source.wad_index].parse_map(map_name) {
+                Ok(map) => diagnostics.extend(self.lint_map(&map)),
+                Err(error) => diagnostics.push(Diagnostic {
+                    severity: Severity::Error,
+                    location: DiagnosticLocation::Map(map_name.clone()),
+                    kind: DiagnosticKind::Unreadable(error.to_string()),
+                }),
+            }
+        }
+
+        diagnostics
+    }
+
+    /// Checks that every texture's patches exist in one of the WADs.
+    pub fn lint_textures(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+
+        for wad in &self.wads {
+            let has_textures = ["TEXTURE1", "TEXTURE2"]
+                .iter()
+                .any(|name| wad.lump_names_in_order.iter().any(|lump| lump == name));
+            if !has_textures {
+                continue;
+            }
+
+            let textures = match wad
+                .parse_patch_names()
+                .map_err(|error| ("PNAMES".to_string(), error))
+                .and_then(|patch_names| {
+                    wad.parse_textures(&patch_names).map_err(|error| {
+                        let lump_name = error.lump_name().unwrap_or("TEXTURE1").to_string();
+                        (lump_name, error)
+                    })
+                }) {
+                Ok(textures) => textures,
+                Err((lump_name, error)) => {
+                    diagnostics.push(Diagnostic {
+                        severity: Severity::Error,
+                        location: DiagnosticLocation::Lump(lump_name),
+                        kind: DiagnosticKind::Unreadable(error.to_string()),
+                    });
+                    continue;
+                }
+            };
+
+            for texture in textures.values() {
+                for entry in &texture.patch_entry {
+                    if !self.has_patch(&entry.patch_name) {
+                        diagnostics.push(Diagnostic {
+                            severity: Severity::Error,
+                            location: DiagnosticLocation::Texture(texture.name.clone()),
+                            kind: DiagnosticKind::MissingPatch(entry.patch_name.clone()),
+                        });
+                    }
+                }
+            }
+        }
+
+        diagnostics
+    }
+
+    /// Patches can be outside of `P_START`/`P_END`, like vanilla allows.
+    fn has_patch(&self, patch_name: &str) -> bool {
+        let patch_name = patch_name.to_uppercase();
+        self.wads.iter().any(|wad| {
+            [LumpNamespace::Patch, LumpNamespace::Global]
+                .iter()
+                .any(|namespace| {
+                    wad.lump_namespaces
+                        .get(namespace)
+                        .is_some_and(|lumps| lumps.contains_key(&patch_name))
+                })
+        })
+    }
+
+    pub fn lint_map(&self, map: &Map) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        let mut report = |severity, location, kind| {
+            diagnostics.push(Diagnostic {
+                severity,
+                location,
+                kind,
+            })
+        };
+        let map_name = || map.name.clone();
+
+        for (index, linedef) in map.linedefs.iter().enumerate() {
+            let location = || DiagnosticLocation::Linedef {
+                map: map_name(),
+                index,
+            };
+
+            let mut vertices = Vec::new();
+            for vertex_idx in [linedef.start_vertex_idx, linedef.end_vertex_idx] {
+                match map.vertices.get(vertex_idx as usize) {
+                    Some(vertex) => vertices.push(vertex),
+                    None => report(
+                        Severity::Error,
+                        location(),
+                        DiagnosticKind::InvalidVertex(vertex_idx),
+                    ),
+                }
+            }
+            if let [start, end] = vertices[..] {
+                if start == end {
+                    report(
+                        Severity::Warning,
+                        location(),
+                        DiagnosticKind::ZeroLengthLine,
+                    );
+                }
+            }
+
+            if linedef.right_sidedef_idx.is_none() {
+                report(
+                    Severity::Error,
+                    location(),
+                    DiagnosticKind::MissingFrontSidedef,
+                );
+            }
+            for sidedef_idx in [linedef.right_sidedef_idx, linedef.left_sidedef_idx]
+                .into_iter()
+                .flatten()
+            {
+                if sidedef_idx as usize >= map.sidedefs.len() {
+                    report(
+                        Severity::Error,
+                        location(),
+                        DiagnosticKind::InvalidSidedef(sidedef_idx),
+                    );
+                }
+            }
+        }
+
+        for (index, sidedef) in map.sidedefs.iter().enumerate() {
+            let location = || DiagnosticLocation::Sidedef {
+                map: map_name(),
+                index,
+            };
+
+            if sidedef.sector_idx as usize >= map.sectors.len() {
+                report(
+                    Severity::Error,
+                    location(),
+                    DiagnosticKind::InvalidSector(sidedef.sector_idx),
+                );
+            }
+            for texture in [
+                &sidedef.upper_texture,
+                &sidedef.middle_texture,
+                &sidedef.lower_texture,
+            ] {
+                if texture != "-" && !self.textures.contains(&texture.to_uppercase()) {
+                    report(
+                        Severity::Error,
+                        location(),
+                        DiagnosticKind::MissingTexture(texture.clone()),
+                    );
+                }
+            }
+        }
+
+        for (index, sector) in map.sectors.iter().enumerate() {
+            for flat in [&sector.floor_flat, &sector.ceiling_flat] {
+                if !self.flats.contains(&flat.to_uppercase()) {
+                    report(
+                        Severity::Error,
+                        DiagnosticLocation::Sector {
+                            map: map_name(),
+                            index,
+                        },
+                        DiagnosticKind::MissingFlat(flat.clone()),
+                    );
+                }
+            }
+        }
+        for (index, vertex) in unclosed_sectors(map) {
+            report(
+                Severity::Warning,
+                DiagnosticLocation::Sector {
+                    map: map_name(),
+                    index,
+                },
+                DiagnosticKind::UnclosedSector { vertex },
+            );
+        }
+
+        for player in 1..=4u8 {
+            if !map
+                .things
+                .iter()
+                .any(|thing| thing.thing_type == player as u16)
+            {
+                // Only player 1 is needed to start a single player game.
+                let severity = match player {
+                    1 => Severity::Error,
+                    _ => Severity::Warning,
+                };
+                report(
+                    severity,
+                    DiagnosticLocation::Map(map_name()),
+                    DiagnosticKind::MissingPlayerStart(player),
+                );
+            }
+        }
+        if let Some(thing_types) = &self.thing_types {
+            for (index, thing) in map.things.iter().enumerate() {
+                if !thing_types.contains(&(thing.thing_type as u32)) {
+                    report(
+                        Severity::Warning,
+                        DiagnosticLocation::Thing {
+                            map: map_name(),
+                            index,
+                        },
+                        DiagnosticKind::UnknownThingType(thing.thing_type),
+                    );
+                }
+            }
+        }
+
+        diagnostics
+    }
+}
+
+/// Sectors whose sides don't form closed loops, with a vertex where one is
+/// open.
+///
+/// In a closed loop every vertex is shared by an even number of the sector's
+/// sides. Lines with the sector on both sides count twice, so they're fine.
+fn unclosed_sectors(map: &Map) -> Vec<(usize, u16)> {
+    let mut degrees = vec![Vec::<(u16, u32)>::new(); map.sectors.len()];
+    for linedef in &map.linedefs {
+        for sidedef_idx in [linedef.right_sidedef_idx, linedef.left_sidedef_idx]
+            .into_iter()
+            .flatten()
+        {
+            let Some(sidedef) = map.sidedefs.get(sidedef_idx as usize) else {
+                continue;
+            };
+            let Some(sector) = degrees.get_mut(sidedef.sector_idx as usize) else {
+                continue;
+            };
+            for vertex in [linedef.start_vertex_idx, linedef.end_vertex_idx] {
+                match sector.iter_mut().find(|(v, _)| *v == vertex) {
+                    Some((_, degree)) => *degree += 1,
+                    None => sector.push((vertex, 1)),
+                }
+            }
+        }
+    }
+
+    degrees
+        .iter()
+        .enumerate()
+        .filter_map(|(sector_idx, vertices)| {
+            let (vertex, _) = vertices.iter().find(|(_, degree)| degree % 2 == 1)?;
+            Some((sector_idx, *vertex))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Game;
+    use id_map_format::{Linedef, NodeFormat, Reject, Sector, Sidedef, Thing, Vertex, WadBuilder};
+
+    /// A square room whose last wall is missing, with broken references.
+    fn broken_room() -> Map {
+        let vertices = [(0, 0), (64, 0), (64, 64), (0, 64)]
+            .into_iter()
+            .map(|(x, y)| Vertex { x, y })
+            .collect();
+        let line = |start, end, right| Linedef {
+            start_vertex_idx: start,
+            end_vertex_idx: end,
+            flags: 1,
+            line_type: 0,
+            sector_tag: 0,
+            right_sidedef_idx: right,
+            left_sidedef_idx: None,
+        };
+        let sidedef = |middle_texture: &str, sector_idx| Sidedef {
+            x_offset: 0,
+            y_offset: 0,
+            upper_texture: "-".to_string(),
+            lower_texture: "-".to_string(),
+            middle_texture: middle_texture.to_string(),
+            sector_idx,
+        };
+        let thing = |thing_type| Thing {
+            x: 32,
+            y: 32,
+            angle: 0,
+            thing_type,
+            spawn_flags: 7,
+        };
+
+        Map {
+            name: "MAP01".to_string(),
+            things: vec![thing(2), thing(3), thing(4), thing(3001), thing(12345)],
+            sectors: vec![Sector {
+                floor_height: 0,
+                ceiling_height: 128,
+                floor_flat: "FLOOR0_1".to_string(),
+                ceiling_flat: "NOFLAT".to_string(),
+                light_level: 160,
+                special_type: 0,
+                sector_tag: 0,
+            }],
+            sidedefs: vec![sidedef("WALL", 0), sidedef("NOWALL", 0), sidedef("WALL", 7)],
+            linedefs: vec![
+                line(0, 1, Some(0)),
+                line(1, 2, Some(1)),
+                line(2, 3, Some(9)),
+                line(3, 3, None),
+                line(3, 40, Some(0)),
+            ],
+            vertices,
+            segs: None,
+            subsectors: None,
+            nodes: None,
+            node_vertices: vec![],
+            node_format: NodeFormat::Vanilla,
+            blockmap: None,
+            reject: Reject::all_visible(1),
+            udmf: None,
+        }
+    }
+
+    #[test]
+    fn lint_map() {
+        // One texture made of a patch that doesn't exist.
+        let mut texture1 = vec![1, 0, 0, 0, 8, 0, 0, 0];
+        texture1.extend(b"WALL\0\0\0\0");
+        texture1.extend([0, 0, 0, 0, 64, 0, 128, 0, 0, 0, 0, 0, 1, 0]);
+        texture1.extend([0, 0, 0, 0, 0, 0, 1, 0, 0, 0]);
+        let mut pnames = vec![1, 0, 0, 0];
+        pnames.extend(b"NOPATCH\0");
+
+        let mut builder = WadBuilder::new(true);
+        builder
+            .add_lump(&LumpNamespace::Global, "PNAMES", pnames.clone())
+            .unwrap()
+            .add_lump(&LumpNamespace::Global, "TEXTURE1", texture1.clone())
+            .unwrap()
+            .add_lump(&LumpNamespace::Flat, "FLOOR0_1", vec![0; 4096])
+            .unwrap();
+        let wad = builder.build().unwrap();
+
+        let config = GameConfig::from_game(Game::Doom).unwrap();
+        let linter = Linter::new(&[&wad], Some(&config));
+
+        assert_eq!(
+            linter.lint_textures(),
+            vec![Diagnostic {
+                severity: Severity::Error,
+                location: DiagnosticLocation::Texture("WALL".to_string()),
+                kind: DiagnosticKind::MissingPatch("NOPATCH".to_string()),
+            }]
+        );
+
+        // A broken TEXTURE2 is reported against TEXTURE2.
+        let mut builder = WadBuilder::new(true);
+        builder
+            .add_lump(&LumpNamespace::Global, "PNAMES", pnames)
+            .unwrap()
+            .add_lump(&LumpNamespace::Global, "TEXTURE1", texture1)
+            .unwrap()
+            .add_lump(&LumpNamespace::Global, "TEXTURE2", vec![1, 0, 0, 0])
+            .unwrap();
+        let broken_wad = builder.build().unwrap();
+        assert_eq!(
+            Linter::new(&[&broken_wad], Some(&config)).lint_textures(),
+            vec![Diagnostic {
+                severity: Severity::Error,
+                location: DiagnosticLocation::Lump("TEXTURE2".to_string()),
+                kind: DiagnosticKind::Unreadable(
+                    "Lump TEXTURE2 is corrupted at byte 4.".to_string()
+                ),
+            }]
+        );
+
+        let diagnostics = linter.lint_map(&broken_room());
+        let messages: Vec<String> = diagnostics.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "error: MAP01 linedef 2: invalid sidedef 9",
+                "warning: MAP01 linedef 3: zero length",
+                "error: MAP01 linedef 3: no front sidedef",
+                "error: MAP01 linedef 4: invalid vertex 40",
+                "error: MAP01 sidedef 1: missing texture NOWALL",
+                "error: MAP01 sidedef 2: invalid sector 7",
+                "error: MAP01 sector 0: missing flat NOFLAT",
+                "warning: MAP01 sector 0: not closed, open at vertex 0",
+                "error: MAP01: no start for player 1",
+                "warning: MAP01 thing 4: unknown thing type 12345",
+            ]
+        );
+    }
+}
@@ -0,0 +1,669 @@
+use std::{collections::HashMap, fmt::Write};
+
+use crate::{Linedef, Map, RgbaImage, Thing};
+
+/// Linedef flags the automap cares about.
+///
+/// References:
+/// - https://doomwiki.org/wiki/Linedef#Linedef_flags
+const LINE_SECRET: u16 = 0x0020;
+const LINE_DONT_DRAW: u16 = 0x0080;
+
+/// The largest image drawn on either side, in pixels. Larger maps or scales
+/// are scaled down to fit.
+const MAX_SIZE: f32 = 8192.0;
+
+/// How a line is drawn, in the order they're drawn so the most important end
+/// up on top.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum AutomapLineKind {
+    /// Flagged to never be drawn. Only drawn with [AutomapOptions::show_hidden].
+    Hidden,
+    /// Two-sided, with the same floor and ceiling on both sides.
+    TwoSided,
+    /// Two-sided, with a different ceiling height on each side.
+    CeilingStep,
+    /// Two-sided, with a different floor height on each side.
+    FloorStep,
+    OneSided,
+    /// Has an action, like a door or a switch.
+    Special,
+    /// Flagged as secret, so vanilla draws it like a one-sided wall.
+    Secret,
+}
+
+impl AutomapLineKind {
+    pub fn color(&self) -> (u8, u8, u8) {
+        match self {
+            AutomapLineKind::Hidden => (0x40, 0x40, 0x40),
+            AutomapLineKind::TwoSided => (0x80, 0x80, 0x80),
+            AutomapLineKind::CeilingStep => (0xFC, 0xFC, 0x00),
+            AutomapLineKind::FloorStep => (0xBC, 0x78, 0x48),
+            AutomapLineKind::OneSided => (0xFC, 0x00, 0x00),
+            AutomapLineKind::Special => (0x00, 0xC0, 0xFF),
+            AutomapLineKind::Secret => (0xFF, 0x00, 0xFF),
+        }
+    }
+
+    fn class(&self) -> &'static str {
+        match self {
+            AutomapLineKind::Hidden => "hidden",
+            AutomapLineKind::TwoSided => "two-sided",
+            AutomapLineKind::CeilingStep => "ceiling-step",
+            AutomapLineKind::FloorStep => "floor-step",
+            AutomapLineKind::OneSided => "one-sided",
+            AutomapLineKind::Special => "special",
+            AutomapLineKind::Secret => "secret",
+        }
+    }
+}
+
+/// What a thing is, as decided by the game's thing tables.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AutomapThingKind {
+    Player,
+    Monster,
+    Weapon,
+    Pickup,
+    Obstacle,
+    Decoration,
+    /// Not in the thing types given to [Automap::new].
+    Unknown,
+}
+
+impl AutomapThingKind {
+    pub fn color(&self) -> (u8, u8, u8) {
+        match self {
+            AutomapThingKind::Player => (0x00, 0xFF, 0x00),
+            AutomapThingKind::Monster => (0xFF, 0x60, 0x60),
+            AutomapThingKind::Weapon => (0xFF, 0xA0, 0x00),
+            AutomapThingKind::Pickup => (0x40, 0x80, 0xFF),
+            AutomapThingKind::Obstacle => (0xA0, 0xA0, 0xA0),
+            AutomapThingKind::Decoration => (0x60, 0x60, 0x60),
+            AutomapThingKind::Unknown => (0xFF, 0xFF, 0xFF),
+        }
+    }
+
+    fn class(&self) -> &'static str {
+        match self {
+            AutomapThingKind::Player => "player",
+            AutomapThingKind::Monster => "monster",
+            AutomapThingKind::Weapon => "weapon",
+            AutomapThingKind::Pickup => "pickup",
+            AutomapThingKind::Obstacle => "obstacle",
+            AutomapThingKind::Decoration => "decoration",
+            AutomapThingKind::Unknown => "unknown",
+        }
+    }
+
+    /// Players and monsters show which way they face.
+    fn has_direction(&self) -> bool {
+        matches!(self, AutomapThingKind::Player | AutomapThingKind::Monster)
+    }
+}
+
+/// How to draw things of a type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AutomapThingInfo {
+    pub kind: AutomapThingKind,
+    /// In map units.
+    pub radius: u32,
+}
+
+/// A rectangle in map units.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AutomapBounds {
+    pub min_x: f32,
+    pub min_y: f32,
+    pub max_x: f32,
+    pub max_y: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AutomapOptions {
+    /// Pixels per map unit.
+    pub scale: f32,
+    /// The part of the map to draw. Defaults to all of its vertices.
+    pub bounds: Option<AutomapBounds>,
+    /// Empty space around the bounds, in pixels.
+    pub margin: f32,
+    pub show_things: bool,
+    pub show_hidden: bool,
+    pub background: (u8, u8, u8),
+}
+
+impl Default for AutomapOptions {
+    fn default() -> Self {
+        Self {
+            scale: 0.25,
+            bounds: None,
+            margin: 16.0,
+            show_things: true,
+            show_hidden: false,
+            background: (0, 0, 0),
+        }
+    }
+}
+
+/// A line, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AutomapLine {
+    pub start: (f32, f32),
+    pub end: (f32, f32),
+    pub kind: AutomapLineKind,
+}
+
+/// A thing marker, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AutomapThing {
+    pub center: (f32, f32),
+    pub radius: f32,
+    /// In radians, counter-clockwise from east, like [Thing::angle].
+    pub angle: f32,
+    pub kind: AutomapThingKind,
+}
+
+/// A top-down drawing of a map, like the in-game automap, that can be
+/// written out as SVG or PNG without a GPU.
+///
+/// Map coordinates have y pointing up, so they're flipped to image
+/// coordinates here.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Automap {
+    pub width: u32,
+    pub height: u32,
+    pub background: (u8, u8, u8),
+    /// Sorted by [AutomapLineKind], so they can be drawn in order.
+    pub lines: Vec<AutomapLine>,
+    pub things: Vec<AutomapThing>,
+}
+
+impl Automap {
+    /// Things are classified by type with `thing_types`. Types that aren't in
+    /// it are drawn as [AutomapThingKind::Unknown].
+    ///
+    /// The image is scaled down if it would be more than 8192 pixels on a
+    /// side.
+    pub fn new(
+        map: &Map,
+        thing_types: &HashMap<u16, AutomapThingInfo>,
+        options: &AutomapOptions,
+    ) -> Self {
+        let bounds = options.bounds.unwrap_or_else(|| vertex_bounds(map));
+        let (extent_x, extent_y) = (bounds.max_x - bounds.min_x, bounds.max_y - bounds.min_y);
+        let fit = (MAX_SIZE - 2.0 * options.margin).max(1.0) / extent_x.max(extent_y);
+        let scale = match options.scale.min(fit) {
+            scale if scale.is_finite() && scale > 0.0 => scale,
+            _ => 1.0,
+        };
+        let to_image = |x: f32, y: f32| {
+            (
+                (x - bounds.min_x) * scale + options.margin,
+                (bounds.max_y - y) * scale + options.margin,
+            )
+        };
+
+        let mut lines: Vec<AutomapLine> = map
+            .linedefs
+            .iter()
+            .filter_map(|linedef| {
+                let start = map.vertices.get(linedef.start_vertex_idx as usize)?;
+                let end = map.vertices.get(linedef.end_vertex_idx as usize)?;
+                Some(AutomapLine {
+                    start: to_image(start.x as f32, start.y as f32),
+                    end: to_image(end.x as f32, end.y as f32),
+                    kind: line_kind(map, linedef),
+                })
+            })
+            .filter(|line| options.show_hidden || line.kind != AutomapLineKind::Hidden)
+            .collect();
+        lines.sort_by_key(|line| line.kind);
+
+        let things = match options.show_things {
+            true => map
+                .things
+                .iter()
+                .map(|thing| {
+                    let info = thing_info(thing, thing_types);
+                    AutomapThing {
+                        center: to_image(thing.x as f32, thing.y as f32),
+                        radius: (info.radius as f32 * scale).max(1.5),
+                        angle: (thing.angle as f32).to_radians(),
+                        kind: info.kind,
+                    }
+                })
+                .collect(),
+            false => vec![],
+        };
+
+        let size = |extent: f32| {
+            ((extent * scale + 2.0 * options.margin).ceil()).clamp(1.0, MAX_SIZE) as u32
+        };
+        Self {
+            width: size(extent_x),
+            height: size(extent_y),
+            background: options.background,
+            lines,
+            things,
+        }
+    }
+
+    pub fn to_svg(&self) -> String {
+        let mut svg = String::new();
+        let _ = writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{1}" viewBox="0 0 {0} {1}">"#,
+            self.width, self.height
+        );
+        let _ = writeln!(
+            svg,
+            r#"<rect width="100%" height="100%" fill="{}"/>"#,
+            hex(self.background)
+        );
+
+        for group in self.lines.chunk_by(|a, b| a.kind == b.kind) {
+            let kind = group[0].kind;
+            let _ = writeln!(
+                svg,
+                r#"<g class="{}" stroke="{}" stroke-linecap="round">"#,
+                kind.class(),
+                hex(kind.color())
+            );
+            for line in group {
+                let _ = writeln!(
+                    svg,
+                    r#"<line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}"/>"#,
+                    line.start.0, line.start.1, line.end.0, line.end.1
+                );
+            }
+            svg.push_str("</g>\n");
+        }
+
+        if !self.things.is_empty() {
+            svg.push_str("<g class=\"things\">\n");
+            for thing in &self.things {
+                let color = hex(thing.kind.color());
+                let (x, y) = thing.center;
+                let _ = writeln!(
+                    svg,
+                    r#"<circle class="{}" cx="{x:.1}" cy="{y:.1}" r="{:.1}" fill="{color}"/>"#,
+                    thing.kind.class(),
+                    thing.radius
+                );
+                if thing.kind.has_direction() {
+                    let (tip_x, tip_y) = thing.direction_tip();
+                    let _ = writeln!(
+                        svg,
+                        r#"<line x1="{x:.1}" y1="{y:.1}" x2="{tip_x:.1}" y2="{tip_y:.1}" stroke="{color}"/>"#,
+                    );
+                }
+            }
+            svg.push_str("</g>\n");
+        }
+
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /// Rasterizes the map with one pixel wide, aliased lines.
+    pub fn to_image(&self) -> RgbaImage {
+        let mut canvas = Canvas::new(self.width, self.height, self.background);
+        for line in &self.lines {
+            canvas.line(line.start, line.end, line.kind.color());
+        }
+        for thing in &self.things {
+            let color = thing.kind.color();
+            canvas.disc(thing.center, thing.radius, color);
+            if thing.kind.has_direction() {
+                canvas.line(thing.center, thing.direction_tip(), color);
+            }
+        }
+
+        canvas.image
+    }
+
+    pub fn to_png(&self) -> Vec<u8> {
+        self.to_image().to_png()
+    }
+}
+
+impl AutomapThing {
+    /// The end of the line showing which way the thing faces. Image y points
+    /// down, so the angle is flipped.
+    fn direction_tip(&self) -> (f32, f32) {
+        let length = self.radius * 2.0;
+        (
+            self.center.0 + self.angle.cos() * length,
+            self.center.1 - self.angle.sin() * length,
+        )
+    }
+}
+
+fn vertex_bounds(map: &Map) -> AutomapBounds {
+    let xs = map.vertices.iter().map(|vertex| vertex.x as f32);
+    let ys = map.vertices.iter().map(|vertex| vertex.y as f32);
+    match map.vertices.is_empty() {
+        true => AutomapBounds {
+            min_x: 0.0,
+            min_y: 0.0,
+            max_x: 0.0,
+            max_y: 0.0,
+        },
+        false => AutomapBounds {
+            min_x: xs.clone().fold(f32::MAX, f32::min),
+            min_y: ys.clone().fold(f32::MAX, f32::min),
+            max_x: xs.fold(f32::MIN, f32::max),
+            max_y: ys.fold(f32::MIN, f32::max),
+        },
+    }
+}
+
+fn line_kind(map: &Map, linedef: &Linedef) -> AutomapLineKind {
+    if linedef.flags & LINE_DONT_DRAW != 0 {
+        return AutomapLineKind::Hidden;
+    }
+    if linedef.flags & LINE_SECRET != 0 {
+        return AutomapLineKind::Secret;
+    }
+    if linedef.line_type != 0 {
+        return AutomapLineKind::Special;
+    }
+
+    let sector = |sidedef_idx: Option<u16>| {
+        let sidedef = map.sidedefs.get(sidedef_idx? as usize)?;
+        map.sectors.get(sidedef.sector_idx as usize)
+    };
+    match (
+        sector(linedef.right_sidedef_idx),
+        sector(linedef.left_sidedef_idx),
+    ) {
+        (Some(front), Some(back)) if front.floor_height != back.floor_height => {
+            AutomapLineKind::FloorStep
+        }
+        (Some(front), Some(back)) if front.ceiling_height != back.ceiling_height => {
+            AutomapLineKind::CeilingStep
+        }
+        (Some(_), Some(_)) => AutomapLineKind::TwoSided,
+        _ => AutomapLineKind::OneSided,
+    }
+}
+
+fn thing_info(thing: &Thing, thing_types: &HashMap<u16, AutomapThingInfo>) -> AutomapThingInfo {
+    thing_types
+        .get(&thing.thing_type)
+        .copied()
+        .unwrap_or(AutomapThingInfo {
+            kind: AutomapThingKind::Unknown,
+            radius: 16,
+        })
+}
+
+fn hex((r, g, b): (u8, u8, u8)) -> String {
+    format!("#{r:02x}{g:02x}{b:02x}")
+}
+
+struct Canvas {
+    image: RgbaImage,
+}
+
+impl Canvas {
+    fn new(width: u32, height: u32, (r, g, b): (u8, u8, u8)) -> Self {
+        Self {
+            image: RgbaImage {
+                width,
+                height,
+                pixels: [r, g, b, 0xFF].repeat(width as usize * height as usize),
+            },
+        }
+    }
+
+    fn plot(&mut self, x: i64, y: i64, (r, g, b): (u8, u8, u8)) {
+        if x < 0 || y < 0 || x >= self.image.width as i64 || y >= self.image.height as i64 {
+            return;
+        }
+        let offset = (y as usize * self.image.width as usize + x as usize) * 4;
+        self.image.pixels[offset..offset + 3].copy_from_slice(&[r, g, b]);
+    }
+
+    /// Bresenham's line, clipped to the canvas first so lines far outside the
+    /// bounds don't cost anything.
+    fn line(&mut self, start: (f32, f32), end: (f32, f32), color: (u8, u8, u8)) {
+        let Some((start, end)) = self.clip(start, end) else {
+            return;
+        };
+
+        let (mut x, mut y) = (start.0.floor() as i64, start.1.floor() as i64);
+        let (end_x, end_y) = (end.0.floor() as i64, end.1.floor() as i64);
+        let (dx, dy) = ((end_x - x).abs(), -(end_y - y).abs());
+        let (step_x, step_y) = ((end_x - x).signum(), (end_y - y).signum());
+        let mut error = dx + dy;
+        loop {
+            self.plot(x, y, color);
+            if x == end_x && y == end_y {
+                break;
+            }
+            let doubled = 2 * error;
+            if doubled >= dy {
+                error += dy;
+                x += step_x;
+            }
+            if doubled <= dx {
+                error += dx;
+                y += step_y;
+            }
+        }
+    }
+
+    /// Liang-Barsky clipping to the canvas, with a pixel of slack.
+    fn clip(&self, start: (f32, f32), end: (f32, f32)) -> Option<((f32, f32), (f32, f32))> {
+        let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+        let (max_x, max_y) = (
+            self.image.width as f32 + 1.0,
+            self.image.height as f32 + 1.0,
+        );
+        let (mut t0, mut t1) = (0.0f32, 1.0f32);
+        for (p, q) in [
+            (-dx, start.0 + 1.0),
+            (dx, max_x - start.0),
+            (-dy, start.1 + 1.0),
+            (dy, max_y - start.1),
+        ] {
+            if p == 0.0 {
+                if q < 0.0 {
+                    return None;
+                }
+                continue;
+            }
+            let t = q / p;
+            if p < 0.0 {
+                t0 = t0.max(t);
+            } else {
+                t1 = t1.min(t);
+            }
+        }
+        if t0 > t1 {
+            return None;
+        }
+
+        Some((
+            (start.0 + t0 * dx, start.1 + t0 * dy),
+            (start.0 + t1 * dx, start.1 + t1 * dy),
+        ))
+    }
+
+    fn disc(&mut self, center: (f32, f32), radius: f32, color: (u8, u8, u8)) {
+        let min_x = (center.0 - radius).floor() as i64;
+        let max_x = (center.0 + radius).ceil() as i64;
+        let min_y = (center.1 - radius).floor() as i64;
+        let max_y = (center.1 + radius).ceil() as i64;
+        for y in min_y..=max_y {
+            for x in min_x..=max_x {
+                let (dx, dy) = (x as f32 + 0.5 - center.0, y as f32 + 0.5 - center.1);
+                if dx * dx + dy * dy <= radius * radius {
+                    self.plot(x, y, color);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{NodeFormat, PngImage, Reject, Sector, Sidedef, Vertex};
+
+    /// Two 64x64 rooms side by side, with a step between them.
+    fn two_rooms() -> Map {
+        let vertices = [(0, 0), (64, 0), (128, 0), (128, 64), (64, 64), (0, 64)]
+            .into_iter()
+            .map(|(x, y)| Vertex { x, y })
+            .collect();
+        let line = |start, end, right, left, flags, line_type| Linedef {
+            start_vertex_idx: start,
+            end_vertex_idx: end,
+            flags,
+            line_type,
+            sector_tag: 0,
+            right_sidedef_idx: Some(right),
+            left_sidedef_idx: left,
+        };
+        let linedefs = vec![
+            line(0, 1, 0, None, 1, 0),
+            line(1, 2, 1, None, 1, 0),
+            line(2, 3, 1, None, 1, 1),
+            line(3, 4, 1, None, LINE_SECRET, 0),
+            line(4, 5, 0, None, 1, 0),
+            line(5, 0, 0, None, LINE_DONT_DRAW, 0),
+            line(1, 4, 0, Some(1), 4, 0),
+        ];
+        let sidedef = |sector_idx| Sidedef {
+            x_offset: 0,
+            y_offset: 0,
+            upper_texture: "-".to_string(),
+            lower_texture: "-".to_string(),
+            middle_texture: "-".to_string(),
+            sector_idx,
+        };
+        let sector = |floor_height| Sector {
+            floor_height,
+            ceiling_height: 128,
+            floor_flat: "FLOOR4_8".to_string(),
+            ceiling_flat: "CEIL3_5".to_string(),
+            light_level: 160,
+            special_type: 0,
+            sector_tag: 0,
+        };
+        let thing = |x, thing_type| Thing {
+            x,
+            y: 32,
+            angle: 0,
+            thing_type,
+            spawn_flags: 7,
+        };
+
+        Map {
+            name: "MAP01".to_string(),
+            things: vec![thing(32, 1), thing(96, 3001), thing(100, 9999)],
+            sectors: vec![sector(0), sector(24)],
+            sidedefs: vec![sidedef(0), sidedef(1)],
+            linedefs,
+            vertices,
+            segs: None,
+            subsectors: None,
+            nodes: None,
+            node_vertices: vec![],
+            node_format: NodeFormat::Vanilla,
+            blockmap: None,
+            reject: Reject::all_visible(2),
+            udmf: None,
+        }
+    }
+
+    #[test]
+    fn render_automap() {
+        let map = two_rooms();
+        let info = |kind, radius| AutomapThingInfo { kind, radius };
+        let thing_types = HashMap::from([
+            (1, info(AutomapThingKind::Player, 16)),
+            (3001, info(AutomapThingKind::Monster, 20)),
+        ]);
+        let options = AutomapOptions {
+            scale: 0.5,
+            margin: 2.0,
+            ..Default::default()
+        };
+        let automap = Automap::new(&map, &thing_types, &options);
+
+        assert_eq!((automap.width, automap.height), (68, 36));
+        let kinds: Vec<_> = automap.lines.iter().map(|line| line.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                AutomapLineKind::FloorStep,
+                AutomapLineKind::OneSided,
+                AutomapLineKind::OneSided,
+                AutomapLineKind::OneSided,
+                AutomapLineKind::Special,
+                AutomapLineKind::Secret,
+            ]
+        );
+        let things: Vec<_> = automap.things.iter().map(|thing| thing.kind).collect();
+        assert_eq!(
+            things,
+            vec![
+                AutomapThingKind::Player,
+                AutomapThingKind::Monster,
+                AutomapThingKind::Unknown,
+            ]
+        );
+
+        let svg = automap.to_svg();
+        assert!(svg.starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg" width="68""#));
+        assert!(svg.contains(r##"<g class="secret" stroke="#ff00ff""##));
+        // The bottom left corner, flipped to the bottom of the image.
+        assert!(svg.contains(r#"<line x1="2.0" y1="34.0" x2="34.0" y2="34.0"/>"#));
+        assert!(svg.contains(r#"<circle class="monster""#));
+        assert!(!svg.contains("hidden"));
+
+        let png = PngImage::decode("MAP01", &automap.to_png()).unwrap();
+        let pixel = |x: u32, y: u32| {
+            let offset = ((y * png.width + x) * 4) as usize;
+            (png.rgba[offset], png.rgba[offset + 1], png.rgba[offset + 2])
+        };
+        assert_eq!(pixel(10, 34), AutomapLineKind::OneSided.color());
+        assert_eq!(pixel(34, 10), AutomapLineKind::FloorStep.color());
+        assert_eq!(pixel(50, 2), AutomapLineKind::Secret.color());
+        // The hidden line isn't drawn.
+        assert_eq!(pixel(2, 10), (0, 0, 0));
+        assert_eq!(pixel(18, 18), AutomapThingKind::Player.color());
+
+        // Zoomed in on the right room, without things.
+        let options = AutomapOptions {
+            scale: 1.0,
+            margin: 0.0,
+            bounds: Some(AutomapBounds {
+                min_x: 64.0,
+                min_y: 0.0,
+                max_x: 128.0,
+                max_y: 64.0,
+            }),
+            show_things: false,
+            ..Default::default()
+        };
+        let automap = Automap::new(&map, &HashMap::new(), &options);
+        assert!(automap.things.is_empty());
+        let image = automap.to_image();
+        assert_eq!((image.width, image.height), (64, 64));
+        let offset = ((10 * 64) * 4) as usize;
+        assert_eq!(&image.pixels[offset..offset + 3], &[0xBC, 0x78, 0x48]);
+
+        // Huge scales are brought down to fit.
+        let options = AutomapOptions {
+            scale: 1e9,
+            margin: 0.0,
+            ..Default::default()
+        };
+        let automap = Automap::new(&map, &thing_types, &options);
+        assert_eq!((automap.width, automap.height), (8192, 4096));
+    }
+}
@@ -0,0 +1,201 @@
+use indexmap::IndexMap;
+
+use crate::{merge::is_marker, Lump, LumpNamespace, Map, Wad};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LumpChange {
+    Added { size: usize },
+    Removed { size: usize },
+    Changed { old_size: usize, new_size: usize },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LumpDiff {
+    pub namespace: LumpNamespace,
+    pub name: String,
+    pub change: LumpChange,
+}
+
+/// How much of each kind of geometry a map has.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MapCounts {
+    pub things: usize,
+    pub linedefs: usize,
+    pub sidedefs: usize,
+    pub vertices: usize,
+    pub sectors: usize,
+}
+
+impl MapCounts {
+    pub fn from_map(map: &Map) -> Self {
+        Self {
+            things: map.things.len(),
+            linedefs: map.linedefs.len(),
+            sidedefs: map.sidedefs.len(),
+            vertices: map.vertices.len(),
+            sectors: map.sectors.len(),
+        }
+    }
+}
+
+/// A map with any changed lumps. Counts are [None] when the map is missing
+/// from that side, or can't be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MapDiff {
+    pub name: String,
+    pub old: Option<MapCounts>,
+    pub new: Option<MapCounts>,
+}
+
+/// The differences between two WADs, lump by lump.
+///
+/// Lumps are matched by namespace and name. Like lookups, only the last lump
+/// of a name in each WAD counts.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WadDiff {
+    /// Changed and removed lumps in the old WAD's order, then added lumps in
+    /// the new WAD's.
+    pub lumps: Vec<LumpDiff>,
+    pub maps: Vec<MapDiff>,
+}
+
+impl WadDiff {
+    pub fn new(old: &Wad, new: &Wad) -> Self {
+        let old_lumps = lumps_by_key(old);
+        let new_lumps = lumps_by_key(new);
+
+        let mut lumps = Vec::new();
+        for ((namespace, name), old_lump) in &old_lumps {
+            let change = match new_lumps.get(&(namespace.clone(), name.clone())) {
+                None => LumpChange::Removed {
+                    size: old_lump.size,
+                },
+                Some(new_lump) if new_lump.bytes() != old_lump.bytes() => LumpChange::Changed {
+                    old_size: old_lump.size,
+                    new_size: new_lump.size,
+                },
+                Some(_) => continue,
+            };
+            lumps.push(LumpDiff {
+                namespace: namespace.clone(),
+                name: name.clone(),
+                change,
+            });
+        }
+        for ((namespace, name), new_lump) in &new_lumps {
+            if !old_lumps.contains_key(&(namespace.clone(), name.clone())) {
+                lumps.push(LumpDiff {
+                    namespace: namespace.clone(),
+                    name: name.clone(),
+                    change: LumpChange::Added {
+                        size: new_lump.size,
+                    },
+                });
+            }
+        }
+
+        let mut map_names: Vec<&String> = Vec::new();
+        for lump in &lumps {
+            if let LumpNamespace::Map(map_name) = &lump.namespace {
+                if !map_names.contains(&map_name) {
+                    map_names.push(map_name);
+                }
+            }
+        }
+        let counts = |wad: &Wad, map_name: &str| {
+            wad.parse_map(map_name)
+                .ok()
+                .map(|map| MapCounts::from_map(&map))
+        };
+        let maps = map_names
+            .into_iter()
+            .map(|map_name| MapDiff {
+                name: map_name.clone(),
+                old: counts(old, map_name),
+                new: counts(new, map_name),
+            })
+            .collect();
+
+        Self { lumps, maps }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lumps.is_empty()
+    }
+}
+
+fn lumps_by_key(wad: &Wad) -> IndexMap<(LumpNamespace, String), &Lump> {
+    wad.lumps_in_order
+        .iter()
+        .filter(|lump| !is_marker(lump))
+        .map(|lump| ((lump.namespace.clone(), lump.name.to_uppercase()), lump))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{merge::tests::add_map, WadBuilder};
+
+    #[test]
+    fn diff_wads() {
+        let mut builder = WadBuilder::new(false);
+        builder
+            .add_lump(&LumpNamespace::Global, "PLAYPAL", vec![1])
+            .unwrap()
+            .add_lump(&LumpNamespace::Global, "DEMO1", vec![2])
+            .unwrap()
+            .add_lump(&LumpNamespace::Flat, "FLOOR0_1", vec![3])
+            .unwrap();
+        add_map(&mut builder, "MAP01", 1);
+        add_map(&mut builder, "MAP02", 1);
+        let old = builder.build().unwrap();
+
+        let mut builder = WadBuilder::new(false);
+        builder
+            .add_lump(&LumpNamespace::Global, "PLAYPAL", vec![1, 1])
+            .unwrap()
+            .add_lump(&LumpNamespace::Flat, "FLOOR0_1", vec![3])
+            .unwrap()
+            .add_lump(&LumpNamespace::Patch, "WALL00_1", vec![4])
+            .unwrap();
+        add_map(&mut builder, "MAP01", 3);
+        add_map(&mut builder, "MAP02", 1);
+        let new = builder.build().unwrap();
+
+        assert!(WadDiff::new(&old, &old).is_empty());
+
+        let diff = WadDiff::new(&old, &new);
+        let changes: Vec<_> = diff
+            .lumps
+            .iter()
+            .map(|lump| (lump.name.as_str(), lump.change))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                (
+                    "PLAYPAL",
+                    LumpChange::Changed {
+                        old_size: 1,
+                        new_size: 2
+                    }
+                ),
+                ("DEMO1", LumpChange::Removed { size: 1 }),
+                (
+                    "THINGS",
+                    LumpChange::Changed {
+                        old_size: 10,
+                        new_size: 30
+                    }
+                ),
+                ("WALL00_1", LumpChange::Added { size: 1 }),
+            ]
+        );
+
+        assert_eq!(diff.maps.len(), 1);
+        assert_eq!(diff.maps[0].name, "MAP01");
+        assert_eq!(diff.maps[0].old.map(|counts| counts.things), Some(1));
+        assert_eq!(diff.maps[0].new.map(|counts| counts.things), Some(3));
+    }
+}
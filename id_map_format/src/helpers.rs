@@ -14,6 +14,12 @@ macro_rules! u32_le {
     };
 }
 
+macro_rules! i32_le {
+    ($bytes:expr) => {
+        i32::from_le_bytes($bytes.try_into().unwrap())
+    };
+}
+
 macro_rules! u16_le {
     ($bytes:expr) => {
         u16::from_le_bytes($bytes.try_into().unwrap())
@@ -36,8 +42,101 @@ pub fn parse_bytes_cstr(bytes: &[u8]) -> Result<CString, NulError> {
     CString::new(str)
 }
 
+/// Parses the 8-byte name at `offset` in a lump, like a flat or texture name
+/// in a map record.
+pub(crate) fn parse_name_at(
+    lump_name: &str,
+    bytes: &[u8],
+    offset: usize,
+) -> Result<String, WadError> {
+    let name = offset
+        .checked_add(8)
+        .and_then(|end| bytes.get(offset..end))
+        .and_then(|name| parse_bytes_cstr(name).ok())
+        .and_then(|name| name.into_string().ok());
+    name.ok_or_else(|| WadError::corrupted(lump_name, offset))
+}
+
+/// Inverse of [parse_bytes_cstr]: pads a name out to 8 bytes with nulls.
+pub fn write_bytes_cstr(str: &str) -> Result<[u8; 8], WadError> {
+    if str.len() > 8 || !str.is_ascii() {
+        return Err(WadError::InvalidLumpName(str.to_string()));
+    }
+
+    let mut bytes = [0u8; 8];
+    bytes[..str.len()].copy_from_slice(str.as_bytes());
+    Ok(bytes)
+}
+
+/// Little-endian cursor over a lump, for formats that are read as a stream
+/// rather than as fixed-size records.
+///
+/// Running off the end is reported as a [WadError::CorruptedLump], at the
+/// offset we tried to read from.
+pub(crate) struct ByteReader<'a> {
+    lump_name: &'a str,
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    pub fn new(lump_name: &'a str, bytes: &'a [u8]) -> Self {
+        Self {
+            lump_name,
+            bytes,
+            offset: 0,
+        }
+    }
+
+    /// Starts a reader at `offset`, which is checked on the first read.
+    pub fn new_at(lump_name: &'a str, bytes: &'a [u8], offset: usize) -> Self {
+        Self {
+            lump_name,
+            bytes,
+            offset,
+        }
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn take(&mut self, len: usize) -> Result<&'a [u8], WadError> {
+        let end = self.offset.checked_add(len);
+        match end.and_then(|end| self.bytes.get(self.offset..end)) {
+            Some(bytes) => {
+                self.offset += len;
+                Ok(bytes)
+            }
+            None => Err(WadError::corrupted(self.lump_name, self.offset)),
+        }
+    }
+
+    pub fn u8(&mut self) -> Result<u8, WadError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, WadError> {
+        Ok(u16_le!(self.take(2)?))
+    }
+
+    pub fn i16(&mut self) -> Result<i16, WadError> {
+        Ok(i16_le!(self.take(2)?))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, WadError> {
+        Ok(u32_le!(self.take(4)?))
+    }
+
+    pub fn i32(&mut self) -> Result<i32, WadError> {
+        Ok(i32_le!(self.take(4)?))
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum WadError {
+    #[error("Failed to read WAD: {0}")]
+    Io(#[from] std::io::Error),
     #[error("Invalid header")]
     InvalidHeader,
     #[error("Corrupted bytes")]
@@ -47,19 +146,57 @@ pub enum WadError {
 
     #[error("Missing lump {0}")]
     MissingLump(String),
-    #[error("Missing namespace {0}")]
-    CorruptedLump(String),
+    #[error("Lump {0} already exists.")]
+    DuplicateLump(String),
+    #[error("Invalid lump name {0}")]
+    InvalidLumpName(String),
+    #[error("Lumps in map {0} cannot be reordered.")]
+    MapLumpsNotReorderable(String),
+    #[error("Lump {lump_name} is corrupted at byte {offset}.")]
+    CorruptedLump { lump_name: String, offset: usize },
 
     #[error("Not enough palettes in PLAYPAL lump.")]
     NotEnoughPalettes,
     #[error("Not enough colormaps in COLORMAP lump.")]
     NotEnoughColormaps,
 
+    #[error("Invalid TEXTMAP: {0}")]
+    InvalidTextmap(String),
+
+    #[error("Invalid MAPINFO: {0}")]
+    InvalidMapInfo(String),
+
+    #[error("Music lump {0} isn't MUS or MIDI.")]
+    UnsupportedMusic(String),
+
+    #[error("Too many entries to encode {0} in the vanilla format.")]
+    LumpOverflow(String),
+    #[error("Map {0} has BSP data that can't be written in its node format.")]
+    IncompatibleNodeFormat(String),
+
     #[error("Requested map {0} not found.")]
     MapDoesNotExist(String),
 
-    #[error("Texture by index not found.")]
-    TexturePatchNotFound,
+    #[error("Texture {0} not found.")]
+    TextureDoesNotExist(String),
     #[error("Patch {0} not found.")]
     PatchDoesNotExist(String),
 }
+
+impl WadError {
+    pub(crate) fn corrupted(lump_name: &str, offset: usize) -> Self {
+        Self::CorruptedLump {
+            lump_name: lump_name.to_string(),
+            offset,
+        }
+    }
+
+    /// The lump the error is about, if it's about one.
+    pub fn lump_name(&self) -> Option<&str> {
+        match self {
+            Self::CorruptedLump { lump_name, .. } => Some(lump_name),
+            Self::MissingLump(lump_name) => Some(lump_name),
+            _ => None,
+        }
+    }
+}
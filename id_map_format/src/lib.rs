@@ -2,30 +2,67 @@ extern crate lazy_static;
 
 #[macro_use]
 mod helpers;
+mod automap;
+mod cp437;
+mod diff;
 mod lumps;
+mod merge;
+mod resource_archive;
+mod wad_builder;
 
-use helpers::parse_bytes_cstr;
+use helpers::{parse_bytes_cstr, ByteReader};
 use lazy_static::lazy_static;
 
 use std::{
     collections::{HashMap, HashSet},
-    rc::Rc,
+    fmt,
+    fs::File,
+    path::Path,
+    sync::Arc,
 };
 
+use memmap2::Mmap;
+
+pub use automap::{
+    Automap, AutomapBounds, AutomapLine, AutomapLineKind, AutomapOptions, AutomapThing,
+    AutomapThingInfo, AutomapThingKind,
+};
+pub use cp437::CP437_CHARS;
+pub use diff::{LumpChange, LumpDiff, MapCounts, MapDiff, WadDiff};
 pub use helpers::WadError;
 pub use lumps::*;
+pub use merge::{LumpSource, MergedResource, MergedWads, ResourceKey};
+pub use resource_archive::{ArchiveEntry, ArchivePath, ResourceArchive};
+pub use wad_builder::WadBuilder;
+
+/// The bytes backing a [Wad], shared by all of its lumps.
+///
+/// This can be an owned buffer, a `&'static [u8]` or a memory-mapped file.
+pub type WadBytes = Arc<dyn AsRef<[u8]> + Send + Sync>;
 
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct Lump {
     pub name: String,
+    pub namespace: LumpNamespace,
     pub offset: usize,
     pub size: usize,
-    bytes: Rc<Vec<u8>>,
+    bytes: WadBytes,
 }
 
 impl Lump {
     pub fn bytes(&self) -> &[u8] {
-        &self.bytes[self.offset..self.offset + self.size]
+        &(*self.bytes).as_ref()[self.offset..self.offset + self.size]
+    }
+}
+
+impl fmt::Debug for Lump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Lump")
+            .field("name", &self.name)
+            .field("namespace", &self.namespace)
+            .field("offset", &self.offset)
+            .field("size", &self.size)
+            .finish_non_exhaustive()
     }
 }
 
@@ -40,7 +77,11 @@ pub enum LumpNamespace {
 
 pub struct Wad {
     pub is_iwad: bool,
+    /// Where the directory starts, from the header. 0 for PK3s.
+    pub directory_offset: usize,
     pub lump_names_in_order: Vec<String>,
+    /// Every lump in directory order, tagged with the namespace it was parsed into.
+    pub lumps_in_order: Vec<Lump>,
 
     /// For more deterministic parsing, we parse the lumps into these namespaces.
     ///
@@ -89,9 +130,34 @@ lazy_static! {
 /// - https://zdoom.org/wiki/WAD
 impl Wad {
     pub fn new(bytes_raw: Vec<u8>) -> Result<Self, WadError> {
+        Self::from_bytes(bytes_raw)
+    }
+
+    /// Parses a WAD without copying it, from anything that can be viewed as bytes.
+    pub fn from_bytes<B: AsRef<[u8]> + Send + Sync + 'static>(bytes: B) -> Result<Self, WadError> {
+        Self::from_shared_bytes(Arc::new(bytes))
+    }
+
+    /// Memory-maps a WAD from disk, so lumps are only read when they're used.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, WadError> {
+        let file = File::open(path)?;
+
+        // SAFETY: The map is read-only, but it's undefined behavior if the file
+        // is modified while it's mapped. Like most tools, we assume WADs aren't
+        // written to while they're open.
+        let mmap = unsafe { Mmap::map(&file)? };
+
+        Self::from_bytes(mmap)
+    }
+
+    pub fn from_shared_bytes(shared_bytes: WadBytes) -> Result<Self, WadError> {
         // Parse header.
 
-        let bytes = Rc::new(bytes_raw);
+        let bytes = (*shared_bytes).as_ref();
+
+        if bytes.len() < 12 {
+            return Err(WadError::InvalidHeader);
+        }
 
         let header = match parse_bytes_cstr(&bytes[0..4]) {
             Ok(header) => header,
@@ -108,15 +174,15 @@ impl Wad {
 
         // Parse directory, get lumps & indices.
 
-        let mut lumps = Vec::<Lump>::with_capacity(num_lumps);
+        // Don't trust num_lumps for the allocation, each entry takes 16 bytes.
+        let mut lumps = Vec::<Lump>::with_capacity(num_lumps.min(bytes.len() / 16));
+        let mut dir_reader = ByteReader::new_at("directory", bytes, dir_start_offset);
 
-        for i in 0..num_lumps {
-            let dir_offset = dir_start_offset + i * 16;
+        for _ in 0..num_lumps {
+            let offset = dir_reader.u32()? as usize;
+            let size = dir_reader.u32()? as usize;
 
-            let offset = u32_le!(&bytes[dir_offset..dir_offset + 4]) as usize;
-            let size = u32_le!(&bytes[dir_offset + 4..dir_offset + 8]) as usize;
-
-            let name_cstr = match parse_bytes_cstr(&bytes[dir_offset + 8..dir_offset + 16]) {
+            let name_cstr = match parse_bytes_cstr(dir_reader.take(8)?) {
                 Ok(name) => name,
                 Err(_) => return Err(WadError::CorruptedBytes),
             };
@@ -126,9 +192,17 @@ impl Wad {
                 Err(_) => return Err(WadError::CorruptedString),
             };
 
+            // Empty lumps (like markers) are sometimes written with a garbage
+            // offset, which is harmless since they're never read.
+            let offset = if size == 0 { 0 } else { offset };
+            if offset.checked_add(size).is_none_or(|end| end > bytes.len()) {
+                return Err(WadError::corrupted(&name, offset));
+            }
+
             lumps.push(Lump {
                 name,
-                bytes: bytes.clone(),
+                namespace: LumpNamespace::Global,
+                bytes: shared_bytes.clone(),
                 offset,
                 size,
             });
@@ -138,8 +212,11 @@ impl Wad {
 
         let mut curr_namespace = LumpNamespace::Global;
         let mut map_iter_idx: i128 = 0;
+        // UDMF maps run from the marker to ENDMAP, with no fixed lumps in between.
+        let mut is_udmf_map = false;
 
         let mut lump_names_in_order = Vec::new();
+        let mut lumps_in_order = Vec::with_capacity(lumps.len());
 
         let mut lump_namespaces: HashMap<LumpNamespace, HashMap<String, Lump>> = HashMap::new();
 
@@ -152,6 +229,12 @@ impl Wad {
                 map_iter_idx = -1;
             }
 
+            // Likewise, if it's TEXTMAP we're in a UDMF map.
+            if i < lumps.len() - 1 && lumps[i + 1].name == "TEXTMAP" {
+                curr_namespace = LumpNamespace::Map(lump.name.clone());
+                is_udmf_map = true;
+            }
+
             // Entering a patch block.
             if lumps[i].name == "P_START" || lumps[i].name == "PP_START" {
                 curr_namespace = LumpNamespace::Patch;
@@ -168,7 +251,9 @@ impl Wad {
             }
 
             if let LumpNamespace::Map(_) = curr_namespace {
-                if map_iter_idx == -1 {
+                if is_udmf_map {
+                    // Everything up to ENDMAP is part of the map.
+                } else if map_iter_idx == -1 {
                     map_iter_idx = 0;
                 } else {
                     let map_iter_idx_usize: usize = map_iter_idx as usize;
@@ -181,10 +266,8 @@ impl Wad {
                         map_iter_idx += 1;
                     } else if !OPTIONAL_MAP_LUMP_NAMES.contains(lump.name.as_str()) {
                         if map_iter_idx != ORDERED_MAP_LUMP_NAMES.len() as i128 {
-                            return Err(WadError::CorruptedLump(
-                                ORDERED_MAP_LUMP_NAMES[map_iter_idx_usize]
-                                    .to_string()
-                                    .clone(),
+                            return Err(WadError::MissingLump(
+                                ORDERED_MAP_LUMP_NAMES[map_iter_idx_usize].to_string(),
                             ));
                         }
 
@@ -197,17 +280,27 @@ impl Wad {
             }
 
             // Add to the current namespace.
+            let mut lump = lump.clone();
+            lump.namespace = curr_namespace.clone();
+            lumps_in_order.push(lump.clone());
+
             match lump_namespaces.get_mut(&curr_namespace) {
                 Some(lump_map) => {
-                    lump_map.insert(lump.name.to_uppercase().clone(), lump.clone());
+                    lump_map.insert(lump.name.to_uppercase().clone(), lump);
                 }
                 None => {
                     let mut lump_map = HashMap::new();
-                    lump_map.insert(lump.name.to_uppercase().clone(), lump.clone());
+                    lump_map.insert(lump.name.to_uppercase().clone(), lump);
                     lump_namespaces.insert(curr_namespace.clone(), lump_map);
                 }
             }
 
+            // Leaving a UDMF map.
+            if is_udmf_map && lumps[i].name == "ENDMAP" {
+                curr_namespace = LumpNamespace::Global;
+                is_udmf_map = false;
+            }
+
             // Leaving a patch block.
             if lumps[i].name == "P_END" || lumps[i].name == "PP_END" {
                 curr_namespace = LumpNamespace::Global;
@@ -226,22 +319,20 @@ impl Wad {
 
         Ok(Self {
             is_iwad,
+            directory_offset: dir_start_offset,
             lump_names_in_order,
+            lumps_in_order,
             lump_namespaces,
         })
     }
 
+    /// Map names in the order their markers appear in the WAD.
     pub fn map_names(&self) -> Vec<String> {
-        let map_names: Vec<String> = self
-            .lump_namespaces
-            .keys()
-            .filter_map(|k| match k {
-                LumpNamespace::Map(map_name) => Some(map_name.clone()),
-                _ => None,
-            })
-            .collect();
-
-        map_names
+        self.lumps_in_order
+            .iter()
+            .filter(|lump| lump.namespace == LumpNamespace::Map(lump.name.clone()))
+            .map(|lump| lump.name.clone())
+            .collect()
     }
 
     pub fn endoom_or_endtext(&self) -> Option<Vec<u8>> {
@@ -257,3 +348,367 @@ impl Wad {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn wads_are_send_and_sync() {
+        assert_send_sync::<Wad>();
+        assert_send_sync::<Lump>();
+    }
+
+    #[test]
+    fn from_file_and_from_bytes() {
+        let mut builder = WadBuilder::new(false);
+        builder
+            .add_lump(&LumpNamespace::Global, "DEMO1", vec![1, 2, 3])
+            .unwrap();
+        let bytes = builder.to_bytes();
+
+        let path = std::env::temp_dir().join(format!("id_map_format_{}.wad", std::process::id()));
+        std::fs::write(&path, &bytes).unwrap();
+        let mapped = Wad::from_file(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        for wad in [mapped.unwrap(), Wad::from_bytes(bytes).unwrap()] {
+            let lump = lump_from_namespace(&LumpNamespace::Global, "DEMO1", &wad).unwrap();
+            assert_eq!(lump.bytes(), &[1, 2, 3]);
+        }
+
+        assert!(matches!(
+            Wad::from_file(std::env::temp_dir().join("does_not_exist.wad")),
+            Err(WadError::Io(_))
+        ));
+    }
+
+    /// A WAD with one of everything we know how to parse.
+    fn corpus_wad() -> Vec<u8> {
+        let mut builder = WadBuilder::new(true);
+        let global = LumpNamespace::Global;
+
+        builder
+            .add_lump(&global, "PLAYPAL", (0..768).map(|i| i as u8).collect())
+            .unwrap();
+        builder
+            .add_lump(
+                &global,
+                "COLORMAP",
+                (0..34 * 256).map(|i| i as u8).collect(),
+            )
+            .unwrap();
+
+        let mut pnames = 1u32.to_le_bytes().to_vec();
+        pnames.extend_from_slice(b"WALL\0\0\0\0");
+        builder.add_lump(&global, "PNAMES", pnames).unwrap();
+
+        let mut texture1 = Vec::new();
+        texture1.extend_from_slice(&1u32.to_le_bytes());
+        texture1.extend_from_slice(&8u32.to_le_bytes());
+        texture1.extend_from_slice(b"STARTAN3");
+        texture1.extend_from_slice(&[0; 4]);
+        texture1.extend_from_slice(&64u16.to_le_bytes());
+        texture1.extend_from_slice(&128u16.to_le_bytes());
+        texture1.extend_from_slice(&[0; 4]);
+        texture1.extend_from_slice(&1u16.to_le_bytes());
+        texture1.extend_from_slice(&[0, 0, 0, 0, 0, 0, 1, 0, 0, 0]);
+        builder.add_lump(&global, "TEXTURE1", texture1).unwrap();
+
+        // A 2x2 patch, where each column is a single span.
+        let mut patch = Vec::new();
+        for value in [2u16, 2, 0, 0] {
+            patch.extend_from_slice(&value.to_le_bytes());
+        }
+        patch.extend_from_slice(&16u32.to_le_bytes());
+        patch.extend_from_slice(&22u32.to_le_bytes());
+        for _ in 0..2 {
+            patch.extend_from_slice(&[0, 2, 0, 1, 2, 0, 255]);
+        }
+        builder
+            .add_lump(&LumpNamespace::Patch, "WALL", patch.clone())
+            .unwrap();
+
+        // The same patch as a font glyph, and a graphic.
+        builder
+            .add_lump(&global, "STCFN065", patch.clone())
+            .unwrap()
+            .add_lump(&global, "TITLEPIC", patch)
+            .unwrap();
+
+        // A 2x2 PNG, as a patch and a flat.
+        let png = RgbaImage {
+            width: 2,
+            height: 2,
+            pixels: (0..16).map(|i| i * 16).collect(),
+        }
+        .to_png();
+        builder
+            .add_lump(&LumpNamespace::Patch, "PNGWALL", png.clone())
+            .unwrap()
+            .add_lump(&LumpNamespace::Flat, "PNGFLAT", png)
+            .unwrap();
+
+        // A raw flat, short enough to be padded.
+        builder
+            .add_lump(&LumpNamespace::Flat, "FLOOR4_8", (0..64).collect())
+            .unwrap();
+
+        // One note on channel 0, then the end of the score.
+        let mut mus = b"MUS\x1A".to_vec();
+        for value in [8u16, 18, 1, 0, 1, 0, 30] {
+            mus.extend_from_slice(&value.to_le_bytes());
+        }
+        mus.extend_from_slice(&[0x90, 0x80 | 60, 100, 70, 0x80, 60, 0, 0x60]);
+        builder.add_lump(&global, "D_RUNNIN", mus).unwrap();
+
+        // A DMX sound with 3 samples between 16 bytes of padding, and a PC
+        // speaker sound with 4 tones.
+        let mut dmx = Vec::new();
+        dmx.extend_from_slice(&3u16.to_le_bytes());
+        dmx.extend_from_slice(&11025u16.to_le_bytes());
+        dmx.extend_from_slice(&35u32.to_le_bytes());
+        dmx.extend_from_slice(&[0x80; 35]);
+        builder.add_lump(&global, "DSPISTOL", dmx).unwrap();
+        let mut pc_speaker = Vec::new();
+        pc_speaker.extend_from_slice(&0u16.to_le_bytes());
+        pc_speaker.extend_from_slice(&4u16.to_le_bytes());
+        pc_speaker.extend_from_slice(&[0, 40, 80, 120]);
+        builder.add_lump(&global, "DPPISTOL", pc_speaker).unwrap();
+
+        builder
+            .add_lump(&global, "ENDOOM", (0..4000).map(|i| i as u8).collect())
+            .unwrap();
+
+        // A triangle, in vanilla, extended and UDMF formats.
+        let map = |name: &str, node_format| Map {
+            name: name.to_string(),
+            things: vec![Thing {
+                x: 16,
+                y: 16,
+                angle: 90,
+                thing_type: 1,
+                spawn_flags: 7,
+            }],
+            sectors: vec![Sector {
+                floor_height: 0,
+                ceiling_height: 128,
+                floor_flat: "FLOOR4_8".to_string(),
+                ceiling_flat: "CEIL3_5".to_string(),
+                light_level: 160,
+                special_type: 0,
+                sector_tag: 0,
+            }],
+            sidedefs: (0..3)
+                .map(|_| Sidedef {
+                    x_offset: 0,
+                    y_offset: 0,
+                    upper_texture: "-".to_string(),
+                    lower_texture: "-".to_string(),
+                    middle_texture: "STARTAN3".to_string(),
+                    sector_idx: 0,
+                })
+                .collect(),
+            linedefs: (0..3)
+                .map(|i| Linedef {
+                    start_vertex_idx: i,
+                    end_vertex_idx: (i + 1) % 3,
+                    flags: 1,
+                    line_type: 0,
+                    sector_tag: 0,
+                    right_sidedef_idx: Some(i),
+                    left_sidedef_idx: None,
+                })
+                .collect(),
+            vertices: vec![
+                Vertex { x: 0, y: 0 },
+                Vertex { x: 256, y: 0 },
+                Vertex { x: 0, y: 256 },
+            ],
+            segs: Some(
+                (0..3)
+                    .map(|i| Seg {
+                        start_vertex_idx: i,
+                        end_vertex_idx: (i + 1) % 3,
+                        angle: 0,
+                        linedef_idx: Some(i),
+                        is_left_side: false,
+                        offset: 0,
+                        partner_seg_idx: None,
+                    })
+                    .collect(),
+            ),
+            subsectors: Some(vec![Subsector {
+                num_segs: 3,
+                first_seg_idx: 0,
+            }]),
+            nodes: Some(vec![]),
+            node_vertices: vec![],
+            node_format,
+            blockmap: None,
+            reject: Reject::all_visible(1),
+            udmf: None,
+        };
+
+        let mut vanilla = map("MAP01", NodeFormat::Vanilla);
+        vanilla.blockmap = Some(Blockmap::generate(&vanilla));
+        builder.set_map_from(&vanilla).unwrap();
+
+        builder
+            .set_map_from(&map("MAP02", NodeFormat::Extended { compressed: true }))
+            .unwrap();
+
+        let mut udmf = map("MAP03", NodeFormat::Vanilla);
+        udmf.segs = None;
+        udmf.subsectors = None;
+        udmf.nodes = None;
+        udmf.udmf = Some(UdmfData {
+            namespace: "doom".to_string(),
+            ..Default::default()
+        });
+        builder.set_map_from(&udmf).unwrap();
+
+        builder.to_bytes()
+    }
+
+    /// Runs every parser over the WAD, ignoring errors.
+    fn parse_everything(bytes: Vec<u8>) -> bool {
+        let wad = match Wad::new(bytes) {
+            Ok(wad) => wad,
+            Err(_) => return false,
+        };
+
+        let mut all_ok = wad.parse_palettes().is_ok();
+        all_ok &= wad.parse_colormaps().is_ok();
+        all_ok &= match wad.parse_patch_names() {
+            Ok(patch_names) => wad.parse_textures(&patch_names).is_ok(),
+            Err(_) => false,
+        };
+        all_ok &= wad.parse_patch("WALL").is_ok();
+        all_ok &= wad.parse_patch("PNGWALL").is_ok();
+        all_ok &= wad.parse_flat("FLOOR4_8").is_ok();
+        all_ok &= wad.parse_flat("PNGFLAT").is_ok();
+        all_ok &= wad.parse_graphic("TITLEPIC").is_ok();
+        all_ok &= wad.parse_font("STCFN").is_ok();
+        all_ok &= wad.parse_endoom().is_ok();
+        all_ok &= wad
+            .parse_music("D_RUNNIN")
+            .map(|music| music.to_midi())
+            .is_ok();
+        all_ok &= match wad.parse_sound("DSPISTOL") {
+            Ok(Sound::Digital(sound)) => !sound.to_wav().is_empty(),
+            _ => false,
+        };
+        all_ok &= match wad.parse_sound("DPPISTOL") {
+            Ok(Sound::PcSpeaker(sound)) => !sound.to_wav(11025).is_empty(),
+            _ => false,
+        };
+
+        let mut map_names = wad.map_names();
+        map_names.sort();
+        all_ok &= map_names == ["MAP01", "MAP02", "MAP03"];
+        for map_name in map_names {
+            all_ok &= wad.parse_map(&map_name).is_ok();
+        }
+
+        all_ok
+    }
+
+    /// Deterministic stand-in for a random number generator.
+    fn lcg(state: &mut u64) -> usize {
+        *state = state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (*state >> 33) as usize
+    }
+
+    #[test]
+    fn corrupted_inputs_never_panic() {
+        let bytes = corpus_wad();
+        assert!(parse_everything(bytes.clone()));
+
+        // Truncate the whole file.
+        for len in 0..bytes.len() {
+            parse_everything(bytes[..len].to_vec());
+        }
+
+        // Truncate each lump in turn, keeping the directory intact.
+        let wad = Wad::new(bytes.clone()).unwrap();
+        for lump in wad.lumps_in_order.iter().filter(|lump| lump.size > 0) {
+            let step = (lump.size / 64).max(1);
+            for len in (0..lump.size).step_by(step) {
+                let mut builder = WadBuilder::from_wad(&wad).unwrap();
+                builder
+                    .replace_lump(&lump.namespace, &lump.name, lump.bytes()[..len].to_vec())
+                    .unwrap();
+                parse_everything(builder.to_bytes());
+            }
+        }
+
+        // Overwrite a few random bytes, including the header and directory.
+        let mut state = 0x1D;
+        for _ in 0..5000 {
+            let mut corrupted = bytes.clone();
+            for _ in 0..1 + lcg(&mut state) % 4 {
+                let idx = lcg(&mut state) % corrupted.len();
+                corrupted[idx] = lcg(&mut state) as u8;
+            }
+            parse_everything(corrupted);
+        }
+    }
+
+    #[test]
+    fn errors_carry_lump_and_offset() {
+        let wad = Wad::new(corpus_wad()).unwrap();
+
+        // Point the patch's second column past the end of the lump.
+        let patch = lump_from_namespace(&LumpNamespace::Patch, "WALL", &wad).unwrap();
+        let mut patch_bytes = patch.bytes().to_vec();
+        patch_bytes[12..16].copy_from_slice(&1000u32.to_le_bytes());
+
+        let mut builder = WadBuilder::from_wad(&wad).unwrap();
+        builder
+            .replace_lump(&LumpNamespace::Patch, "WALL", patch_bytes)
+            .unwrap();
+
+        let wad = builder.build().unwrap();
+        match wad.parse_patch("WALL") {
+            Err(WadError::CorruptedLump { lump_name, offset }) => {
+                assert_eq!(lump_name, "WALL");
+                assert_eq!(offset, 1000);
+            }
+            result => panic!("Expected a corrupted lump, got {:?}", result.map(|_| ())),
+        }
+
+        // Truncating THINGS reports the partial record.
+        let mut builder = WadBuilder::from_wad(&wad).unwrap();
+        builder
+            .replace_lump(
+                &LumpNamespace::Map("MAP01".to_string()),
+                "THINGS",
+                vec![0; 15],
+            )
+            .unwrap();
+        assert!(matches!(
+            builder.build().unwrap().parse_map("MAP01"),
+            Err(WadError::CorruptedLump { offset: 10, .. })
+        ));
+
+        // A flat name that isn't text reports where the name starts.
+        let mut sector = vec![0; 26];
+        sector[12] = 0xFF;
+        let mut builder = WadBuilder::from_wad(&wad).unwrap();
+        builder
+            .replace_lump(&LumpNamespace::Map("MAP01".to_string()), "SECTORS", sector)
+            .unwrap();
+        match builder.build().unwrap().parse_map("MAP01") {
+            Err(WadError::CorruptedLump { lump_name, offset }) => {
+                assert_eq!(lump_name, "SECTORS");
+                assert_eq!(offset, 12);
+            }
+            result => panic!("Expected a corrupted lump, got {:?}", result.map(|_| ())),
+        }
+    }
+}
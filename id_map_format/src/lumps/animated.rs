@@ -0,0 +1,170 @@
+use crate::{
+    helpers::{parse_bytes_cstr, ByteReader},
+    lump_from_namespace, LumpNamespace, Wad, WadError,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnimatedKind {
+    Flat,
+    Texture,
+}
+
+/// An animation from the Boom ANIMATED lump: every flat or texture from
+/// `first` to `last`, in WAD order, each shown for `speed` tics.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnimatedDef {
+    pub kind: AnimatedKind,
+    pub first: String,
+    pub last: String,
+    pub speed: u32,
+}
+
+/// A switch from the Boom SWITCHES lump, which swaps between the `off` and
+/// `on` textures when used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SwitchDef {
+    pub off: String,
+    pub on: String,
+    /// 1 for shareware, 2 for registered DOOM, 3 for DOOM II.
+    pub episode: u16,
+}
+
+/// Names are 8 characters plus a null terminator.
+fn parse_name(reader: &mut ByteReader, lump_name: &str) -> Result<String, WadError> {
+    let offset = reader.offset();
+    let name = parse_bytes_cstr(reader.take(9)?)
+        .ok()
+        .and_then(|name| name.into_string().ok())
+        .ok_or_else(|| WadError::corrupted(lump_name, offset))?;
+    Ok(name.to_uppercase())
+}
+
+impl Wad {
+    /// References:
+    /// - https://doomwiki.org/wiki/ANIMATED
+    pub fn parse_animated(&self) -> Result<Vec<AnimatedDef>, WadError> {
+        let lump = lump_from_namespace(&LumpNamespace::Global, "ANIMATED", self)?;
+        let mut reader = ByteReader::new(&lump.name, lump.bytes());
+
+        // 23 byte records, until one with a type of 0xFF.
+        let mut animated = Vec::new();
+        loop {
+            let kind = match reader.u8()? {
+                0xFF => break,
+                // Bit 0 marks textures. ZDoom uses bit 1 to allow decals.
+                kind if kind & 1 == 0 => AnimatedKind::Flat,
+                _ => AnimatedKind::Texture,
+            };
+            let last = parse_name(&mut reader, &lump.name)?;
+            let first = parse_name(&mut reader, &lump.name)?;
+            let speed = reader.i32()?.max(1) as u32;
+
+            animated.push(AnimatedDef {
+                kind,
+                first,
+                last,
+                speed,
+            });
+        }
+
+        Ok(animated)
+    }
+
+    /// References:
+    /// - https://doomwiki.org/wiki/SWITCHES
+    pub fn parse_switches(&self) -> Result<Vec<SwitchDef>, WadError> {
+        let lump = lump_from_namespace(&LumpNamespace::Global, "SWITCHES", self)?;
+        let mut reader = ByteReader::new(&lump.name, lump.bytes());
+
+        // 20 byte records, until one with an episode of 0.
+        let mut switches = Vec::new();
+        loop {
+            let off = parse_name(&mut reader, &lump.name)?;
+            let on = parse_name(&mut reader, &lump.name)?;
+            let episode = reader.u16()?;
+            if episode == 0 {
+                break;
+            }
+
+            switches.push(SwitchDef { off, on, episode });
+        }
+
+        Ok(switches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::WadBuilder;
+
+    fn name(name: &str) -> [u8; 9] {
+        let mut bytes = [0; 9];
+        bytes[..name.len()].copy_from_slice(name.as_bytes());
+        bytes
+    }
+
+    #[test]
+    fn parse_animated_and_switches() {
+        let mut animated = Vec::new();
+        for (kind, last, first, speed) in
+            [(0u8, "NUKAGE3", "NUKAGE1", 8), (3, "slime08", "SLIME05", 4)]
+        {
+            animated.push(kind);
+            animated.extend(name(last));
+            animated.extend(name(first));
+            animated.extend(i32::to_le_bytes(speed));
+        }
+        animated.push(0xFF);
+
+        let mut switches = Vec::new();
+        for (off, on, episode) in [("SW1BRCOM", "SW2BRCOM", 1u16), ("", "", 0)] {
+            switches.extend(name(off));
+            switches.extend(name(on));
+            switches.extend(episode.to_le_bytes());
+        }
+
+        let mut builder = WadBuilder::new(false);
+        builder
+            .add_lump(&LumpNamespace::Global, "ANIMATED", animated.clone())
+            .unwrap()
+            .add_lump(&LumpNamespace::Global, "SWITCHES", switches.clone())
+            .unwrap();
+        let wad = builder.build().unwrap();
+
+        assert_eq!(
+            wad.parse_animated().unwrap(),
+            vec![
+                AnimatedDef {
+                    kind: AnimatedKind::Flat,
+                    first: "NUKAGE1".to_string(),
+                    last: "NUKAGE3".to_string(),
+                    speed: 8,
+                },
+                AnimatedDef {
+                    kind: AnimatedKind::Texture,
+                    first: "SLIME05".to_string(),
+                    last: "SLIME08".to_string(),
+                    speed: 4,
+                },
+            ]
+        );
+        assert_eq!(
+            wad.parse_switches().unwrap(),
+            vec![SwitchDef {
+                off: "SW1BRCOM".to_string(),
+                on: "SW2BRCOM".to_string(),
+                episode: 1,
+            }]
+        );
+
+        // A missing terminator is an error, at the offset of the missing record.
+        builder
+            .replace_lump(&LumpNamespace::Global, "ANIMATED", animated[..46].to_vec())
+            .unwrap();
+        assert!(matches!(
+            builder.build().unwrap().parse_animated(),
+            Err(WadError::CorruptedLump { offset: 46, .. })
+        ));
+    }
+}
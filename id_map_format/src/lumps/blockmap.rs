@@ -0,0 +1,286 @@
+use crate::{Lump, Map, WadError};
+
+/// Size of a blockmap cell, in map units.
+pub const BLOCKMAP_BLOCK_SIZE: i32 = 128;
+
+/// The blockmap is a uniform grid over the map, where each block lists the
+/// linedefs that pass through it. It's used for collision detection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Blockmap {
+    /// Bottom-left corner of the grid.
+    pub x_origin: i16,
+    pub y_origin: i16,
+
+    pub num_columns: u16,
+    pub num_rows: u16,
+
+    /// Linedef indices for each block, in row-major order starting from the
+    /// bottom-left: `blocks[row * num_columns + column]`.
+    pub blocks: Vec<Vec<u32>>,
+}
+
+impl Blockmap {
+    /// Returns the block containing the point, if it's inside the grid.
+    pub fn block_at(&self, x: f32, y: f32) -> Option<(u16, u16)> {
+        let column = ((x - self.x_origin as f32) / BLOCKMAP_BLOCK_SIZE as f32).floor();
+        let row = ((y - self.y_origin as f32) / BLOCKMAP_BLOCK_SIZE as f32).floor();
+
+        if column < 0.
+            || row < 0.
+            || column >= self.num_columns as f32
+            || row >= self.num_rows as f32
+        {
+            return None;
+        }
+
+        Some((column as u16, row as u16))
+    }
+
+    pub fn lines_in_block(&self, column: u16, row: u16) -> &[u32] {
+        if column >= self.num_columns || row >= self.num_rows {
+            return &[];
+        }
+
+        let block_idx = row as usize * self.num_columns as usize + column as usize;
+        &self.blocks[block_idx]
+    }
+
+    /// Builds a blockmap from the map's geometry, for maps that don't ship with one.
+    ///
+    /// Like most node builders, the grid starts 8 units outside the map's
+    /// bounding box.
+    pub fn generate(map: &Map) -> Self {
+        let mut min_x = i32::MAX;
+        let mut min_y = i32::MAX;
+        let mut max_x = i32::MIN;
+        let mut max_y = i32::MIN;
+
+        for vertex in &map.vertices {
+            min_x = min_x.min(vertex.x as i32);
+            min_y = min_y.min(vertex.y as i32);
+            max_x = max_x.max(vertex.x as i32);
+            max_y = max_y.max(vertex.y as i32);
+        }
+
+        if map.vertices.is_empty() {
+            return Self {
+                x_origin: 0,
+                y_origin: 0,
+                num_columns: 0,
+                num_rows: 0,
+                blocks: vec![],
+            };
+        }
+
+        let x_origin = (min_x - 8).max(i16::MIN as i32);
+        let y_origin = (min_y - 8).max(i16::MIN as i32);
+
+        let num_columns = ((max_x - x_origin) / BLOCKMAP_BLOCK_SIZE + 1) as usize;
+        let num_rows = ((max_y - y_origin) / BLOCKMAP_BLOCK_SIZE + 1) as usize;
+
+        let mut blocks: Vec<Vec<u32>> = vec![Vec::new(); num_columns * num_rows];
+
+        for (linedef_idx, linedef) in map.linedefs.iter().enumerate() {
+            let (start, end) = match (
+                map.vertices.get(linedef.start_vertex_idx as usize),
+                map.vertices.get(linedef.end_vertex_idx as usize),
+            ) {
+                (Some(start), Some(end)) => (start, end),
+                _ => continue,
+            };
+
+            let (x1, y1) = (start.x as i32 - x_origin, start.y as i32 - y_origin);
+            let (x2, y2) = (end.x as i32 - x_origin, end.y as i32 - y_origin);
+
+            // Only test the blocks within the linedef's bounding box.
+            let min_column = (x1.min(x2) / BLOCKMAP_BLOCK_SIZE) as usize;
+            let max_column = (x1.max(x2) / BLOCKMAP_BLOCK_SIZE) as usize;
+            let min_row = (y1.min(y2) / BLOCKMAP_BLOCK_SIZE) as usize;
+            let max_row = (y1.max(y2) / BLOCKMAP_BLOCK_SIZE) as usize;
+
+            for row in min_row..=max_row {
+                for column in min_column..=max_column {
+                    let left = column as i32 * BLOCKMAP_BLOCK_SIZE;
+                    let bottom = row as i32 * BLOCKMAP_BLOCK_SIZE;
+
+                    if line_touches_box(
+                        (x1, y1),
+                        (x2, y2),
+                        (left, bottom),
+                        (
+                            left + BLOCKMAP_BLOCK_SIZE - 1,
+                            bottom + BLOCKMAP_BLOCK_SIZE - 1,
+                        ),
+                    ) {
+                        blocks[row * num_columns + column].push(linedef_idx as u32);
+                    }
+                }
+            }
+        }
+
+        Self {
+            x_origin: x_origin as i16,
+            y_origin: y_origin as i16,
+            num_columns: num_columns as u16,
+            num_rows: num_rows as u16,
+            blocks,
+        }
+    }
+}
+
+/// A line touches a box (within its bounding box) unless all four corners
+/// are strictly on the same side of it.
+fn line_touches_box(start: (i32, i32), end: (i32, i32), min: (i32, i32), max: (i32, i32)) -> bool {
+    let (dx, dy) = ((end.0 - start.0) as i64, (end.1 - start.1) as i64);
+    let side = |x: i32, y: i32| {
+        let cross = dx * (y - start.1) as i64 - dy * (x - start.0) as i64;
+        cross.signum()
+    };
+
+    let corners = [
+        side(min.0, min.1),
+        side(min.0, max.1),
+        side(max.0, min.1),
+        side(max.0, max.1),
+    ];
+
+    !(corners.iter().all(|s| *s > 0) || corners.iter().all(|s| *s < 0))
+}
+
+/// References:
+/// - "The Unofficial Doom Specs": `docs/dmsp1666.txt`, 4-10.
+/// - https://doomwiki.org/wiki/Blockmap
+pub(crate) fn parse_blockmap(lump: &Lump) -> Result<Blockmap, WadError> {
+    let lump_bytes = lump.bytes();
+    let corrupted = |offset| WadError::corrupted(&lump.name, offset);
+
+    if lump.size < 8 || !lump.size.is_multiple_of(2) {
+        return Err(corrupted(lump.size - lump.size % 2));
+    }
+
+    let x_origin = i16_le!(&lump_bytes[0..2]);
+    let y_origin = i16_le!(&lump_bytes[2..4]);
+    let num_columns = u16_le!(&lump_bytes[4..6]);
+    let num_rows = u16_le!(&lump_bytes[6..8]);
+
+    // The blockmap is addressed in 16-bit words.
+    let words: Vec<u16> = lump_bytes
+        .chunks_exact(2)
+        .map(|word| u16_le!(word))
+        .collect();
+
+    let num_blocks = num_columns as usize * num_rows as usize;
+    if words.len() < 4 + num_blocks {
+        return Err(corrupted(lump.size));
+    }
+
+    let mut blocks: Vec<Vec<u32>> = Vec::with_capacity(num_blocks);
+    for i in 0..num_blocks {
+        let list_offset = words[4 + i] as usize;
+
+        // Every list starts with a 0 and ends with 0xFFFF.
+        let list_len = words
+            .get(list_offset + 1..)
+            .and_then(|list| list.iter().position(|word| *word == 0xFFFF));
+        let list = match list_len {
+            Some(list_len) => &words[list_offset + 1..list_offset + 1 + list_len],
+            None => return Err(corrupted((4 + i) * 2)),
+        };
+
+        blocks.push(list.iter().map(|idx| *idx as u32).collect());
+    }
+
+    Ok(Blockmap {
+        x_origin,
+        y_origin,
+        num_columns,
+        num_rows,
+        blocks,
+    })
+}
+
+pub(crate) fn write_blockmap(blockmap: &Blockmap) -> Result<Vec<u8>, WadError> {
+    let overflow = || WadError::LumpOverflow("BLOCKMAP".into());
+
+    let num_blocks = blockmap.num_columns as usize * blockmap.num_rows as usize;
+    if blockmap.blocks.len() != num_blocks {
+        return Err(WadError::corrupted("BLOCKMAP", 0));
+    }
+
+    let mut words: Vec<u16> = vec![
+        blockmap.x_origin as u16,
+        blockmap.y_origin as u16,
+        blockmap.num_columns,
+        blockmap.num_rows,
+    ];
+
+    // Offsets are filled in as we write each list.
+    words.resize(4 + num_blocks, 0);
+
+    for (i, block) in blockmap.blocks.iter().enumerate() {
+        words[4 + i] = u16::try_from(words.len()).map_err(|_| overflow())?;
+
+        words.push(0);
+        for linedef_idx in block {
+            words.push(u16::try_from(*linedef_idx).map_err(|_| overflow())?);
+        }
+        words.push(0xFFFF);
+    }
+
+    Ok(words.iter().flat_map(|word| word.to_le_bytes()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Linedef, NodeFormat, Reject, Vertex, WadBuilder};
+
+    #[test]
+    fn generate_and_round_trip() {
+        // A single diagonal line from (0, 0) to (200, 200).
+        let mut map = Map {
+            name: "MAP01".to_string(),
+            things: vec![],
+            sectors: vec![],
+            sidedefs: vec![],
+            linedefs: vec![Linedef {
+                start_vertex_idx: 0,
+                end_vertex_idx: 1,
+                flags: 0,
+                line_type: 0,
+                sector_tag: 0,
+                right_sidedef_idx: None,
+                left_sidedef_idx: None,
+            }],
+            vertices: vec![Vertex { x: 0, y: 0 }, Vertex { x: 200, y: 200 }],
+            segs: None,
+            subsectors: None,
+            nodes: None,
+            node_vertices: vec![],
+            node_format: NodeFormat::Vanilla,
+            blockmap: None,
+            reject: Reject::all_visible(0),
+            udmf: None,
+        };
+
+        let blockmap = Blockmap::generate(&map);
+        assert_eq!((blockmap.x_origin, blockmap.y_origin), (-8, -8));
+        assert_eq!((blockmap.num_columns, blockmap.num_rows), (2, 2));
+
+        // The diagonal passes through the bottom-left and top-right blocks,
+        // and only grazes the corners of the others.
+        assert_eq!(blockmap.lines_in_block(0, 0), &[0]);
+        assert_eq!(blockmap.lines_in_block(1, 1), &[0]);
+        assert!(blockmap.lines_in_block(1, 0).is_empty());
+        assert!(blockmap.lines_in_block(0, 1).is_empty());
+        assert_eq!(blockmap.block_at(150., 150.), Some((1, 1)));
+        assert_eq!(blockmap.block_at(-20., 0.), None);
+
+        map.blockmap = Some(blockmap);
+
+        let mut builder = WadBuilder::new(false);
+        builder.set_map_from(&map).unwrap();
+        let parsed = builder.build().unwrap().parse_map("MAP01").unwrap();
+        assert_eq!(parsed.blockmap, map.blockmap);
+    }
+}
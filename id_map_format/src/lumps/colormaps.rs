@@ -15,7 +15,7 @@ impl Wad {
         let lump_bytes = lump.bytes();
 
         if lump.size % 256 != 0 {
-            return Err(WadError::CorruptedLump(lump.name.clone()));
+            return Err(WadError::corrupted(&lump.name, lump.size - lump.size % 256));
         }
 
         let num_colormaps = lump.size / 256;
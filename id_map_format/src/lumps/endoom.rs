@@ -0,0 +1,239 @@
+use std::fmt::Write;
+
+use crate::{
+    cp437::{glyph, CP437_CHARS, GLYPH_HEIGHT, GLYPH_WIDTH},
+    lump_from_namespace, LumpNamespace, Wad, WadError,
+};
+
+pub const ENDOOM_COLUMNS: usize = 80;
+pub const ENDOOM_ROWS: usize = 25;
+
+/// The size of [Endoom::to_rgba], in pixels.
+pub const ENDOOM_IMAGE_WIDTH: u32 = ENDOOM_COLUMNS as u32 * GLYPH_WIDTH;
+pub const ENDOOM_IMAGE_HEIGHT: u32 = ENDOOM_ROWS as u32 * GLYPH_HEIGHT;
+
+/// The 16 colors of the VGA text mode palette.
+pub const VGA_COLORS: [(u8, u8, u8); 16] = [
+    (0x00, 0x00, 0x00),
+    (0x00, 0x00, 0xAA),
+    (0x00, 0xAA, 0x00),
+    (0x00, 0xAA, 0xAA),
+    (0xAA, 0x00, 0x00),
+    (0xAA, 0x00, 0xAA),
+    (0xAA, 0x55, 0x00),
+    (0xAA, 0xAA, 0xAA),
+    (0x55, 0x55, 0x55),
+    (0x55, 0x55, 0xFF),
+    (0x55, 0xFF, 0x55),
+    (0x55, 0xFF, 0xFF),
+    (0xFF, 0x55, 0x55),
+    (0xFF, 0x55, 0xFF),
+    (0xFF, 0xFF, 0x55),
+    (0xFF, 0xFF, 0xFF),
+];
+
+/// VGA colors are ordered blue, green, red; ANSI colors red, green, blue.
+const VGA_TO_ANSI: [u8; 8] = [0, 4, 2, 6, 1, 5, 3, 7];
+
+/// A character on the screen, with its colors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EndoomCell {
+    /// A CP437 character.
+    pub character: u8,
+    /// An index into [VGA_COLORS].
+    pub foreground: u8,
+    /// An index into the first 8 [VGA_COLORS].
+    pub background: u8,
+    pub blink: bool,
+}
+
+impl EndoomCell {
+    pub fn to_char(&self) -> char {
+        CP437_CHARS[self.character as usize]
+    }
+
+    fn ansi_attributes(&self) -> String {
+        let foreground = VGA_TO_ANSI[(self.foreground & 7) as usize];
+        let background = VGA_TO_ANSI[self.background as usize];
+        let mut sgr = match self.foreground >= 8 {
+            true => format!("\x1b[0;{};{}", 90 + foreground, 40 + background),
+            false => format!("\x1b[0;{};{}", 30 + foreground, 40 + background),
+        };
+        if self.blink {
+            sgr.push_str(";5");
+        }
+        sgr.push('m');
+        sgr
+    }
+}
+
+/// The 80x25 text mode screen shown when quitting, from the ENDOOM lump (or
+/// ENDTEXT in Heretic and Hexen).
+///
+/// References:
+/// - https://doomwiki.org/wiki/ENDOOM
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Endoom {
+    /// Row-major.
+    pub cells: Vec<EndoomCell>,
+}
+
+impl Endoom {
+    /// Each cell is a character byte followed by an attribute byte, with the
+    /// foreground color in the low 4 bits, the background color in the next
+    /// 3, and blink in the top bit.
+    pub fn parse(lump_name: &str, bytes: &[u8]) -> Result<Self, WadError> {
+        let size = ENDOOM_COLUMNS * ENDOOM_ROWS * 2;
+        let bytes = bytes
+            .get(..size)
+            .ok_or_else(|| WadError::corrupted(lump_name, bytes.len()))?;
+
+        let cells = bytes
+            .chunks_exact(2)
+            .map(|cell| EndoomCell {
+                character: cell[0],
+                foreground: cell[1] & 0x0F,
+                background: (cell[1] >> 4) & 0x07,
+                blink: cell[1] & 0x80 != 0,
+            })
+            .collect();
+
+        Ok(Self { cells })
+    }
+
+    pub fn cell(&self, column: usize, row: usize) -> &EndoomCell {
+        &self.cells[row * ENDOOM_COLUMNS + column]
+    }
+
+    fn rows(&self) -> impl Iterator<Item = &[EndoomCell]> {
+        self.cells.chunks_exact(ENDOOM_COLUMNS)
+    }
+
+    /// The characters, without colors, one line per row.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for row in self.rows() {
+            let line: String = row.iter().map(EndoomCell::to_char).collect();
+            text.push_str(line.trim_end());
+            text.push('\n');
+        }
+        text
+    }
+
+    /// The screen with ANSI escape codes for colors and blink, for printing
+    /// to a terminal. Colors are reset at the end of every row.
+    pub fn to_ansi(&self) -> String {
+        let mut ansi = String::new();
+        for row in self.rows() {
+            let mut current = None;
+            for cell in row {
+                let attributes = (cell.foreground, cell.background, cell.blink);
+                if current != Some(attributes) {
+                    ansi.push_str(&cell.ansi_attributes());
+                    current = Some(attributes);
+                }
+                ansi.push(cell.to_char());
+            }
+            let _ = writeln!(ansi, "\x1b[0m");
+        }
+        ansi
+    }
+
+    /// Draws the screen as [ENDOOM_IMAGE_WIDTH] by [ENDOOM_IMAGE_HEIGHT] RGBA
+    /// pixels, with an 8x16 font.
+    ///
+    /// Blinking characters are drawn when `blink_visible` is set, and left as
+    /// just their background otherwise. DOS toggles them about twice a second.
+    pub fn to_rgba(&self, blink_visible: bool) -> Vec<u8> {
+        let width = ENDOOM_IMAGE_WIDTH as usize;
+        let mut rgba = vec![0; width * ENDOOM_IMAGE_HEIGHT as usize * 4];
+
+        for (i, cell) in self.cells.iter().enumerate() {
+            let (column, row) = (i % ENDOOM_COLUMNS, i / ENDOOM_COLUMNS);
+            let rows = glyph(cell.character);
+            let visible = !cell.blink || blink_visible;
+
+            for (y, bits) in rows.iter().enumerate() {
+                for x in 0..GLYPH_WIDTH as usize {
+                    let color = match visible && bits & (0x80 >> x) != 0 {
+                        true => VGA_COLORS[cell.foreground as usize],
+                        false => VGA_COLORS[cell.background as usize],
+                    };
+
+                    let pixel_x = column * GLYPH_WIDTH as usize + x;
+                    let pixel_y = row * GLYPH_HEIGHT as usize + y;
+                    let offset = (pixel_y * width + pixel_x) * 4;
+                    rgba[offset..offset + 4].copy_from_slice(&[color.0, color.1, color.2, 0xFF]);
+                }
+            }
+        }
+
+        rgba
+    }
+}
+
+impl Wad {
+    pub fn parse_endoom(&self) -> Result<Endoom, WadError> {
+        let lump = match lump_from_namespace(&LumpNamespace::Global, "ENDOOM", self) {
+            Ok(lump) => lump,
+            Err(_) => lump_from_namespace(&LumpNamespace::Global, "ENDTEXT", self)?,
+        };
+
+        Endoom::parse(&lump.name, lump.bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::WadBuilder;
+
+    #[test]
+    fn parse_endoom() {
+        let mut bytes = [b' ', 0x07].repeat(ENDOOM_COLUMNS * ENDOOM_ROWS);
+        // "Hi" in bright white on blue, then a blinking red box corner.
+        bytes[..6].copy_from_slice(&[b'H', 0x1F, b'i', 0x1F, 0xC9, 0x84]);
+
+        let mut builder = WadBuilder::new(false);
+        builder
+            .add_lump(&LumpNamespace::Global, "ENDTEXT", bytes.clone())
+            .unwrap();
+        let endoom = builder.build().unwrap().parse_endoom().unwrap();
+
+        assert_eq!(
+            endoom.cell(2, 0),
+            &EndoomCell {
+                character: 0xC9,
+                foreground: 4,
+                background: 0,
+                blink: true,
+            }
+        );
+        assert_eq!(endoom.to_text().lines().next(), Some("Hi╔"));
+
+        let ansi = endoom.to_ansi();
+        assert!(ansi.starts_with("\x1b[0;97;44mHi\x1b[0;31;40;5m╔\x1b[0;37;40m "));
+        assert_eq!(ansi.lines().count(), ENDOOM_ROWS);
+
+        let rgba = endoom.to_rgba(true);
+        assert_eq!(
+            rgba.len(),
+            (ENDOOM_IMAGE_WIDTH * ENDOOM_IMAGE_HEIGHT * 4) as usize
+        );
+        let pixel = |rgba: &[u8], x: u32, y: u32| {
+            let offset = ((y * ENDOOM_IMAGE_WIDTH + x) * 4) as usize;
+            rgba[offset..offset + 3].to_vec()
+        };
+        // The top left of the H is blue background, and its left stem white.
+        assert_eq!(pixel(&rgba, 0, 0), vec![0x00, 0x00, 0xAA]);
+        assert_eq!(pixel(&rgba, 0, 2), vec![0xFF, 0xFF, 0xFF]);
+        // The corner of the box blinks.
+        assert_eq!(pixel(&rgba, 18, 6), vec![0xAA, 0x00, 0x00]);
+        assert_eq!(pixel(&endoom.to_rgba(false), 18, 6), vec![0x00, 0x00, 0x00]);
+
+        assert!(matches!(
+            Endoom::parse("ENDOOM", &bytes[..100]),
+            Err(WadError::CorruptedLump { offset: 100, .. })
+        ));
+    }
+}
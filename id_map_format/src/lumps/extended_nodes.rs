@@ -0,0 +1,693 @@
+use std::io::{Read, Write};
+
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+
+use crate::{
+    helpers::ByteReader, seg_vertex, Linedef, Lump, Map, Node, NodeBounds, NodeChild, NodeFormat,
+    NodeVertex, Seg, Subsector, Vertex, WadError,
+};
+
+/// The most a compressed node lump may inflate to. The largest maps around
+/// need a few megabytes, and it stops a tiny lump from exhausting memory.
+const MAX_DECOMPRESSED_LEN: usize = 64 * 1024 * 1024;
+
+pub(crate) struct ExtendedNodes {
+    pub format: NodeFormat,
+    pub node_vertices: Vec<NodeVertex>,
+    pub subsectors: Vec<Subsector>,
+    pub segs: Vec<Seg>,
+    pub nodes: Vec<Node>,
+}
+
+/// Parses ZDoom's extended node formats, which pack vertices, subsectors,
+/// segs and nodes into a single lump with 32-bit indices.
+///
+/// Returns [None] if the lump doesn't start with one of the magic headers.
+///
+/// References:
+/// - https://zdoom.org/wiki/Node#ZDoom_extended_nodes
+pub(crate) fn parse_extended_nodes(
+    lump: &Lump,
+    vertices: &[Vertex],
+    linedefs: &[Linedef],
+) -> Result<Option<ExtendedNodes>, WadError> {
+    let lump_bytes = lump.bytes();
+    if lump_bytes.len() < 4 {
+        return Ok(None);
+    }
+
+    let format = match &lump_bytes[0..4] {
+        b"XNOD" => NodeFormat::Extended { compressed: false },
+        b"ZNOD" => NodeFormat::Extended { compressed: true },
+        b"XGLN" => NodeFormat::ExtendedGl {
+            version: 1,
+            compressed: false,
+        },
+        b"ZGLN" => NodeFormat::ExtendedGl {
+            version: 1,
+            compressed: true,
+        },
+        b"XGL2" => NodeFormat::ExtendedGl {
+            version: 2,
+            compressed: false,
+        },
+        b"ZGL2" => NodeFormat::ExtendedGl {
+            version: 2,
+            compressed: true,
+        },
+        _ => return Ok(None),
+    };
+
+    // Everything after the magic is zlib-compressed in the Z* variants.
+    //
+    // Offsets in errors are into the decompressed data for those.
+    let decompressed: Vec<u8>;
+    let (body, body_offset) = match format {
+        NodeFormat::Extended { compressed: true }
+        | NodeFormat::ExtendedGl {
+            compressed: true, ..
+        } => {
+            let mut bytes = Vec::new();
+            let read = ZlibDecoder::new(&lump_bytes[4..])
+                .take(MAX_DECOMPRESSED_LEN as u64 + 1)
+                .read_to_end(&mut bytes);
+            if read.is_err() || bytes.len() > MAX_DECOMPRESSED_LEN {
+                return Err(WadError::corrupted(&lump.name, 4));
+            }
+            decompressed = bytes;
+            (&decompressed[..], 0)
+        }
+        _ => (lump_bytes, 4),
+    };
+
+    let mut reader = ByteReader::new_at(&lump.name, body, body_offset);
+
+    // Vertices.
+    //
+    // Indices below `num_original_vertices` refer to VERTEXES, the rest to
+    // the new vertices. We remap them to follow on directly from VERTEXES.
+    let num_original_vertices = reader.u32()?;
+    let num_new_vertices = reader.u32()?;
+
+    let mut node_vertices: Vec<NodeVertex> = Vec::new();
+    for _ in 0..num_new_vertices {
+        let x = reader.i32()?;
+        let y = reader.i32()?;
+        node_vertices.push(NodeVertex { x, y });
+    }
+
+    let remap_vertex = |vertex_idx: u32| match vertex_idx < num_original_vertices {
+        true => vertex_idx,
+        // Out of range indices are caught when the segs are checked below.
+        false => (vertices.len() as u32).saturating_add(vertex_idx - num_original_vertices),
+    };
+
+    // Subsectors.
+    //
+    // Segs are stored in subsector order, so only the count is given.
+    let num_subsectors = reader.u32()?;
+
+    let mut subsectors: Vec<Subsector> = Vec::new();
+    let mut first_seg_idx: u32 = 0;
+    for _ in 0..num_subsectors {
+        let num_segs_offset = reader.offset();
+        let num_segs = reader.u32()?;
+        subsectors.push(Subsector {
+            num_segs,
+            first_seg_idx,
+        });
+        first_seg_idx = first_seg_idx
+            .checked_add(num_segs)
+            .ok_or_else(|| WadError::corrupted(&lump.name, num_segs_offset))?;
+    }
+
+    // Segs.
+    //
+    // Errors in the segs are reported at the start of the list, since we
+    // check them after reading it.
+    let segs_offset = reader.offset();
+    let num_segs = reader.u32()?;
+
+    let mut segs: Vec<Seg> = Vec::new();
+    for _ in 0..num_segs {
+        let seg = match format {
+            NodeFormat::ExtendedGl { version, .. } => {
+                // GL segs only store their start vertex; the end vertex is the
+                // start of the next seg in the subsector.
+                let start_vertex_idx = remap_vertex(reader.u32()?);
+                let partner_seg_idx = match reader.u32()? {
+                    u32::MAX => None,
+                    idx => Some(idx),
+                };
+                let linedef_idx = match version {
+                    1 => match reader.u16()? {
+                        u16::MAX => None,
+                        idx => Some(idx as u32),
+                    },
+                    _ => match reader.u32()? {
+                        u32::MAX => None,
+                        idx => Some(idx),
+                    },
+                };
+                let is_left_side = reader.u8()? != 0;
+
+                Seg {
+                    start_vertex_idx,
+                    end_vertex_idx: start_vertex_idx,
+                    angle: 0,
+                    linedef_idx,
+                    is_left_side,
+                    offset: 0,
+                    partner_seg_idx,
+                }
+            }
+            _ => {
+                let start_vertex_idx = remap_vertex(reader.u32()?);
+                let end_vertex_idx = remap_vertex(reader.u32()?);
+                let linedef_idx = Some(reader.u16()? as u32);
+                let is_left_side = reader.u8()? != 0;
+
+                Seg {
+                    start_vertex_idx,
+                    end_vertex_idx,
+                    angle: 0,
+                    linedef_idx,
+                    is_left_side,
+                    offset: 0,
+                    partner_seg_idx: None,
+                }
+            }
+        };
+
+        segs.push(seg);
+    }
+
+    for subsector in &subsectors {
+        let first = subsector.first_seg_idx as usize;
+        let last = first + subsector.num_segs as usize;
+        if last > segs.len() {
+            return Err(WadError::corrupted(&lump.name, segs_offset));
+        }
+
+        if let NodeFormat::ExtendedGl { .. } = format {
+            for i in first..last {
+                let next = if i + 1 == last { first } else { i + 1 };
+                segs[i].end_vertex_idx = segs[next].start_vertex_idx;
+            }
+        }
+    }
+
+    // Neither format stores the angle or offset, so we derive them like
+    // vanilla node builders do.
+    for seg in segs.iter_mut() {
+        let start = seg_vertex(vertices, &node_vertices, seg.start_vertex_idx);
+        let end = seg_vertex(vertices, &node_vertices, seg.end_vertex_idx);
+        let (start, end) = match (start, end) {
+            (Some(start), Some(end)) => (start, end),
+            _ => return Err(WadError::corrupted(&lump.name, segs_offset)),
+        };
+
+        seg.angle = bam_angle(end.0 - start.0, end.1 - start.1);
+
+        if let Some(linedef) = seg.linedef_idx.and_then(|idx| linedefs.get(idx as usize)) {
+            let origin_idx = match seg.is_left_side {
+                true => linedef.end_vertex_idx,
+                false => linedef.start_vertex_idx,
+            };
+
+            if let Some(origin) = vertices.get(origin_idx as usize) {
+                let dx = start.0 - origin.x as f32;
+                let dy = start.1 - origin.y as f32;
+                seg.offset = (dx * dx + dy * dy).sqrt().round() as i16;
+            }
+        }
+    }
+
+    // Nodes.
+    let num_nodes = reader.u32()?;
+
+    let mut nodes: Vec<Node> = Vec::new();
+    for _ in 0..num_nodes {
+        let x = reader.i16()?;
+        let y = reader.i16()?;
+        let dx = reader.i16()?;
+        let dy = reader.i16()?;
+
+        let mut bounds = [NodeBounds {
+            top: 0,
+            bottom: 0,
+            left: 0,
+            right: 0,
+        }; 2];
+        for bounds in bounds.iter_mut() {
+            bounds.top = reader.i16()?;
+            bounds.bottom = reader.i16()?;
+            bounds.left = reader.i16()?;
+            bounds.right = reader.i16()?;
+        }
+
+        let right_child = child_from_u32(reader.u32()?);
+        let left_child = child_from_u32(reader.u32()?);
+
+        nodes.push(Node {
+            x,
+            y,
+            dx,
+            dy,
+            right_bounds: bounds[0],
+            left_bounds: bounds[1],
+            right_child,
+            left_child,
+        });
+    }
+
+    Ok(Some(ExtendedNodes {
+        format,
+        node_vertices,
+        subsectors,
+        segs,
+        nodes,
+    }))
+}
+
+/// Encodes the map's BSP data as a single extended node lump.
+///
+/// Subsectors must own contiguous, in-order runs of segs, since the format
+/// only stores a count per subsector.
+pub(crate) fn write_extended_nodes(map: &Map) -> Result<Vec<u8>, WadError> {
+    let incompatible = || WadError::IncompatibleNodeFormat(map.name.clone());
+
+    let (segs, subsectors, nodes) = match (&map.segs, &map.subsectors, &map.nodes) {
+        (Some(segs), Some(subsectors), Some(nodes)) => (segs, subsectors, nodes),
+        _ => return Err(incompatible()),
+    };
+
+    let (magic, gl_version, compressed): (&[u8; 4], Option<u8>, bool) = match map.node_format {
+        NodeFormat::Extended { compressed: false } => (b"XNOD", None, false),
+        NodeFormat::Extended { compressed: true } => (b"ZNOD", None, true),
+        NodeFormat::ExtendedGl {
+            version: 1,
+            compressed,
+        } => (
+            if compressed { b"ZGLN" } else { b"XGLN" },
+            Some(1),
+            compressed,
+        ),
+        NodeFormat::ExtendedGl {
+            version: 2,
+            compressed,
+        } => (
+            if compressed { b"ZGL2" } else { b"XGL2" },
+            Some(2),
+            compressed,
+        ),
+        _ => return Err(incompatible()),
+    };
+
+    let mut body: Vec<u8> = Vec::new();
+
+    // Vertices.
+    body.extend_from_slice(&(map.vertices.len() as u32).to_le_bytes());
+    body.extend_from_slice(&(map.node_vertices.len() as u32).to_le_bytes());
+    for vertex in &map.node_vertices {
+        body.extend_from_slice(&vertex.x.to_le_bytes());
+        body.extend_from_slice(&vertex.y.to_le_bytes());
+    }
+
+    // Subsectors.
+    body.extend_from_slice(&(subsectors.len() as u32).to_le_bytes());
+    let mut next_seg_idx: u32 = 0;
+    for (i, subsector) in subsectors.iter().enumerate() {
+        if subsector.first_seg_idx != next_seg_idx {
+            return Err(incompatible());
+        }
+        // Reported where the subsector would be in a vanilla SSECTORS lump.
+        next_seg_idx = next_seg_idx
+            .checked_add(subsector.num_segs)
+            .ok_or_else(|| WadError::corrupted("SSECTORS", i * 4))?;
+
+        body.extend_from_slice(&subsector.num_segs.to_le_bytes());
+    }
+    if next_seg_idx as usize != segs.len() {
+        return Err(incompatible());
+    }
+
+    // Segs.
+    body.extend_from_slice(&(segs.len() as u32).to_le_bytes());
+    for seg in segs {
+        body.extend_from_slice(&seg.start_vertex_idx.to_le_bytes());
+
+        match gl_version {
+            None => {
+                let linedef_idx = seg.linedef_idx.ok_or_else(incompatible)?;
+                let linedef_idx = u16::try_from(linedef_idx).map_err(|_| incompatible())?;
+
+                body.extend_from_slice(&seg.end_vertex_idx.to_le_bytes());
+                body.extend_from_slice(&linedef_idx.to_le_bytes());
+            }
+            Some(version) => {
+                let partner_seg_idx = seg.partner_seg_idx.unwrap_or(u32::MAX);
+                body.extend_from_slice(&partner_seg_idx.to_le_bytes());
+
+                match version {
+                    1 => {
+                        let linedef_idx = match seg.linedef_idx {
+                            Some(idx) => u16::try_from(idx)
+                                .ok()
+                                .filter(|idx| *idx != u16::MAX)
+                                .ok_or_else(incompatible)?,
+                            None => u16::MAX,
+                        };
+                        body.extend_from_slice(&linedef_idx.to_le_bytes());
+                    }
+                    _ => {
+                        let linedef_idx = seg.linedef_idx.unwrap_or(u32::MAX);
+                        body.extend_from_slice(&linedef_idx.to_le_bytes());
+                    }
+                }
+            }
+        }
+
+        body.push(seg.is_left_side as u8);
+    }
+
+    // Nodes.
+    body.extend_from_slice(&(nodes.len() as u32).to_le_bytes());
+    for node in nodes {
+        body.extend_from_slice(&node.x.to_le_bytes());
+        body.extend_from_slice(&node.y.to_le_bytes());
+        body.extend_from_slice(&node.dx.to_le_bytes());
+        body.extend_from_slice(&node.dy.to_le_bytes());
+
+        for bounds in [&node.right_bounds, &node.left_bounds] {
+            body.extend_from_slice(&bounds.top.to_le_bytes());
+            body.extend_from_slice(&bounds.bottom.to_le_bytes());
+            body.extend_from_slice(&bounds.left.to_le_bytes());
+            body.extend_from_slice(&bounds.right.to_le_bytes());
+        }
+
+        for child in [node.right_child, node.left_child] {
+            let child = child_to_u32(child).ok_or_else(incompatible)?;
+            body.extend_from_slice(&child.to_le_bytes());
+        }
+    }
+
+    // Writing into a Vec can't fail.
+    let bytes = match compressed {
+        true => {
+            let mut encoder = ZlibEncoder::new(magic.to_vec(), Compression::default());
+            encoder.write_all(&body).unwrap();
+            encoder.finish().unwrap()
+        }
+        false => [magic.as_slice(), &body].concat(),
+    };
+
+    Ok(bytes)
+}
+
+fn child_from_u32(child: u32) -> NodeChild {
+    // The high bit marks a subsector.
+    match child & 0x8000_0000 {
+        0 => NodeChild::Node(child),
+        _ => NodeChild::Subsector(child & 0x7FFF_FFFF),
+    }
+}
+
+fn child_to_u32(child: NodeChild) -> Option<u32> {
+    match child {
+        NodeChild::Node(idx) if idx < 0x8000_0000 => Some(idx),
+        NodeChild::Subsector(idx) if idx < 0x8000_0000 => Some(idx | 0x8000_0000),
+        _ => None,
+    }
+}
+
+/// Converts a direction into a binary angle, where a full turn is 65536.
+pub(crate) fn bam_angle(dx: f32, dy: f32) -> i16 {
+    let turns = dy.atan2(dx) / std::f32::consts::TAU;
+    (turns * 65536.).round() as i32 as i16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LumpNamespace, Reject, WadBuilder};
+
+    /// A 128x128 room split down the middle at x = 64, with the split
+    /// vertices stored as node vertices.
+    fn split_room(node_format: NodeFormat) -> Map {
+        let vertices = vec![
+            Vertex { x: 0, y: 0 },
+            Vertex { x: 0, y: 128 },
+            Vertex { x: 128, y: 128 },
+            Vertex { x: 128, y: 0 },
+        ];
+
+        let linedefs = (0..4)
+            .map(|i| Linedef {
+                start_vertex_idx: i,
+                end_vertex_idx: (i + 1) % 4,
+                flags: 1,
+                line_type: 0,
+                sector_tag: 0,
+                right_sidedef_idx: Some(0),
+                left_sidedef_idx: None,
+            })
+            .collect();
+
+        let seg = |start_vertex_idx, end_vertex_idx, linedef_idx, partner_seg_idx| Seg {
+            start_vertex_idx,
+            end_vertex_idx,
+            angle: 0,
+            linedef_idx,
+            is_left_side: false,
+            offset: 0,
+            partner_seg_idx,
+        };
+
+        let is_gl = matches!(node_format, NodeFormat::ExtendedGl { .. });
+        let segs = match is_gl {
+            true => vec![
+                // East.
+                seg(5, 2, Some(1), None),
+                seg(2, 3, Some(2), None),
+                seg(3, 4, Some(3), None),
+                seg(4, 5, None, Some(7)),
+                // West.
+                seg(0, 1, Some(0), None),
+                seg(1, 5, Some(1), None),
+                seg(5, 4, None, Some(3)),
+                seg(4, 0, Some(3), None),
+            ],
+            false => vec![
+                seg(5, 2, Some(1), None),
+                seg(2, 3, Some(2), None),
+                seg(3, 4, Some(3), None),
+                seg(0, 1, Some(0), None),
+                seg(1, 5, Some(1), None),
+                seg(4, 0, Some(3), None),
+            ],
+        };
+        let segs_per_subsector = segs.len() as u32 / 2;
+
+        let bounds = |left, right| NodeBounds {
+            top: 128,
+            bottom: 0,
+            left,
+            right,
+        };
+
+        Map {
+            name: "MAP01".to_string(),
+            things: vec![],
+            sectors: vec![],
+            sidedefs: vec![],
+            linedefs,
+            vertices,
+            segs: Some(segs),
+            subsectors: Some(vec![
+                Subsector {
+                    num_segs: segs_per_subsector,
+                    first_seg_idx: 0,
+                },
+                Subsector {
+                    num_segs: segs_per_subsector,
+                    first_seg_idx: segs_per_subsector,
+                },
+            ]),
+            nodes: Some(vec![Node {
+                x: 64,
+                y: 0,
+                dx: 0,
+                dy: 128,
+                right_bounds: bounds(64, 128),
+                left_bounds: bounds(0, 64),
+                right_child: NodeChild::Subsector(0),
+                left_child: NodeChild::Subsector(1),
+            }]),
+            node_vertices: vec![
+                NodeVertex { x: 64 << 16, y: 0 },
+                NodeVertex {
+                    x: 64 << 16,
+                    y: 128 << 16,
+                },
+            ],
+            node_format,
+            blockmap: None,
+            reject: Reject::all_visible(0),
+            udmf: None,
+        }
+    }
+
+    fn write_and_parse(map: &Map) -> Map {
+        let mut builder = WadBuilder::new(false);
+        builder.set_map_from(map).unwrap();
+        builder.build().unwrap().parse_map(&map.name).unwrap()
+    }
+
+    #[test]
+    fn round_trip_all_formats() {
+        for node_format in [
+            NodeFormat::Extended { compressed: false },
+            NodeFormat::Extended { compressed: true },
+            NodeFormat::ExtendedGl {
+                version: 1,
+                compressed: false,
+            },
+            NodeFormat::ExtendedGl {
+                version: 1,
+                compressed: true,
+            },
+            NodeFormat::ExtendedGl {
+                version: 2,
+                compressed: false,
+            },
+            NodeFormat::ExtendedGl {
+                version: 2,
+                compressed: true,
+            },
+        ] {
+            let map = split_room(node_format);
+            let parsed = write_and_parse(&map);
+
+            assert_eq!(parsed.node_format, node_format);
+            assert_eq!(parsed.node_vertices, map.node_vertices);
+            assert_eq!(parsed.subsectors, map.subsectors);
+            assert_eq!(parsed.nodes, map.nodes);
+
+            // Angles and offsets are derived from the geometry.
+            let segs = parsed.segs.as_ref().unwrap();
+            for (parsed_seg, seg) in segs.iter().zip(map.segs.as_ref().unwrap()) {
+                assert_eq!(parsed_seg.start_vertex_idx, seg.start_vertex_idx);
+                assert_eq!(parsed_seg.end_vertex_idx, seg.end_vertex_idx);
+                assert_eq!(parsed_seg.linedef_idx, seg.linedef_idx);
+                assert_eq!(parsed_seg.partner_seg_idx, seg.partner_seg_idx);
+            }
+
+            let west_wall = segs.iter().find(|s| s.linedef_idx == Some(0)).unwrap();
+            assert_eq!(west_wall.angle, 0x4000);
+            let south_wall = segs
+                .iter()
+                .find(|s| s.start_vertex_idx == 4 && s.linedef_idx == Some(3))
+                .unwrap();
+            assert_eq!(south_wall.offset, 64);
+
+            assert_eq!(write_and_parse(&parsed), parsed);
+            assert_eq!(parsed.seg_vertex(5), Some((64., 128.)));
+            assert_eq!(parsed.find_subsector(100., 10.), Some(0));
+        }
+    }
+
+    #[test]
+    fn minisegs_need_gl_nodes() {
+        let mut map = split_room(NodeFormat::ExtendedGl {
+            version: 1,
+            compressed: false,
+        });
+
+        map.node_format = NodeFormat::Extended { compressed: false };
+        assert!(matches!(
+            map.to_lumps(),
+            Err(WadError::IncompatibleNodeFormat(_))
+        ));
+
+        map.node_format = NodeFormat::Vanilla;
+        assert!(matches!(
+            map.to_lumps(),
+            Err(WadError::IncompatibleNodeFormat(_))
+        ));
+    }
+
+    #[test]
+    fn subsector_seg_ranges_are_checked() {
+        let map = split_room(NodeFormat::Extended { compressed: false });
+        let parse_nodes = |counts: &[u32]| {
+            let mut nodes = b"XNOD".to_vec();
+            nodes.extend(6u32.to_le_bytes());
+            nodes.extend(0u32.to_le_bytes());
+            nodes.extend((counts.len() as u32).to_le_bytes());
+            for count in counts {
+                nodes.extend(count.to_le_bytes());
+            }
+            // No segs or nodes.
+            nodes.extend([0; 8]);
+
+            let mut builder = WadBuilder::new(false);
+            builder.set_map_from(&map).unwrap();
+            builder
+                .replace_lump(&LumpNamespace::Map(map.name.clone()), "NODES", nodes)
+                .unwrap();
+            builder.build().unwrap().parse_map(&map.name)
+        };
+
+        // More segs than there are.
+        assert!(matches!(
+            parse_nodes(&[5]),
+            Err(WadError::CorruptedLump { offset: 20, .. })
+        ));
+        // Seg counts that overflow.
+        assert!(matches!(
+            parse_nodes(&[u32::MAX, 1]),
+            Err(WadError::CorruptedLump { offset: 20, .. })
+        ));
+
+        let mut map = map.clone();
+        map.subsectors = Some(vec![
+            Subsector {
+                num_segs: u32::MAX,
+                first_seg_idx: 0,
+            },
+            Subsector {
+                num_segs: 1,
+                first_seg_idx: u32::MAX,
+            },
+        ]);
+        assert!(matches!(
+            map.to_lumps(),
+            Err(WadError::CorruptedLump { offset: 4, .. })
+        ));
+    }
+
+    #[test]
+    fn compressed_nodes_are_bounded() {
+        let mut encoder = ZlibEncoder::new(b"ZNOD".to_vec(), Compression::fast());
+        let zeros = vec![0; 1024 * 1024];
+        for _ in 0..=MAX_DECOMPRESSED_LEN / zeros.len() {
+            encoder.write_all(&zeros).unwrap();
+        }
+
+        let map = split_room(NodeFormat::Extended { compressed: true });
+        let mut builder = WadBuilder::new(false);
+        builder.set_map_from(&map).unwrap();
+        builder
+            .replace_lump(
+                &LumpNamespace::Map(map.name.clone()),
+                "NODES",
+                encoder.finish().unwrap(),
+            )
+            .unwrap();
+        assert!(matches!(
+            builder.build().unwrap().parse_map(&map.name),
+            Err(WadError::CorruptedLump { offset: 4, .. })
+        ));
+    }
+}
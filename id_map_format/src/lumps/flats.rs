@@ -0,0 +1,119 @@
+use crate::{is_png, lump_from_namespace, LumpNamespace, Palette, PngImage, Wad, WadError};
+
+/// Flats are 64 pixels wide. Vanilla flats are 64 high, but some ports allow
+/// taller ones.
+pub const FLAT_WIDTH: u32 = 64;
+const FLAT_SIZE: usize = 64 * 64;
+
+/// A floor or ceiling texture.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Flat {
+    pub width: u32,
+    pub height: u32,
+    /// Row-major palette indices.
+    pub pixels: Vec<u8>,
+}
+
+impl Flat {
+    /// The pixel at `(x, y)`, tiling the flat in both directions.
+    pub fn pixel(&self, x: u32, y: u32) -> u8 {
+        self.pixels[((y % self.height) * self.width + x % self.width) as usize]
+    }
+}
+
+impl Wad {
+    /// Raw flats shorter than 64x64 are padded with index 0. PNG flats are
+    /// quantized to this WAD's PLAYPAL, like [Self::parse_patch].
+    pub fn parse_flat(&self, flat_name: &str) -> Result<Flat, WadError> {
+        self.parse_flat_inner(flat_name, None)
+    }
+
+    pub fn parse_flat_with_palette(
+        &self,
+        flat_name: &str,
+        palette: &Palette,
+    ) -> Result<Flat, WadError> {
+        self.parse_flat_inner(flat_name, Some(palette))
+    }
+
+    fn parse_flat_inner(
+        &self,
+        flat_name: &str,
+        palette: Option<&Palette>,
+    ) -> Result<Flat, WadError> {
+        let lump = lump_from_namespace(&LumpNamespace::Flat, &flat_name.to_uppercase(), self)?;
+        let bytes = lump.bytes();
+
+        if is_png(bytes) {
+            let image = PngImage::decode(&lump.name, bytes)?;
+            let pixels = match palette {
+                Some(palette) => image.to_palette_indices(palette),
+                None => image.to_palette_indices(&self.parse_palettes()?[0]),
+            };
+            return Ok(Flat {
+                width: image.width,
+                height: image.height,
+                pixels,
+            });
+        }
+
+        let mut pixels = bytes.to_vec();
+        if pixels.len() < FLAT_SIZE {
+            pixels.resize(FLAT_SIZE, 0);
+        }
+        let height = pixels.len() as u32 / FLAT_WIDTH;
+        pixels.truncate((FLAT_WIDTH * height) as usize);
+
+        Ok(Flat {
+            width: FLAT_WIDTH,
+            height,
+            pixels,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lumps::png::tests::build_png, WadBuilder};
+
+    #[test]
+    fn parse_flats() {
+        // A 1x1 gray PNG.
+        let png = build_png(1, 1, 8, 0, &[], &[0, 200]);
+
+        let mut palette = [(0, 0, 0); 256];
+        palette[7] = (190, 190, 190);
+        let playpal: Vec<u8> = palette.iter().flat_map(|(r, g, b)| [*r, *g, *b]).collect();
+
+        let mut builder = WadBuilder::new(false);
+        builder
+            .add_lump(&LumpNamespace::Global, "PLAYPAL", playpal)
+            .unwrap()
+            .add_lump(&LumpNamespace::Flat, "FLOOR0_1", vec![5; 64 * 64])
+            .unwrap()
+            .add_lump(&LumpNamespace::Flat, "SHORT", vec![5; 100])
+            .unwrap()
+            .add_lump(&LumpNamespace::Flat, "PNGFLAT", png)
+            .unwrap();
+        let wad = builder.build().unwrap();
+
+        let flat = wad.parse_flat("floor0_1").unwrap();
+        assert_eq!((flat.width, flat.height), (64, 64));
+
+        let flat = wad.parse_flat("SHORT").unwrap();
+        assert_eq!(flat.pixels.len(), 64 * 64);
+        assert_eq!((flat.pixels[99], flat.pixels[100]), (5, 0));
+
+        let flat = wad.parse_flat("PNGFLAT").unwrap();
+        assert_eq!((flat.width, flat.height), (1, 1));
+        assert_eq!(flat.pixel(10, 3), 7);
+
+        let mut other_palette = [(0, 0, 0); 256];
+        other_palette[9] = (200, 200, 200);
+        let flat = wad
+            .parse_flat_with_palette("PNGFLAT", &other_palette)
+            .unwrap();
+        assert_eq!(flat.pixels, vec![9]);
+    }
+}
@@ -0,0 +1,151 @@
+use std::collections::BTreeMap;
+
+use crate::{LumpNamespace, Patch, Wad, WadError};
+
+/// A bitmap font made of one patch per character, like DOOM's HUD font
+/// (`STCFN033`...) or Heretic's `FONTA`/`FONTB`.
+///
+/// References:
+/// - https://doomwiki.org/wiki/STCFN
+#[derive(Debug)]
+pub struct Font {
+    /// The lump name prefix, like `STCFN`.
+    pub name: String,
+    pub glyphs: BTreeMap<char, Patch>,
+    /// How far a space, or a character without a glyph, moves the cursor.
+    pub space_width: u32,
+    /// The height of the tallest glyph.
+    pub height: u32,
+}
+
+impl Font {
+    /// The glyph for `c`. Fonts usually only have uppercase letters, which
+    /// lowercase ones fall back to, like vanilla.
+    pub fn glyph(&self, c: char) -> Option<&Patch> {
+        self.glyphs
+            .get(&c)
+            .or_else(|| self.glyphs.get(&c.to_ascii_uppercase()))
+    }
+
+    /// How far `c` moves the cursor.
+    pub fn advance(&self, c: char) -> u32 {
+        match self.glyph(c) {
+            Some(patch) if c != ' ' => patch.width,
+            _ => self.space_width,
+        }
+    }
+
+    /// The glyphs to draw for a line of text, with their x offsets from the
+    /// start of the line. Characters without a glyph are left as gaps.
+    pub fn layout(&self, text: &str) -> Vec<(u32, &Patch)> {
+        let mut x = 0;
+        let mut glyphs = Vec::new();
+        for c in text.chars() {
+            if let Some(patch) = self.glyph(c).filter(|_| c != ' ') {
+                glyphs.push((x, patch));
+            }
+            x += self.advance(c);
+        }
+        glyphs
+    }
+
+    /// The width of the widest line of `text`.
+    pub fn text_width(&self, text: &str) -> u32 {
+        text.lines()
+            .map(|line| line.chars().map(|c| self.advance(c)).sum())
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+impl Wad {
+    /// Collects a font from the lumps starting with `prefix`.
+    ///
+    /// `STCFN` lumps are numbered by character code. Other fonts, like
+    /// Heretic's `FONTA01`, count up from `!`, so 1 is character 33.
+    pub fn parse_font(&self, prefix: &str) -> Result<Font, WadError> {
+        let prefix = prefix.to_uppercase();
+        let first_char = match prefix.as_str() {
+            "STCFN" => 0,
+            _ => 32,
+        };
+
+        let mut glyphs = BTreeMap::new();
+        let lumps = self
+            .lumps_in_order
+            .iter()
+            .filter(|lump| lump.namespace == LumpNamespace::Global);
+        for lump in lumps {
+            let Some(number) = lump.name.strip_prefix(&prefix) else {
+                continue;
+            };
+            let Some(c) = number
+                .parse::<u32>()
+                .ok()
+                .and_then(|number| char::from_u32(first_char + number))
+            else {
+                continue;
+            };
+
+            glyphs.insert(c, self.parse_picture_or_png(lump, None)?);
+        }
+
+        if glyphs.is_empty() {
+            return Err(WadError::MissingLump(prefix));
+        }
+
+        // What vanilla moves the cursor by for a space.
+        let space_width = match prefix.as_str() {
+            "STCFN" => 4,
+            "FONTA" => 5,
+            "FONTB" => 8,
+            _ => glyphs.values().map(|patch| patch.width).max().unwrap_or(0) / 2,
+        };
+        let height = glyphs.values().map(|patch| patch.height).max().unwrap_or(0);
+
+        Ok(Font {
+            name: prefix,
+            glyphs,
+            space_width,
+            height,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lumps::screens::tests::tiny_picture, WadBuilder};
+
+    #[test]
+    fn parse_fonts() {
+        let mut builder = WadBuilder::new(false);
+        builder
+            .add_lump(&LumpNamespace::Global, "STCFN065", tiny_picture(3, &[1]))
+            .unwrap()
+            .add_lump(&LumpNamespace::Global, "STCFN066", tiny_picture(5, &[1, 2]))
+            .unwrap()
+            .add_lump(&LumpNamespace::Global, "FONTA01", tiny_picture(2, &[1]))
+            .unwrap()
+            .add_lump(&LumpNamespace::Global, "FONTA_S", vec![])
+            .unwrap();
+        let wad = builder.build().unwrap();
+
+        let font = wad.parse_font("STCFN").unwrap();
+        assert_eq!(font.glyphs.keys().collect::<Vec<_>>(), vec![&'A', &'B']);
+        assert_eq!(font.height, 2);
+        assert_eq!(font.text_width("ab A\nB"), 3 + 5 + 4 + 3);
+
+        let layout: Vec<_> = font.layout("A?B").iter().map(|(x, _)| *x).collect();
+        assert_eq!(layout, vec![0, 3 + 4]);
+
+        let font = wad.parse_font("FONTA").unwrap();
+        assert_eq!(font.glyphs.keys().collect::<Vec<_>>(), vec![&'!']);
+        assert_eq!(font.space_width, 5);
+
+        assert!(matches!(
+            wad.parse_font("FONTB"),
+            Err(WadError::MissingLump(_))
+        ));
+    }
+}
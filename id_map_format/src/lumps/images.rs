@@ -0,0 +1,327 @@
+use std::io::Write;
+
+use flate2::{write::ZlibEncoder, Compression};
+
+use crate::{Colormaps, Flat, Palette, Patch, Texture, Wad, WadError, PNG_SIGNATURE};
+
+/// The kinds of graphic [Wad::parse_indexed_image] can look up by name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageKind {
+    Texture,
+    Flat,
+    Patch,
+    Sprite,
+}
+
+/// Palette indices with transparency, for compositing graphics on the CPU.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexedImage {
+    pub width: u32,
+    pub height: u32,
+    /// Row-major. [None] is transparent.
+    pub pixels: Vec<Option<u8>>,
+}
+
+/// True-color pixels, ready to be written out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RgbaImage {
+    pub width: u32,
+    pub height: u32,
+    /// Row-major RGBA.
+    pub pixels: Vec<u8>,
+}
+
+impl IndexedImage {
+    /// A fully transparent image.
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![None; (width * height) as usize],
+        }
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> Option<u8> {
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, palette_index: u8) {
+        self.pixels[(y * self.width + x) as usize] = Some(palette_index);
+    }
+
+    /// Posts that run past the bottom of the patch wrap around to the top,
+    /// like the renderer's `PaletteImage::from_patch`.
+    pub fn from_patch(patch: &Patch) -> Self {
+        let mut image = Self::new(patch.width, patch.height);
+        if patch.height == 0 {
+            return image;
+        }
+
+        for (x, spans) in patch.columns.iter().enumerate() {
+            for span in spans {
+                for (i, palette_index) in span.palette_indices.iter().enumerate() {
+                    let y = (span.y_offset as u32 + i as u32) % patch.height;
+                    image.set(x as u32, y, *palette_index);
+                }
+            }
+        }
+
+        image
+    }
+
+    pub fn from_flat(flat: &Flat) -> Self {
+        Self {
+            width: flat.width,
+            height: flat.height,
+            pixels: flat.pixels.iter().copied().map(Some).collect(),
+        }
+    }
+
+    /// Draws the opaque pixels of `other` with its top left at `(x, y)`,
+    /// clipped to this image.
+    pub fn draw(&mut self, other: &IndexedImage, x: i32, y: i32) {
+        for other_y in 0..other.height {
+            for other_x in 0..other.width {
+                let Some(palette_index) = other.get(other_x, other_y) else {
+                    continue;
+                };
+
+                let (to_x, to_y) = (x + other_x as i32, y + other_y as i32);
+                if (0..self.width as i32).contains(&to_x) && (0..self.height as i32).contains(&to_y)
+                {
+                    self.set(to_x as u32, to_y as u32, palette_index);
+                }
+            }
+        }
+    }
+
+    /// Composites a texture from its patches in order, the same way the
+    /// renderer's `PaletteImageData` does. `patch` looks patches up by name,
+    /// so they can come from any WAD.
+    pub fn from_texture<E>(
+        texture: &Texture,
+        mut patch: impl FnMut(&str) -> Result<Patch, E>,
+    ) -> Result<Self, E> {
+        let mut image = Self::new(texture.width as u32, texture.height as u32);
+        for entry in &texture.patch_entry {
+            let patch = Self::from_patch(&patch(&entry.patch_name)?);
+            image.draw(&patch, entry.x_offset as i32, entry.y_offset as i32);
+        }
+
+        Ok(image)
+    }
+
+    /// Looks colors up in `palette`, after passing them through `colormap`,
+    /// one of the 256 byte light levels of a [Colormaps].
+    pub fn to_rgba(&self, palette: &Palette, colormap: Option<&[u8]>) -> RgbaImage {
+        let pixels = self
+            .pixels
+            .iter()
+            .flat_map(|pixel| match pixel {
+                Some(index) => {
+                    let index = colormap.map_or(*index, |colormap| colormap[*index as usize]);
+                    let (r, g, b) = palette[index as usize];
+                    [r, g, b, 0xFF]
+                }
+                None => [0, 0, 0, 0],
+            })
+            .collect();
+
+        RgbaImage {
+            width: self.width,
+            height: self.height,
+            pixels,
+        }
+    }
+}
+
+/// The 256 byte light level `level` of `colormaps`, where 0 is full
+/// brightness and 31 is darkest.
+pub fn colormap_level(colormaps: &Colormaps, level: usize) -> Option<&[u8]> {
+    colormaps.get(level * 256..(level + 1) * 256)
+}
+
+impl RgbaImage {
+    /// The palette as a 16x16 grid of `cell_size` pixel squares, in index
+    /// order.
+    pub fn from_palette(palette: &Palette, cell_size: u32) -> Self {
+        let size = 16 * cell_size;
+        let mut pixels = Vec::with_capacity((size * size * 4) as usize);
+        for y in 0..size {
+            for x in 0..size {
+                let (r, g, b) = palette[((y / cell_size) * 16 + x / cell_size) as usize];
+                pixels.extend([r, g, b, 0xFF]);
+            }
+        }
+
+        Self {
+            width: size,
+            height: size,
+            pixels,
+        }
+    }
+
+    /// Every light level of `colormaps` as a row, and every palette index as a
+    /// column.
+    pub fn from_colormaps(colormaps: &Colormaps, palette: &Palette) -> Self {
+        let pixels = colormaps
+            .iter()
+            .flat_map(|index| {
+                let (r, g, b) = palette[*index as usize];
+                [r, g, b, 0xFF]
+            })
+            .collect();
+
+        Self {
+            width: 256,
+            height: (colormaps.len() / 256) as u32,
+            pixels,
+        }
+    }
+
+    /// Encodes the image as an 8-bit RGBA PNG.
+    ///
+    /// The output only depends on the pixels, so it can be compared
+    /// byte-for-byte against golden files.
+    pub fn to_png(&self) -> Vec<u8> {
+        let mut png = PNG_SIGNATURE.to_vec();
+
+        let mut header = Vec::with_capacity(13);
+        header.extend(self.width.to_be_bytes());
+        header.extend(self.height.to_be_bytes());
+        // 8-bit RGBA, default compression and filtering, not interlaced.
+        header.extend([8, 6, 0, 0, 0]);
+        write_chunk(&mut png, b"IHDR", &header);
+
+        // Every row is unfiltered.
+        let row_len = (self.width * 4) as usize;
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        for row in self.pixels.chunks_exact(row_len.max(1)) {
+            encoder.write_all(&[0]).unwrap();
+            encoder.write_all(row).unwrap();
+        }
+        write_chunk(&mut png, b"IDAT", &encoder.finish().unwrap());
+        write_chunk(&mut png, b"IEND", &[]);
+
+        png
+    }
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend((data.len() as u32).to_be_bytes());
+    png.extend(kind);
+    png.extend(data);
+
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(kind);
+    crc.update(data);
+    png.extend(crc.finalize().to_be_bytes());
+}
+
+impl Wad {
+    /// Looks up a graphic by name and kind. Textures are composited from this
+    /// WAD's TEXTURE1/TEXTURE2 and patches.
+    pub fn parse_indexed_image(
+        &self,
+        kind: ImageKind,
+        name: &str,
+    ) -> Result<IndexedImage, WadError> {
+        match kind {
+            ImageKind::Texture => {
+                let textures = self.parse_textures(&self.parse_patch_names()?)?;
+                let texture = textures
+                    .get(&name.to_uppercase())
+                    .ok_or_else(|| WadError::TextureDoesNotExist(name.to_string()))?;
+                IndexedImage::from_texture(texture, |patch_name| self.parse_patch(patch_name))
+            }
+            ImageKind::Flat => Ok(IndexedImage::from_flat(&self.parse_flat(name)?)),
+            ImageKind::Patch => Ok(IndexedImage::from_patch(&self.parse_patch(name)?)),
+            ImageKind::Sprite => Ok(IndexedImage::from_patch(&self.parse_sprite(name)?)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        lumps::screens::tests::tiny_picture, LumpNamespace, PngImage, TexturePatchEntry, WadBuilder,
+    };
+
+    #[test]
+    fn compose_and_export() {
+        let texture = Texture {
+            name: "TEST".to_string(),
+            priority: true,
+            width: 4,
+            height: 2,
+            patch_entry: vec![
+                TexturePatchEntry {
+                    x_offset: 0,
+                    y_offset: 0,
+                    patch_name: "A".to_string(),
+                },
+                // Overlaps the first patch, and hangs off the right edge.
+                TexturePatchEntry {
+                    x_offset: 2,
+                    y_offset: 1,
+                    patch_name: "B".to_string(),
+                },
+            ],
+        };
+
+        let mut builder = WadBuilder::new(false);
+        builder
+            .add_lump(&LumpNamespace::Patch, "A", tiny_picture(3, &[1, 2]))
+            .unwrap()
+            .add_lump(&LumpNamespace::Patch, "B", tiny_picture(3, &[3]))
+            .unwrap();
+        let wad = builder.build().unwrap();
+
+        let image = IndexedImage::from_texture(&texture, |name| wad.parse_patch(name)).unwrap();
+        assert_eq!(
+            image.pixels,
+            vec![
+                Some(1),
+                Some(1),
+                Some(1),
+                None,
+                Some(2),
+                Some(2),
+                Some(3),
+                Some(3),
+            ]
+        );
+
+        let mut palette = [(0, 0, 0); 256];
+        palette[1] = (10, 0, 0);
+        palette[2] = (20, 0, 0);
+        palette[3] = (30, 0, 0);
+        // A colormap that darkens everything to index 1.
+        let colormaps: Colormaps = [vec![1u8; 256], vec![2; 256]].concat();
+
+        let rgba = image.to_rgba(&palette, None);
+        assert_eq!(&rgba.pixels[..4], &[10, 0, 0, 255]);
+        assert_eq!(&rgba.pixels[12..16], &[0, 0, 0, 0]);
+        let dark = image.to_rgba(&palette, colormap_level(&colormaps, 0));
+        assert_eq!(&dark.pixels[24..28], &[10, 0, 0, 255]);
+        assert!(colormap_level(&colormaps, 2).is_none());
+
+        // The PNG decodes back to the same pixels.
+        let png = rgba.to_png();
+        let decoded = PngImage::decode("TEST", &png).unwrap();
+        assert_eq!((decoded.width, decoded.height), (4, 2));
+        assert_eq!(decoded.rgba, rgba.pixels);
+        // CRC of an empty IEND chunk.
+        assert_eq!(&png[png.len() - 4..], &[0xAE, 0x42, 0x60, 0x82]);
+
+        let palette_image = RgbaImage::from_palette(&palette, 2);
+        assert_eq!((palette_image.width, palette_image.height), (32, 32));
+        assert_eq!(&palette_image.pixels[8..12], &[10, 0, 0, 255]);
+
+        assert!(matches!(
+            wad.parse_indexed_image(ImageKind::Patch, "b"),
+            Ok(IndexedImage { width: 3, .. })
+        ));
+    }
+}
@@ -0,0 +1,1060 @@
+use crate::{lump_from_namespace, LumpNamespace, Wad, WadError};
+
+/// Metadata for a single map, from UMAPINFO, MAPINFO, or the built-in tables.
+///
+/// Fields are `None` when the source doesn't set them, so entries can be
+/// layered with [MapInfo::merge].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LevelInfo {
+    /// The map marker, like `E1M1` or `MAP01`.
+    pub map: String,
+    pub level_name: Option<String>,
+    /// Shown in front of the level name on the automap, like `E1M1`.
+    pub label: Option<String>,
+    pub author: Option<String>,
+    pub music: Option<String>,
+    pub sky: Option<String>,
+    /// In seconds.
+    pub par_time: Option<u32>,
+    pub next: Option<String>,
+    pub next_secret: Option<String>,
+    /// The intermission patch with the level name.
+    pub level_pic: Option<String>,
+    /// Whether leaving this map ends the episode.
+    pub end_game: Option<bool>,
+}
+
+/// An entry in the episode menu, starting at `map`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Episode {
+    pub map: String,
+    pub name: String,
+    /// The menu graphic.
+    pub patch: Option<String>,
+    /// The menu shortcut key.
+    pub key: Option<char>,
+}
+
+/// [MapInfo] holds map metadata (names, music, skies, par times, and which map
+/// comes next), plus the episodes they're grouped in.
+///
+/// References:
+/// - https://doomwiki.org/wiki/UMAPINFO
+/// - https://zdoom.org/wiki/MAPINFO/Map_definition
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MapInfo {
+    /// In definition order.
+    pub levels: Vec<LevelInfo>,
+    pub episodes: Vec<Episode>,
+    /// Set by `episode = clear`: the episodes this is merged onto are dropped.
+    pub clear_episodes: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// Bare words and numbers.
+    Word(String),
+    /// Quoted strings.
+    String(String),
+    Equals,
+    Comma,
+    OpenBrace,
+    CloseBrace,
+}
+
+impl Token {
+    fn text(&self) -> Option<&str> {
+        match self {
+            Token::Word(text) | Token::String(text) => Some(text),
+            _ => None,
+        }
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<(Token, usize)>, WadError> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => line += 1,
+            c if c.is_whitespace() => {}
+            '/' if chars.peek() == Some(&'/') => {
+                while !matches!(chars.peek(), Some('\n') | None) {
+                    chars.next();
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                loop {
+                    match chars.next() {
+                        Some('/') if previous == '*' => break,
+                        Some(c) => {
+                            line += (c == '\n') as usize;
+                            previous = c;
+                        }
+                        None => return Err(syntax_error(line, "unterminated comment")),
+                    }
+                }
+            }
+            '=' => tokens.push((Token::Equals, line)),
+            ',' => tokens.push((Token::Comma, line)),
+            '{' => tokens.push((Token::OpenBrace, line)),
+            '}' => tokens.push((Token::CloseBrace, line)),
+            '"' => {
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('n') => string.push('\n'),
+                            Some(c) => string.push(c),
+                            None => return Err(syntax_error(line, "unterminated string")),
+                        },
+                        Some(c) => {
+                            line += (c == '\n') as usize;
+                            string.push(c);
+                        }
+                        None => return Err(syntax_error(line, "unterminated string")),
+                    }
+                }
+                tokens.push((Token::String(string), line));
+            }
+            c => {
+                let mut word = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || matches!(c, '=' | ',' | '{' | '}' | '"') {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push((Token::Word(word), line));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn syntax_error(line: usize, message: &str) -> WadError {
+    WadError::InvalidMapInfo(format!("line {}: {}", line, message))
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+}
+
+impl Parser {
+    fn new(text: &str) -> Result<Self, WadError> {
+        Ok(Self {
+            tokens: tokenize(text)?,
+            position: 0,
+        })
+    }
+
+    fn error(&self, message: &str) -> WadError {
+        let line = self
+            .tokens
+            .get(self.position)
+            .or(self.tokens.last())
+            .map_or(1, |(_, line)| *line);
+        syntax_error(line, message)
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(token, _)| token)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        self.position += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), WadError> {
+        match self.peek() {
+            Some(token) if *token == expected => {
+                self.position += 1;
+                Ok(())
+            }
+            _ => Err(self.error(&format!("expected {:?}", expected))),
+        }
+    }
+
+    /// A word or a string.
+    fn text(&mut self) -> Result<String, WadError> {
+        match self.peek().and_then(Token::text) {
+            Some(text) => {
+                let text = text.to_string();
+                self.position += 1;
+                Ok(text)
+            }
+            None => Err(self.error("expected a value")),
+        }
+    }
+
+    /// Reads `key [= value, value...]` properties until the closing brace.
+    fn block(&mut self) -> Result<Vec<(String, Vec<String>)>, WadError> {
+        self.expect(Token::OpenBrace)?;
+
+        let mut properties = Vec::new();
+        loop {
+            if self.peek() == Some(&Token::CloseBrace) {
+                self.position += 1;
+                return Ok(properties);
+            }
+
+            let key = match self.next() {
+                Some(Token::Word(key)) => key.to_ascii_lowercase(),
+                Some(_) => {
+                    self.position -= 1;
+                    return Err(self.error("expected a property name"));
+                }
+                None => return Err(self.error("unterminated block")),
+            };
+
+            // MAPINFO has bare flags, like `nointermission`.
+            let mut values = Vec::new();
+            if self.peek() == Some(&Token::Equals) {
+                self.position += 1;
+                values.push(self.text()?);
+                while self.peek() == Some(&Token::Comma) {
+                    self.position += 1;
+                    values.push(self.text()?);
+                }
+            }
+            properties.push((key, values));
+        }
+    }
+
+    /// Skips the rest of the previous token's line, stopping at a '{'.
+    fn skip_line(&mut self) {
+        let Some(&(_, line)) = self
+            .position
+            .checked_sub(1)
+            .and_then(|i| self.tokens.get(i))
+        else {
+            return;
+        };
+        while matches!(
+            self.tokens.get(self.position),
+            Some((token, token_line)) if *token_line == line && *token != Token::OpenBrace
+        ) {
+            self.position += 1;
+        }
+    }
+
+    /// Skips a block we don't care about, including nested blocks.
+    fn skip_block(&mut self) -> Result<(), WadError> {
+        self.expect(Token::OpenBrace)?;
+
+        let mut depth = 1;
+        while depth > 0 {
+            match self.next() {
+                Some(Token::OpenBrace) => depth += 1,
+                Some(Token::CloseBrace) => depth -= 1,
+                Some(_) => {}
+                None => return Err(self.error("unterminated block")),
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_bool(value: &str) -> bool {
+    value.eq_ignore_ascii_case("true")
+}
+
+impl MapInfo {
+    /// Parses a UMAPINFO lump.
+    pub fn parse_umapinfo(text: &str) -> Result<Self, WadError> {
+        let mut parser = Parser::new(text)?;
+        let mut map_info = Self::default();
+
+        while parser.peek().is_some() {
+            let keyword = parser.text()?;
+            if !keyword.eq_ignore_ascii_case("map") {
+                return Err(parser.error(&format!("expected MAP, found {:?}", keyword)));
+            }
+
+            let mut level = LevelInfo {
+                map: parser.text()?.to_uppercase(),
+                ..Default::default()
+            };
+
+            for (key, values) in parser.block()? {
+                let first = values.first().cloned();
+                let is_clear = first
+                    .as_deref()
+                    .is_some_and(|v| v.eq_ignore_ascii_case("clear"));
+
+                match key.as_str() {
+                    "levelname" => level.level_name = first,
+                    "label" if is_clear => level.label = Some(String::new()),
+                    "label" => level.label = first,
+                    "author" => level.author = first,
+                    "music" => level.music = first,
+                    "skytexture" => level.sky = first,
+                    "levelpic" => level.level_pic = first,
+                    "next" => level.next = first.map(|m| m.to_uppercase()),
+                    "nextsecret" => level.next_secret = first.map(|m| m.to_uppercase()),
+                    "partime" => {
+                        level.par_time = first.and_then(|p| p.parse().ok());
+                    }
+                    "endgame" | "endbunny" | "endcast" => {
+                        level.end_game = first.map(|v| parse_bool(&v));
+                    }
+                    "endpic" => level.end_game = Some(true),
+                    "episode" if is_clear => {
+                        map_info.episodes.clear();
+                        map_info.clear_episodes = true;
+                    }
+                    "episode" => {
+                        // `episode = "patch", "name", "key"`
+                        let [patch, name, rest @ ..] = &values[..] else {
+                            return Err(parser.error("episode needs a patch and a name"));
+                        };
+                        map_info.episodes.push(Episode {
+                            map: level.map.clone(),
+                            name: name.clone(),
+                            patch: Some(patch.clone()).filter(|p| !p.is_empty()),
+                            key: rest.first().and_then(|k| k.chars().next()),
+                        });
+                    }
+                    _ => {}
+                }
+            }
+
+            map_info.push_level(level);
+        }
+
+        Ok(map_info)
+    }
+
+    /// Parses the map and episode definitions of a ZDoom-style MAPINFO lump.
+    ///
+    /// Only the brace syntax is supported, not the older Hexen syntax.
+    /// Names given with `lookup` refer to LANGUAGE strings we don't have, so
+    /// they're left unset.
+    pub fn parse_mapinfo(text: &str) -> Result<Self, WadError> {
+        let mut parser = Parser::new(text)?;
+        let mut map_info = Self::default();
+
+        while parser.peek().is_some() {
+            let keyword = parser.text()?.to_ascii_lowercase();
+            match keyword.as_str() {
+                "map" => {
+                    let mut level = LevelInfo {
+                        map: parser.text()?.to_uppercase(),
+                        ..Default::default()
+                    };
+
+                    match parser.peek() {
+                        Some(Token::Word(word)) if word.eq_ignore_ascii_case("lookup") => {
+                            parser.position += 1;
+                            parser.text()?;
+                        }
+                        Some(Token::String(_)) | Some(Token::Word(_)) => {
+                            level.level_name = Some(parser.text()?);
+                        }
+                        _ => {}
+                    }
+
+                    if parser.peek() != Some(&Token::OpenBrace) {
+                        return Err(
+                            parser.error("expected '{' (Hexen-style MAPINFO isn't supported)")
+                        );
+                    }
+
+                    for (key, values) in parser.block()? {
+                        let first = values.first().cloned();
+                        match key.as_str() {
+                            "next" | "secretnext" => {
+                                let Some(next) = first else { continue };
+                                // `next = EndGame1`, `next = EndPic, "CREDIT"`, etc.
+                                if next.to_ascii_lowercase().starts_with("end") {
+                                    if key == "next" {
+                                        level.end_game = Some(true);
+                                    }
+                                    continue;
+                                }
+
+                                let next = Some(next.to_uppercase());
+                                if key == "next" {
+                                    level.next = next;
+                                } else {
+                                    level.next_secret = next;
+                                }
+                            }
+                            "sky1" => level.sky = first,
+                            "music" => level.music = first,
+                            "par" => level.par_time = first.and_then(|p| p.parse().ok()),
+                            "titlepatch" => level.level_pic = first,
+                            "author" => level.author = first,
+                            _ => {}
+                        }
+                    }
+
+                    map_info.push_level(level);
+                }
+                "episode" => {
+                    let map = parser.text()?.to_uppercase();
+                    let mut episode = Episode {
+                        map,
+                        name: String::new(),
+                        patch: None,
+                        key: None,
+                    };
+
+                    for (key, values) in parser.block()? {
+                        let first = values.first().cloned();
+                        match key.as_str() {
+                            "name" => episode.name = first.unwrap_or_default(),
+                            "picname" => episode.patch = first,
+                            "key" => episode.key = first.and_then(|k| k.chars().next()),
+                            _ => {}
+                        }
+                    }
+
+                    map_info.episodes.retain(|e| e.map != episode.map);
+                    map_info.episodes.push(episode);
+                }
+                "clearepisodes" => {
+                    map_info.episodes.clear();
+                    map_info.clear_episodes = true;
+                }
+                "include" => {
+                    parser.text()?;
+                }
+                // `defaultmap`, `gameinfo`, `cluster 5`, `skill easy`, ... and
+                // blockless ones like `clearskills`. A block may start on the
+                // next line, but arguments don't.
+                _ => {
+                    parser.skip_line();
+                    if parser.peek() == Some(&Token::OpenBrace) {
+                        parser.skip_block()?;
+                    }
+                }
+            }
+        }
+
+        Ok(map_info)
+    }
+
+    fn push_level(&mut self, level: LevelInfo) {
+        match self.levels.iter_mut().find(|l| l.map == level.map) {
+            Some(existing) => *existing = level,
+            None => self.levels.push(level),
+        }
+    }
+
+    pub fn level(&self, map: &str) -> Option<&LevelInfo> {
+        self.levels.iter().find(|l| l.map.eq_ignore_ascii_case(map))
+    }
+
+    /// Layers `other` on top: its levels override fields they set, and its
+    /// episodes are added.
+    pub fn merge(&mut self, other: &MapInfo) {
+        if other.clear_episodes {
+            self.episodes.clear();
+        }
+        for episode in &other.episodes {
+            self.episodes.retain(|e| e.map != episode.map);
+            self.episodes.push(episode.clone());
+        }
+
+        for level in &other.levels {
+            let Some(existing) = self.levels.iter_mut().find(|l| l.map == level.map) else {
+                self.levels.push(level.clone());
+                continue;
+            };
+
+            macro_rules! layer {
+                ($($field:ident),*) => {
+                    $(
+                        if level.$field.is_some() {
+                            existing.$field = level.$field.clone();
+                        }
+                    )*
+                };
+            }
+            layer!(
+                level_name,
+                label,
+                author,
+                music,
+                sky,
+                par_time,
+                next,
+                next_secret,
+                level_pic,
+                end_game
+            );
+        }
+    }
+
+    /// Map names in play order: each episode from its first map, following
+    /// `next`, then the secret maps reached from it. Maps that can't be
+    /// reached come last, in definition order.
+    pub fn map_order(&self) -> Vec<String> {
+        let mut order: Vec<String> = Vec::new();
+
+        let starts = match self.episodes.is_empty() {
+            true => self
+                .levels
+                .first()
+                .map(|l| l.map.clone())
+                .into_iter()
+                .collect(),
+            false => self
+                .episodes
+                .iter()
+                .map(|e| e.map.clone())
+                .collect::<Vec<_>>(),
+        };
+
+        for start in starts {
+            let mut pending = vec![start];
+            let mut pending_idx = 0;
+            while let Some(start) = pending.get(pending_idx).cloned() {
+                pending_idx += 1;
+
+                let mut current = Some(start);
+                while let Some(map) = current {
+                    if order.contains(&map) {
+                        break;
+                    }
+                    let Some(level) = self.level(&map) else {
+                        break;
+                    };
+
+                    order.push(map);
+                    pending.extend(level.next_secret.clone());
+                    current = match level.end_game {
+                        Some(true) => None,
+                        _ => level.next.clone(),
+                    };
+                }
+            }
+        }
+
+        for level in &self.levels {
+            if !order.contains(&level.map) {
+                order.push(level.map.clone());
+            }
+        }
+
+        order
+    }
+
+    /// Drops levels and episodes for maps the WAD doesn't have, like the
+    /// later episodes of the shareware IWAD.
+    pub fn retain_maps(&mut self, map_names: &[String]) {
+        self.levels.retain(|l| map_names.contains(&l.map));
+        self.episodes.retain(|e| map_names.contains(&e.map));
+    }
+
+    /// The built-in tables for an IWAD, guessed from its map names: `MAPxx`
+    /// is DOOM II, and `ExMy` is Heretic when there's an ENDTEXT lump,
+    /// otherwise DOOM.
+    pub fn builtin(wad: &Wad) -> Self {
+        let map_names = wad.map_names();
+
+        let mut map_info = if map_names.iter().any(|m| m.starts_with("MAP")) {
+            Self::doom2()
+        } else if lump_from_namespace(&LumpNamespace::Global, "ENDTEXT", wad).is_ok() {
+            Self::heretic()
+        } else {
+            Self::doom()
+        };
+
+        map_info.retain_maps(&map_names);
+        map_info
+    }
+
+    /// DOOM and The Ultimate DOOM.
+    pub fn doom() -> Self {
+        let mut levels = Vec::new();
+        for (e, names) in (1..).zip(DOOM_LEVEL_NAMES) {
+            for (m, name) in (1..).zip(names) {
+                let music = match e {
+                    4 => DOOM_E4_MUSIC[m - 1].to_string(),
+                    _ => format!("D_E{}M{}", e, m),
+                };
+                let par_time = DOOM_PAR_TIMES.get(e - 1).map(|pars| pars[m - 1]);
+                let (next, next_secret) = episodic_next(e, m, DOOM_SECRET_EXITS[e - 1]);
+
+                levels.push(LevelInfo {
+                    map: format!("E{}M{}", e, m),
+                    level_name: Some(name.to_string()),
+                    label: Some(format!("E{}M{}", e, m)),
+                    music: Some(music),
+                    sky: Some(format!("SKY{}", e)),
+                    par_time,
+                    level_pic: Some(format!("WILV{}{}", e - 1, m - 1)),
+                    end_game: Some(m == 8),
+                    next,
+                    next_secret,
+                    ..Default::default()
+                });
+            }
+        }
+
+        let episodes = [
+            ("Knee-Deep in the Dead", 'k'),
+            ("The Shores of Hell", 't'),
+            ("Inferno", 'i'),
+            ("Thy Flesh Consumed", 't'),
+        ]
+        .into_iter()
+        .zip(1..)
+        .map(|((name, key), e)| Episode {
+            map: format!("E{}M1", e),
+            name: name.to_string(),
+            patch: Some(format!("M_EPI{}", e)),
+            key: Some(key),
+        })
+        .collect();
+
+        Self {
+            levels,
+            episodes,
+            clear_episodes: false,
+        }
+    }
+
+    /// DOOM II, which is also what Final DOOM's map slots start from.
+    pub fn doom2() -> Self {
+        let levels = (1..)
+            .zip(DOOM2_LEVEL_NAMES)
+            .map(|(n, name)| {
+                let next = match n {
+                    30 => None,
+                    31 | 32 => Some(16),
+                    n => Some(n + 1),
+                };
+                let next_secret = match n {
+                    15 => Some(31),
+                    31 => Some(32),
+                    _ => None,
+                };
+
+                LevelInfo {
+                    map: format!("MAP{:02}", n),
+                    level_name: Some(name.to_string()),
+                    label: Some(format!("MAP{:02}", n)),
+                    music: Some(DOOM2_MUSIC[n - 1].to_string()),
+                    sky: Some(
+                        match n {
+                            1..=11 => "SKY1",
+                            12..=20 => "SKY2",
+                            _ => "SKY3",
+                        }
+                        .to_string(),
+                    ),
+                    par_time: Some(DOOM2_PAR_TIMES[n - 1]),
+                    level_pic: Some(format!("CWILV{:02}", n - 1)),
+                    end_game: Some(n == 30),
+                    next: next.map(|n| format!("MAP{:02}", n)),
+                    next_secret: next_secret.map(|n| format!("MAP{:02}", n)),
+                    ..Default::default()
+                }
+            })
+            .collect();
+
+        Self {
+            levels,
+            episodes: vec![Episode {
+                map: "MAP01".to_string(),
+                name: "Hell on Earth".to_string(),
+                patch: None,
+                key: Some('h'),
+            }],
+            clear_episodes: false,
+        }
+    }
+
+    /// Heretic and Heretic: Shadow of the Serpent Riders.
+    pub fn heretic() -> Self {
+        let mut levels = Vec::new();
+        for (e, names) in (1..).zip(HERETIC_LEVEL_NAMES) {
+            for (m, name) in (1..).zip(names) {
+                let (next, next_secret) = episodic_next(e, m, HERETIC_SECRET_EXITS[e - 1]);
+
+                levels.push(LevelInfo {
+                    map: format!("E{}M{}", e, m),
+                    level_name: Some(name.to_string()),
+                    label: Some(format!("E{}M{}", e, m)),
+                    // The expansion episodes reuse music from the first three.
+                    music: (e <= 3).then(|| format!("MUS_E{}M{}", e, m)),
+                    sky: Some(HERETIC_SKIES[e - 1].to_string()),
+                    end_game: Some(m == 8),
+                    next,
+                    next_secret,
+                    ..Default::default()
+                });
+            }
+        }
+
+        let episodes = [
+            ("City of the Damned", 'c'),
+            ("Hell's Maw", 'h'),
+            ("The Dome of D'Sparil", 'd'),
+            ("The Ossuary", 'o'),
+            ("The Stagnant Demesne", 's'),
+        ]
+        .into_iter()
+        .zip(1..)
+        .map(|((name, key), e)| Episode {
+            map: format!("E{}M1", e),
+            name: name.to_string(),
+            patch: None,
+            key: Some(key),
+        })
+        .collect();
+
+        Self {
+            levels,
+            episodes,
+            clear_episodes: false,
+        }
+    }
+}
+
+/// `ExMy` episodes are 8 maps and a secret 9th, which is reached from
+/// `secret_exit.0` and returns to `secret_exit.1`.
+fn episodic_next(
+    episode: usize,
+    map: usize,
+    secret_exit: (usize, usize),
+) -> (Option<String>, Option<String>) {
+    let name = |m: usize| Some(format!("E{}M{}", episode, m));
+    let next = match map {
+        8 => None,
+        9 => name(secret_exit.1),
+        m => name(m + 1),
+    };
+    let next_secret = if map == secret_exit.0 { name(9) } else { None };
+    (next, next_secret)
+}
+
+const DOOM_LEVEL_NAMES: [[&str; 9]; 4] = [
+    [
+        "Hangar",
+        "Nuclear Plant",
+        "Toxin Refinery",
+        "Command Control",
+        "Phobos Lab",
+        "Central Processing",
+        "Computer Station",
+        "Phobos Anomaly",
+        "Military Base",
+    ],
+    [
+        "Deimos Anomaly",
+        "Containment Area",
+        "Refinery",
+        "Deimos Lab",
+        "Command Center",
+        "Halls of the Damned",
+        "Spawning Vats",
+        "Tower of Babel",
+        "Fortress of Mystery",
+    ],
+    [
+        "Hell Keep",
+        "Slough of Despair",
+        "Pandemonium",
+        "House of Pain",
+        "Unholy Cathedral",
+        "Mt. Erebus",
+        "Limbo",
+        "Dis",
+        "Warrens",
+    ],
+    [
+        "Hell Beneath",
+        "Perfect Hatred",
+        "Sever the Wicked",
+        "Unruly Evil",
+        "They Will Repent",
+        "Against Thee Wickedly",
+        "And Hell Followed",
+        "Unto the Cruel",
+        "Fear",
+    ],
+];
+
+/// Thy Flesh Consumed has no music of its own.
+const DOOM_E4_MUSIC: [&str; 9] = [
+    "D_E3M4", "D_E3M2", "D_E3M3", "D_E1M5", "D_E2M7", "D_E2M4", "D_E2M6", "D_E2M5", "D_E1M9",
+];
+
+/// Thy Flesh Consumed has no par times.
+const DOOM_PAR_TIMES: [[u32; 9]; 3] = [
+    [30, 75, 120, 90, 165, 180, 180, 30, 165],
+    [90, 90, 90, 120, 90, 360, 240, 30, 170],
+    [90, 45, 90, 150, 90, 90, 165, 30, 135],
+];
+
+/// (map with the secret exit, map the secret level returns to)
+const DOOM_SECRET_EXITS: [(usize, usize); 4] = [(3, 4), (5, 6), (6, 7), (2, 3)];
+
+const DOOM2_LEVEL_NAMES: [&str; 32] = [
+    "Entryway",
+    "Underhalls",
+    "The Gantlet",
+    "The Focus",
+    "The Waste Tunnels",
+    "The Crusher",
+    "Dead Simple",
+    "Tricks and Traps",
+    "The Pit",
+    "Refueling Base",
+    "'O' of Destruction!",
+    "The Factory",
+    "Downtown",
+    "The Inmost Dens",
+    "Industrial Zone",
+    "Suburbs",
+    "Tenements",
+    "The Courtyard",
+    "The Citadel",
+    "Gotcha!",
+    "Nirvana",
+    "The Catacombs",
+    "Barrels o' Fun",
+    "The Chasm",
+    "Bloodfalls",
+    "The Abandoned Mines",
+    "Monster Condo",
+    "The Spirit World",
+    "The Living End",
+    "Icon of Sin",
+    "Wolfenstein",
+    "Grosse",
+];
+
+const DOOM2_MUSIC: [&str; 32] = [
+    "D_RUNNIN", "D_STALKS", "D_COUNTD", "D_BETWEE", "D_DOOM", "D_THE_DA", "D_SHAWN", "D_DDTBLU",
+    "D_IN_CIT", "D_DEAD", "D_STLKS2", "D_THEDA2", "D_DOOM2", "D_DDTBL2", "D_RUNNI2", "D_DEAD2",
+    "D_STLKS3", "D_ROMERO", "D_SHAWN2", "D_MESSAG", "D_COUNT2", "D_DDTBL3", "D_AMPIE", "D_THEDA3",
+    "D_ADRIAN", "D_MESSG2", "D_ROMER2", "D_TENSE", "D_SHAWN3", "D_OPENIN", "D_EVIL", "D_ULTIMA",
+];
+
+const DOOM2_PAR_TIMES: [u32; 32] = [
+    30, 90, 120, 120, 90, 150, 120, 120, 270, 90, 210, 150, 150, 150, 210, 150, 420, 150, 210, 150,
+    240, 150, 180, 150, 150, 300, 330, 420, 300, 180, 120, 30,
+];
+
+const HERETIC_LEVEL_NAMES: [[&str; 9]; 5] = [
+    [
+        "The Docks",
+        "The Dungeons",
+        "The Gatehouse",
+        "The Guard Tower",
+        "The Citadel",
+        "The Cathedral",
+        "The Crypts",
+        "Hell's Maw",
+        "The Graveyard",
+    ],
+    [
+        "The Crater",
+        "The Lava Pits",
+        "The River of Fire",
+        "The Ice Grotto",
+        "The Catacombs",
+        "The Labyrinth",
+        "The Great Hall",
+        "The Portals of Chaos",
+        "The Glacier",
+    ],
+    [
+        "The Storehouse",
+        "The Cesspool",
+        "The Confluence",
+        "The Azure Fortress",
+        "The Ophidian Lair",
+        "The Halls of Fear",
+        "The Chasm",
+        "D'Sparil's Keep",
+        "The Aquifer",
+    ],
+    [
+        "Catafalque",
+        "Blockhouse",
+        "Ambulatory",
+        "Sepulcher",
+        "Great Stair",
+        "Halls of the Apostate",
+        "Ramparts of Perdition",
+        "Shattered Bridge",
+        "Mausoleum",
+    ],
+    [
+        "Ochre Cliffs",
+        "Rapids",
+        "Quay",
+        "Courtyard",
+        "Hydratyr",
+        "Colonnade",
+        "Foetid Manse",
+        "Field of Judgement",
+        "Skein of D'Sparil",
+    ],
+];
+
+const HERETIC_SKIES: [&str; 5] = ["SKY1", "SKY2", "SKY3", "SKY1", "SKY3"];
+
+/// (map with the secret exit, map the secret level returns to)
+const HERETIC_SECRET_EXITS: [(usize, usize); 5] = [(6, 7), (4, 5), (4, 5), (4, 5), (3, 4)];
+
+impl Wad {
+    /// Parses the WAD's own UMAPINFO, or its MAPINFO/ZMAPINFO when it has no
+    /// UMAPINFO. Returns `None` when it has neither.
+    ///
+    /// To get metadata for IWAD maps, merge this onto [MapInfo::builtin].
+    pub fn parse_map_info(&self) -> Result<Option<MapInfo>, WadError> {
+        let text = |name: &str| {
+            lump_from_namespace(&LumpNamespace::Global, name, self)
+                .ok()
+                .map(|lump| lump.bytes().iter().map(|&b| b as char).collect::<String>())
+        };
+
+        if let Some(text) = text("UMAPINFO") {
+            return MapInfo::parse_umapinfo(&text).map(Some);
+        }
+        if let Some(text) = text("ZMAPINFO").or_else(|| text("MAPINFO")) {
+            return MapInfo::parse_mapinfo(&text).map(Some);
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_tables() {
+        let doom = MapInfo::doom();
+        assert_eq!(doom.levels.len(), 36);
+        let e1m3 = doom.level("E1M3").unwrap();
+        assert_eq!(e1m3.level_name.as_deref(), Some("Toxin Refinery"));
+        assert_eq!(e1m3.next_secret.as_deref(), Some("E1M9"));
+        assert_eq!(doom.level("E1M9").unwrap().next.as_deref(), Some("E1M4"));
+        assert_eq!(doom.level("E4M1").unwrap().music.as_deref(), Some("D_E3M4"));
+
+        let order = doom.map_order();
+        assert_eq!(order.len(), 36);
+        assert_eq!(
+            &order[..10],
+            ["E1M1", "E1M2", "E1M3", "E1M4", "E1M5", "E1M6", "E1M7", "E1M8", "E1M9", "E2M1"]
+        );
+
+        let doom2 = MapInfo::doom2();
+        assert_eq!(doom2.level("MAP30").unwrap().par_time, Some(180));
+        let order = doom2.map_order();
+        assert_eq!(order.len(), 32);
+        assert_eq!(&order[29..], ["MAP30", "MAP31", "MAP32"]);
+
+        assert_eq!(MapInfo::heretic().levels.len(), 45);
+    }
+
+    #[test]
+    fn parse_umapinfo() {
+        let map_info = MapInfo::parse_umapinfo(
+            r#"
+            // Comment
+            MAP MAP01
+            {
+                levelname = "Outpost"
+                author = "Someone"
+                music = "D_STALKS"
+                partime = 45
+                next = "map05"
+                episode = "M_EPI1", "Frontier", "f"
+                intertext = "Line one",
+                            "Line two"
+                bossaction = Fatso, 23, 666
+            }
+
+            MAP MAP05 { endgame = true }
+            "#,
+        )
+        .unwrap();
+
+        let map01 = map_info.level("MAP01").unwrap();
+        assert_eq!(map01.level_name.as_deref(), Some("Outpost"));
+        assert_eq!(map01.par_time, Some(45));
+        assert_eq!(map01.next.as_deref(), Some("MAP05"));
+        assert_eq!(
+            map_info.episodes,
+            vec![Episode {
+                map: "MAP01".to_string(),
+                name: "Frontier".to_string(),
+                patch: Some("M_EPI1".to_string()),
+                key: Some('f'),
+            }]
+        );
+
+        // Merged onto DOOM II, unset fields come from the built-in table.
+        let mut merged = MapInfo::doom2();
+        merged.merge(&map_info);
+        let map01 = merged.level("MAP01").unwrap();
+        assert_eq!(map01.level_name.as_deref(), Some("Outpost"));
+        assert_eq!(map01.sky.as_deref(), Some("SKY1"));
+        assert_eq!(merged.episodes, map_info.episodes);
+        assert_eq!(&merged.map_order()[..3], ["MAP01", "MAP05", "MAP02"]);
+
+        assert!(MapInfo::parse_umapinfo("MAP MAP01 { levelname = }").is_err());
+        assert!(MapInfo::parse_umapinfo("MAP MAP01 { levelname = \"x\"").is_err());
+    }
+
+    #[test]
+    fn parse_mapinfo() {
+        let map_info = MapInfo::parse_mapinfo(
+            r#"
+            include "other.txt"
+            clearepisodes
+            episode e1m1 { name = "Shores" key = "s" }
+
+            defaultmap { sky1 = "SKY3", 0 nointermission }
+            map E1M1 "Docks" { next = "E1M2" secretnext = "E1M9" sky1 = "SKY2", 0 par = 30 }
+            map E1M2 lookup "HUSTR_E1M2" { next = EndGame1 }
+            cluster 1 { exittext = "Bye" }
+            clearskills
+            map E1M3 { next = "E1M4" }
+            skill baby
+            {
+                spawnfilter = 1
+            }
+            map E1M4 { }
+            "#,
+        )
+        .unwrap();
+
+        assert!(map_info.clear_episodes);
+        assert_eq!(map_info.episodes[0].map, "E1M1");
+        let e1m1 = map_info.level("E1M1").unwrap();
+        assert_eq!(e1m1.level_name.as_deref(), Some("Docks"));
+        assert_eq!(e1m1.next_secret.as_deref(), Some("E1M9"));
+        assert_eq!(e1m1.sky.as_deref(), Some("SKY2"));
+        let e1m2 = map_info.level("E1M2").unwrap();
+        assert_eq!(e1m2.level_name, None);
+        assert_eq!(e1m2.end_game, Some(true));
+        // Neither is swallowed by the keyword before it.
+        assert_eq!(
+            map_info.level("E1M3").unwrap().next.as_deref(),
+            Some("E1M4")
+        );
+        assert!(map_info.level("E1M4").is_some());
+
+        assert!(MapInfo::parse_mapinfo("map MAP01 \"Hexen style\"\nnext MAP02").is_err());
+    }
+}
@@ -1,6 +1,11 @@
-use crate::{helpers::parse_bytes_cstr, lump_from_namespace, LumpNamespace, Wad, WadError};
-
-#[derive(Debug)]
+use crate::{
+    helpers::{parse_name_at, write_bytes_cstr},
+    lump_from_namespace, parse_blockmap, parse_extended_nodes, parse_nodes, parse_reject,
+    parse_segs, parse_subsectors, parse_textmap, write_blockmap, Blockmap, LumpNamespace, Node,
+    NodeFormat, NodeVertex, Reject, Seg, Subsector, UdmfData, Wad, WadBuilder, WadError,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Thing {
     pub x: i16,
     pub y: i16,
@@ -11,7 +16,7 @@ pub struct Thing {
     pub spawn_flags: u16,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Sector {
     pub floor_height: i16,
     pub ceiling_height: i16,
@@ -25,7 +30,7 @@ pub struct Sector {
     pub sector_tag: u16,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Sidedef {
     pub x_offset: i16,
     pub y_offset: i16,
@@ -37,7 +42,7 @@ pub struct Sidedef {
     pub sector_idx: u16,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Linedef {
     pub start_vertex_idx: u16,
     pub end_vertex_idx: u16,
@@ -58,7 +63,7 @@ pub struct Vertex {
     pub y: i16,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Map {
     pub name: String,
 
@@ -75,6 +80,43 @@ pub struct Map {
     pub linedefs: Vec<Linedef>,
     /// Vertices are just points in X,Y space.
     pub vertices: Vec<Vertex>,
+
+    /// BSP data, if the map has been run through a node builder.
+    ///
+    /// Segs are pieces of linedefs, split along partition lines.
+    pub segs: Option<Vec<Seg>>,
+    /// Subsectors are convex runs of segs.
+    pub subsectors: Option<Vec<Subsector>>,
+    /// Nodes form a binary tree over the subsectors; the last node is the root.
+    pub nodes: Option<Vec<Node>>,
+    /// Extra vertices from extended node formats, indexed after `vertices`.
+    pub node_vertices: Vec<NodeVertex>,
+    pub node_format: NodeFormat,
+
+    /// Grid of linedefs used for collision detection.
+    ///
+    /// See [Blockmap::generate] for maps that don't have one.
+    pub blockmap: Option<Blockmap>,
+
+    /// Which sectors can't see each other.
+    ///
+    /// If the map's REJECT lump is missing or too short, every sector can see
+    /// every other sector.
+    pub reject: Reject,
+
+    /// Everything from a UDMF map's TEXTMAP that doesn't fit the fields above.
+    ///
+    /// This is [None] for binary maps.
+    pub udmf: Option<UdmfData>,
+}
+
+/// The parts of a map shared by the binary and UDMF formats.
+pub(crate) struct MapGeometry {
+    pub things: Vec<Thing>,
+    pub sectors: Vec<Sector>,
+    pub sidedefs: Vec<Sidedef>,
+    pub linedefs: Vec<Linedef>,
+    pub vertices: Vec<Vertex>,
 }
 
 impl Wad {
@@ -84,14 +126,113 @@ impl Wad {
             return Err(WadError::MapDoesNotExist(map_name.to_string()));
         }
 
+        // UDMF maps keep their geometry in TEXTMAP, instead of the binary lumps.
+        let (geometry, udmf) = match lump_from_namespace(&namespace, "TEXTMAP", self) {
+            Ok(lump) => {
+                let (geometry, udmf) = parse_textmap(lump)?;
+                (geometry, Some(udmf))
+            }
+            Err(_) => (self.parse_binary_map_geometry(&namespace)?, None),
+        };
+        let MapGeometry {
+            things,
+            sectors,
+            sidedefs,
+            linedefs,
+            vertices,
+        } = geometry;
+
+        // Parse SEGS, SSECTORS and NODES.
+        //
+        // These are optional, and an empty lump is treated the same as a
+        // missing one.
+        let optional_lump = |lump_name: &str| {
+            lump_from_namespace(&namespace, lump_name, self)
+                .ok()
+                .filter(|lump| lump.size > 0)
+        };
+
+        // ZDoom's extended nodes are stored entirely in NODES, or in SSECTORS
+        // for GL nodes. UDMF maps always use ZNODES.
+        let extended_lump_names: &[&str] = match udmf {
+            Some(_) => &["ZNODES"],
+            None => &["NODES", "SSECTORS"],
+        };
+
+        let mut extended_nodes = None;
+        for lump_name in extended_lump_names {
+            if let Some(lump) = optional_lump(lump_name) {
+                extended_nodes = parse_extended_nodes(lump, &vertices, &linedefs)?;
+                if extended_nodes.is_some() {
+                    break;
+                }
+            }
+        }
+
+        let (segs, subsectors, nodes, node_vertices, node_format) = match extended_nodes {
+            Some(extended) => (
+                Some(extended.segs),
+                Some(extended.subsectors),
+                Some(extended.nodes),
+                extended.node_vertices,
+                extended.format,
+            ),
+            None => {
+                let subsectors = optional_lump("SSECTORS")
+                    .map(parse_subsectors)
+                    .transpose()?;
+                // A map that's a single subsector has no nodes at all.
+                let nodes = match optional_lump("NODES") {
+                    Some(lump) => Some(parse_nodes(lump)?),
+                    None => subsectors.as_ref().map(|_| vec![]),
+                };
+                (
+                    optional_lump("SEGS").map(parse_segs).transpose()?,
+                    subsectors,
+                    nodes,
+                    vec![],
+                    NodeFormat::Vanilla,
+                )
+            }
+        };
+
+        // Parse BLOCKMAP.
+        let blockmap = optional_lump("BLOCKMAP").map(parse_blockmap).transpose()?;
+
+        // Parse REJECT.
+        let reject = parse_reject(optional_lump("REJECT"), sectors.len());
+
+        Ok(Map {
+            name: map_name.to_string().clone(),
+            things,
+            sectors,
+            sidedefs,
+            linedefs,
+            vertices,
+            segs,
+            subsectors,
+            nodes,
+            node_vertices,
+            node_format,
+            blockmap,
+            reject,
+            udmf,
+        })
+    }
+
+    /// Parses THINGS, LINEDEFS, SIDEDEFS, VERTEXES and SECTORS.
+    fn parse_binary_map_geometry(
+        &self,
+        namespace: &LumpNamespace,
+    ) -> Result<MapGeometry, WadError> {
         // Parse THINGS.
         let mut things: Vec<Thing> = Vec::new();
         {
-            let lump = lump_from_namespace(&namespace, "THINGS", self)?;
+            let lump = lump_from_namespace(namespace, "THINGS", self)?;
             let lump_bytes = lump.bytes();
 
             if lump.size % 10 != 0 {
-                return Err(WadError::CorruptedLump(lump.name.clone()));
+                return Err(WadError::corrupted(&lump.name, lump.size - lump.size % 10));
             }
 
             let num_things = lump.size / 10;
@@ -122,11 +263,11 @@ impl Wad {
         // Parse SECTORS.
         let mut sectors: Vec<Sector> = Vec::new();
         {
-            let lump = lump_from_namespace(&namespace, "SECTORS", self)?;
+            let lump = lump_from_namespace(namespace, "SECTORS", self)?;
             let lump_bytes = lump.bytes();
 
             if lump.size % 26 != 0 {
-                return Err(WadError::CorruptedLump(lump.name.clone()));
+                return Err(WadError::corrupted(&lump.name, lump.size - lump.size % 26));
             }
 
             let num_sectors = lump.size / 26;
@@ -139,21 +280,9 @@ impl Wad {
                 let floor_height = i16_le!(&sector_bytes[0..2]);
                 let ceiling_height = i16_le!(&sector_bytes[2..4]);
 
-                let floor_flat = match parse_bytes_cstr(&sector_bytes[4..12]) {
-                    Ok(floor_flat) => match floor_flat.to_str() {
-                        Ok(floor_flat) => floor_flat.to_string(),
-                        Err(_) => return Err(WadError::CorruptedString),
-                    },
-                    Err(_) => return Err(WadError::CorruptedBytes),
-                };
+                let floor_flat = parse_name_at(&lump.name, lump_bytes, sector_offset + 4)?;
 
-                let ceiling_flat = match parse_bytes_cstr(&sector_bytes[12..20]) {
-                    Ok(ceiling_flat) => match ceiling_flat.to_str() {
-                        Ok(ceiling_flat) => ceiling_flat.to_string(),
-                        Err(_) => return Err(WadError::CorruptedString),
-                    },
-                    Err(_) => return Err(WadError::CorruptedBytes),
-                };
+                let ceiling_flat = parse_name_at(&lump.name, lump_bytes, sector_offset + 12)?;
 
                 let light_level = i16_le!(&sector_bytes[20..22]);
 
@@ -175,11 +304,11 @@ impl Wad {
         // Parse SIDEDEFS.
         let mut sidedefs: Vec<Sidedef> = Vec::new();
         {
-            let lump = lump_from_namespace(&namespace, "SIDEDEFS", self)?;
+            let lump = lump_from_namespace(namespace, "SIDEDEFS", self)?;
             let lump_bytes = lump.bytes();
 
             if lump.size % 30 != 0 {
-                return Err(WadError::CorruptedLump(lump.name.clone()));
+                return Err(WadError::corrupted(&lump.name, lump.size - lump.size % 30));
             }
 
             let num_sidedefs = lump.size / 30;
@@ -192,29 +321,11 @@ impl Wad {
                 let x_offset = i16_le!(&sidedef_bytes[0..2]);
                 let y_offset = i16_le!(&sidedef_bytes[2..4]);
 
-                let upper_texture = match parse_bytes_cstr(&sidedef_bytes[4..12]) {
-                    Ok(upper_texture) => match upper_texture.to_str() {
-                        Ok(upper_texture) => upper_texture.to_string(),
-                        Err(_) => return Err(WadError::CorruptedString),
-                    },
-                    Err(_) => return Err(WadError::CorruptedBytes),
-                };
+                let upper_texture = parse_name_at(&lump.name, lump_bytes, sidedef_offset + 4)?;
 
-                let lower_texture = match parse_bytes_cstr(&sidedef_bytes[12..20]) {
-                    Ok(lower_texture) => match lower_texture.to_str() {
-                        Ok(lower_texture) => lower_texture.to_string(),
-                        Err(_) => return Err(WadError::CorruptedString),
-                    },
-                    Err(_) => return Err(WadError::CorruptedBytes),
-                };
+                let lower_texture = parse_name_at(&lump.name, lump_bytes, sidedef_offset + 12)?;
 
-                let middle_texture = match parse_bytes_cstr(&sidedef_bytes[20..28]) {
-                    Ok(middle_texture) => match middle_texture.to_str() {
-                        Ok(middle_texture) => middle_texture.to_string(),
-                        Err(_) => return Err(WadError::CorruptedString),
-                    },
-                    Err(_) => return Err(WadError::CorruptedBytes),
-                };
+                let middle_texture = parse_name_at(&lump.name, lump_bytes, sidedef_offset + 20)?;
 
                 let sector_idx = u16_le!(&sidedef_bytes[28..30]);
 
@@ -232,11 +343,11 @@ impl Wad {
         // Parse LINEDEFS.
         let mut linedefs: Vec<Linedef> = Vec::new();
         {
-            let lump = lump_from_namespace(&namespace, "LINEDEFS", self)?;
+            let lump = lump_from_namespace(namespace, "LINEDEFS", self)?;
             let lump_bytes = lump.bytes();
 
             if lump.size % 14 != 0 {
-                return Err(WadError::CorruptedLump(lump.name.clone()));
+                return Err(WadError::corrupted(&lump.name, lump.size - lump.size % 14));
             }
 
             let num_linedefs = lump.size / 14;
@@ -278,11 +389,11 @@ impl Wad {
         // Parse VERTEXES.
         let mut vertices: Vec<Vertex> = Vec::new();
         {
-            let lump = lump_from_namespace(&namespace, "VERTEXES", self)?;
+            let lump = lump_from_namespace(namespace, "VERTEXES", self)?;
             let lump_bytes = lump.bytes();
 
             if lump.size % 4 != 0 {
-                return Err(WadError::CorruptedLump(lump.name.clone()));
+                return Err(WadError::corrupted(&lump.name, lump.size - lump.size % 4));
             }
 
             let num_vertices = lump.size / 4;
@@ -299,8 +410,7 @@ impl Wad {
             }
         }
 
-        Ok(Map {
-            name: map_name.to_string().clone(),
+        Ok(MapGeometry {
             things,
             sectors,
             sidedefs,
@@ -309,3 +419,243 @@ impl Wad {
         })
     }
 }
+
+impl Map {
+    /// Encodes the map back into its lumps, in vanilla's order: THINGS,
+    /// LINEDEFS, SIDEDEFS, VERTEXES, SEGS, SSECTORS, NODES, SECTORS, REJECT
+    /// and BLOCKMAP. Lumps the map doesn't have are written empty.
+    ///
+    /// This is the inverse of [Wad::parse_map].
+    pub fn to_lumps(&self) -> Result<Vec<(String, Vec<u8>)>, WadError> {
+        if let Some(udmf) = &self.udmf {
+            return self.udmf_lumps(udmf);
+        }
+
+        // Encode THINGS.
+        let mut things_bytes: Vec<u8> = Vec::with_capacity(self.things.len() * 10);
+        for thing in &self.things {
+            things_bytes.extend_from_slice(&thing.x.to_le_bytes());
+            things_bytes.extend_from_slice(&thing.y.to_le_bytes());
+
+            things_bytes.extend_from_slice(&thing.angle.to_le_bytes());
+
+            things_bytes.extend_from_slice(&thing.thing_type.to_le_bytes());
+            things_bytes.extend_from_slice(&thing.spawn_flags.to_le_bytes());
+        }
+
+        // Encode LINEDEFS.
+        let mut linedefs_bytes: Vec<u8> = Vec::with_capacity(self.linedefs.len() * 14);
+        for linedef in &self.linedefs {
+            linedefs_bytes.extend_from_slice(&linedef.start_vertex_idx.to_le_bytes());
+            linedefs_bytes.extend_from_slice(&linedef.end_vertex_idx.to_le_bytes());
+
+            linedefs_bytes.extend_from_slice(&linedef.flags.to_le_bytes());
+
+            linedefs_bytes.extend_from_slice(&linedef.line_type.to_le_bytes());
+            linedefs_bytes.extend_from_slice(&linedef.sector_tag.to_le_bytes());
+
+            let right_sidedef_idx = linedef.right_sidedef_idx.unwrap_or(0xFFFF);
+            let left_sidedef_idx = linedef.left_sidedef_idx.unwrap_or(0xFFFF);
+            linedefs_bytes.extend_from_slice(&right_sidedef_idx.to_le_bytes());
+            linedefs_bytes.extend_from_slice(&left_sidedef_idx.to_le_bytes());
+        }
+
+        // Encode SIDEDEFS.
+        let mut sidedefs_bytes: Vec<u8> = Vec::with_capacity(self.sidedefs.len() * 30);
+        for sidedef in &self.sidedefs {
+            sidedefs_bytes.extend_from_slice(&sidedef.x_offset.to_le_bytes());
+            sidedefs_bytes.extend_from_slice(&sidedef.y_offset.to_le_bytes());
+
+            sidedefs_bytes.extend_from_slice(&write_bytes_cstr(&sidedef.upper_texture)?);
+            sidedefs_bytes.extend_from_slice(&write_bytes_cstr(&sidedef.lower_texture)?);
+            sidedefs_bytes.extend_from_slice(&write_bytes_cstr(&sidedef.middle_texture)?);
+
+            sidedefs_bytes.extend_from_slice(&sidedef.sector_idx.to_le_bytes());
+        }
+
+        // Encode VERTEXES.
+        let mut vertices_bytes: Vec<u8> = Vec::with_capacity(self.vertices.len() * 4);
+        for vertex in &self.vertices {
+            vertices_bytes.extend_from_slice(&vertex.x.to_le_bytes());
+            vertices_bytes.extend_from_slice(&vertex.y.to_le_bytes());
+        }
+
+        // Encode SECTORS.
+        let mut sectors_bytes: Vec<u8> = Vec::with_capacity(self.sectors.len() * 26);
+        for sector in &self.sectors {
+            sectors_bytes.extend_from_slice(&sector.floor_height.to_le_bytes());
+            sectors_bytes.extend_from_slice(&sector.ceiling_height.to_le_bytes());
+
+            sectors_bytes.extend_from_slice(&write_bytes_cstr(&sector.floor_flat)?);
+            sectors_bytes.extend_from_slice(&write_bytes_cstr(&sector.ceiling_flat)?);
+
+            sectors_bytes.extend_from_slice(&sector.light_level.to_le_bytes());
+
+            sectors_bytes.extend_from_slice(&sector.special_type.to_le_bytes());
+            sectors_bytes.extend_from_slice(&sector.sector_tag.to_le_bytes());
+        }
+
+        // Vanilla finds each lump at a fixed offset from the marker, so all
+        // ten are written in order, empty if the map doesn't have one.
+        let mut bsp_lumps = self.bsp_lumps()?;
+        let mut bsp_lump = |lump_name: &str| {
+            bsp_lumps
+                .iter()
+                .position(|(name, _)| name == lump_name)
+                .map(|idx| bsp_lumps.remove(idx).1)
+                .unwrap_or_default()
+        };
+        let blockmap_bytes = match &self.blockmap {
+            Some(blockmap) => write_blockmap(blockmap)?,
+            None => vec![],
+        };
+
+        let lumps = vec![
+            ("THINGS".to_string(), things_bytes),
+            ("LINEDEFS".to_string(), linedefs_bytes),
+            ("SIDEDEFS".to_string(), sidedefs_bytes),
+            ("VERTEXES".to_string(), vertices_bytes),
+            ("SEGS".to_string(), bsp_lump("SEGS")),
+            ("SSECTORS".to_string(), bsp_lump("SSECTORS")),
+            ("NODES".to_string(), bsp_lump("NODES")),
+            ("SECTORS".to_string(), sectors_bytes),
+            ("REJECT".to_string(), self.reject.bits.clone()),
+            ("BLOCKMAP".to_string(), blockmap_bytes),
+        ];
+
+        Ok(lumps)
+    }
+}
+
+impl WadBuilder {
+    /// Writes (or replaces) a map block using [Map::to_lumps].
+    pub fn set_map_from(&mut self, map: &Map) -> Result<&mut Self, WadError> {
+        self.set_map(&map.name, map.to_lumps()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wad_builder::CANONICAL_MAP_LUMP_ORDER;
+
+    fn square_room() -> Map {
+        let vertices = vec![
+            Vertex { x: 0, y: 0 },
+            Vertex { x: 0, y: 128 },
+            Vertex { x: 128, y: 128 },
+            Vertex { x: 128, y: 0 },
+        ];
+
+        let linedefs = (0..4)
+            .map(|i| Linedef {
+                start_vertex_idx: i,
+                end_vertex_idx: (i + 1) % 4,
+                flags: 1,
+                line_type: 0,
+                sector_tag: 0,
+                right_sidedef_idx: Some(i),
+                left_sidedef_idx: None,
+            })
+            .collect();
+
+        let sidedefs = (0..4)
+            .map(|_| Sidedef {
+                x_offset: 0,
+                y_offset: -8,
+                upper_texture: "-".to_string(),
+                lower_texture: "-".to_string(),
+                middle_texture: "STARTAN3".to_string(),
+                sector_idx: 0,
+            })
+            .collect();
+
+        Map {
+            name: "E1M1".to_string(),
+            things: vec![Thing {
+                x: 64,
+                y: 64,
+                angle: 90,
+                thing_type: 1,
+                spawn_flags: 7,
+            }],
+            sectors: vec![Sector {
+                floor_height: 0,
+                ceiling_height: 128,
+                floor_flat: "FLOOR4_8".to_string(),
+                ceiling_flat: "F_SKY1".to_string(),
+                light_level: 160,
+                special_type: 0,
+                sector_tag: 0,
+            }],
+            sidedefs,
+            linedefs,
+            vertices,
+            segs: None,
+            subsectors: None,
+            nodes: None,
+            node_vertices: vec![],
+            node_format: NodeFormat::Vanilla,
+            blockmap: None,
+            reject: Reject::all_visible(1),
+            udmf: None,
+        }
+    }
+
+    #[test]
+    fn parse_write_parse() {
+        let map = square_room();
+
+        let mut builder = WadBuilder::new(false);
+        builder.set_map_from(&map).unwrap();
+
+        let wad = builder.build().unwrap();
+        let parsed = wad.parse_map("E1M1").unwrap();
+        assert_eq!(parsed, map);
+
+        // Writing the parsed map again should produce identical lumps.
+        assert_eq!(parsed.to_lumps().unwrap(), map.to_lumps().unwrap());
+        assert_eq!(wad.to_bytes().unwrap(), builder.to_bytes());
+    }
+
+    #[test]
+    fn lumps_in_vanilla_order() {
+        let lump_names: Vec<String> = square_room()
+            .to_lumps()
+            .unwrap()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(lump_names, CANONICAL_MAP_LUMP_ORDER);
+    }
+
+    #[test]
+    fn round_trip_is_byte_exact() {
+        // A map without BSP data, REJECT or BLOCKMAP, as an editor saves it.
+        let mut lumps = square_room().to_lumps().unwrap();
+        for (name, bytes) in &mut lumps {
+            if name == "REJECT" {
+                bytes.clear();
+            }
+        }
+        let mut builder = WadBuilder::new(false);
+        builder.set_map("E1M1", lumps).unwrap();
+        let input = builder.to_bytes();
+
+        let parsed = Wad::new(input.clone()).unwrap().parse_map("E1M1").unwrap();
+        let mut builder = WadBuilder::new(false);
+        builder.set_map_from(&parsed).unwrap();
+        assert_eq!(builder.to_bytes(), input);
+    }
+
+    #[test]
+    fn texture_names_must_fit() {
+        let mut map = square_room();
+        map.sidedefs[0].middle_texture = "TOOLONGNAME".to_string();
+
+        assert!(matches!(
+            map.to_lumps(),
+            Err(WadError::InvalidLumpName(name)) if name == "TOOLONGNAME"
+        ));
+    }
+}
@@ -1,11 +1,45 @@
+mod animated;
+mod blockmap;
 mod colormaps;
+mod endoom;
+mod extended_nodes;
+mod flats;
+mod fonts;
+mod images;
+mod mapinfo;
 mod maps;
+mod music;
+mod node_builder;
+mod nodes;
 mod palettes;
 mod patches;
+mod png;
+mod reject;
+mod screens;
+mod sounds;
+mod sprites;
 mod textures;
+mod udmf;
 
+pub use animated::*;
+pub use blockmap::*;
 pub use colormaps::*;
+pub use endoom::*;
+pub(crate) use extended_nodes::*;
+pub use flats::*;
+pub use fonts::*;
+pub use images::*;
+pub use mapinfo::*;
 pub use maps::*;
+pub use music::*;
+pub use node_builder::*;
+pub use nodes::*;
 pub use palettes::*;
 pub use patches::*;
+pub use png::*;
+pub use reject::*;
+pub use screens::*;
+pub use sounds::*;
+pub use sprites::*;
 pub use textures::*;
+pub use udmf::*;
@@ -0,0 +1,411 @@
+use crate::{helpers::ByteReader, lump_from_namespace, LumpNamespace, Wad, WadError};
+
+/// An event in a MUS score.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MusEvent {
+    ReleaseNote {
+        note: u8,
+    },
+    /// Without a volume, the channel's last volume is used.
+    PlayNote {
+        note: u8,
+        volume: Option<u8>,
+    },
+    /// 128 is centered.
+    PitchBend(u8),
+    /// Valueless controllers 10 to 14, like "all notes off".
+    System(u8),
+    /// Controller 0 changes the instrument.
+    Controller {
+        controller: u8,
+        value: u8,
+    },
+    EndOfMeasure,
+    ScoreEnd,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MusTimedEvent {
+    /// 0 to 15, where 15 is percussion.
+    pub channel: u8,
+    pub event: MusEvent,
+    /// Ticks to wait after this event, at 140 per second.
+    pub delay: u32,
+}
+
+/// [Mus] is the MIDI-like format DOOM's music lumps are stored in.
+///
+/// References:
+/// - https://doomwiki.org/wiki/MUS
+/// - "The Unofficial Doom Specs": `docs/dmsp1666.txt`, 8-5.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mus {
+    pub primary_channels: u16,
+    pub secondary_channels: u16,
+    /// The instruments used, for patch caching on OPL hardware.
+    pub instruments: Vec<u16>,
+    pub events: Vec<MusTimedEvent>,
+}
+
+/// A decoded music lump.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Music {
+    Mus(Mus),
+    /// A Standard MIDI File, as-is.
+    Midi(Vec<u8>),
+}
+
+const MUS_MAGIC: &[u8; 4] = b"MUS\x1A";
+const MIDI_MAGIC: &[u8; 4] = b"MThd";
+
+/// MUS channel 15 is percussion, which is MIDI channel 9.
+const MUS_PERCUSSION_CHANNEL: u8 = 15;
+const MIDI_PERCUSSION_CHANNEL: u8 = 9;
+
+/// MUS controller numbers to MIDI controller numbers. 0 is a program change.
+const MIDI_CONTROLLERS: [u8; 15] = [
+    0x00, 0x20, 0x01, 0x07, 0x0A, 0x0B, 0x5B, 0x5D, 0x40, 0x43, 0x78, 0x7B, 0x7E, 0x7F, 0x79,
+];
+
+impl Mus {
+    pub fn parse(lump_name: &str, bytes: &[u8]) -> Result<Self, WadError> {
+        let mut reader = ByteReader::new(lump_name, bytes);
+        if reader.take(4)? != MUS_MAGIC {
+            return Err(WadError::corrupted(lump_name, 0));
+        }
+
+        let _score_len = reader.u16()?;
+        let score_start = reader.u16()? as usize;
+        let primary_channels = reader.u16()?;
+        let secondary_channels = reader.u16()?;
+        let instrument_count = reader.u16()?;
+        // 14..16 is reserved.
+        reader.take(2)?;
+
+        let instruments = (0..instrument_count)
+            .map(|_| reader.u16())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut reader = ByteReader::new_at(lump_name, bytes, score_start);
+        let mut events = Vec::new();
+
+        // Some scores are missing the score end event, and just stop.
+        while reader.offset() < bytes.len() {
+            let descriptor = reader.u8()?;
+            let channel = descriptor & 0x0F;
+
+            let event = match (descriptor >> 4) & 0x07 {
+                0 => MusEvent::ReleaseNote {
+                    note: reader.u8()? & 0x7F,
+                },
+                1 => {
+                    let note = reader.u8()?;
+                    let volume = match note & 0x80 {
+                        0 => None,
+                        _ => Some(reader.u8()? & 0x7F),
+                    };
+                    MusEvent::PlayNote {
+                        note: note & 0x7F,
+                        volume,
+                    }
+                }
+                2 => MusEvent::PitchBend(reader.u8()?),
+                3 => MusEvent::System(reader.u8()? & 0x7F),
+                4 => MusEvent::Controller {
+                    controller: reader.u8()? & 0x7F,
+                    value: reader.u8()?.min(0x7F),
+                },
+                5 => MusEvent::EndOfMeasure,
+                6 => MusEvent::ScoreEnd,
+                _ => return Err(WadError::corrupted(lump_name, reader.offset() - 1)),
+            };
+
+            // The high bit means a delay follows, as a big-endian base-128 number.
+            let mut delay = 0u32;
+            if descriptor & 0x80 != 0 {
+                loop {
+                    let byte = reader.u8()?;
+                    delay = delay.saturating_mul(128) | (byte & 0x7F) as u32;
+                    if byte & 0x80 == 0 {
+                        break;
+                    }
+                }
+            }
+
+            events.push(MusTimedEvent {
+                channel,
+                event,
+                delay,
+            });
+            if event == MusEvent::ScoreEnd {
+                break;
+            }
+        }
+
+        Ok(Self {
+            primary_channels,
+            secondary_channels,
+            instruments,
+            events,
+        })
+    }
+
+    /// Converts the score to a format 0 Standard MIDI File.
+    ///
+    /// MUS plays at 140 ticks per second, which is 70 ticks per quarter note at
+    /// the default MIDI tempo of 120 BPM. Conversion follows the usual
+    /// `mus2mid` behaviour, so the output matches other source ports.
+    pub fn to_midi(&self) -> Vec<u8> {
+        let mut track = MidiTrack::default();
+
+        // MUS channels are given MIDI channels in order of first use.
+        let mut channel_map: [Option<u8>; 16] = [None; 16];
+        let mut next_midi_channel = 0;
+        // The volume of the last note played on each MUS channel.
+        let mut channel_volumes = [127u8; 16];
+
+        for timed in &self.events {
+            let mus_channel = timed.channel as usize;
+            let channel = match channel_map[mus_channel] {
+                Some(channel) => channel,
+                None if timed.event == MusEvent::ScoreEnd => 0,
+                None if timed.channel == MUS_PERCUSSION_CHANNEL => {
+                    channel_map[mus_channel] = Some(MIDI_PERCUSSION_CHANNEL);
+                    MIDI_PERCUSSION_CHANNEL
+                }
+                None => {
+                    let channel = next_midi_channel;
+                    next_midi_channel += 1;
+                    if next_midi_channel == MIDI_PERCUSSION_CHANNEL {
+                        next_midi_channel += 1;
+                    }
+                    channel_map[mus_channel] = Some(channel);
+
+                    // Start each melodic channel from silence.
+                    track.event(&[0xB0 | channel, 0x7B, 0]);
+                    channel
+                }
+            };
+
+            match timed.event {
+                MusEvent::ReleaseNote { note } => track.event(&[0x80 | channel, note, 0]),
+                MusEvent::PlayNote { note, volume } => {
+                    if let Some(volume) = volume {
+                        channel_volumes[mus_channel] = volume;
+                    }
+                    track.event(&[0x90 | channel, note, channel_volumes[mus_channel]]);
+                }
+                MusEvent::PitchBend(bend) => {
+                    let bend = bend as u16 * 64;
+                    track.event(&[0xE0 | channel, (bend & 0x7F) as u8, (bend >> 7) as u8]);
+                }
+                MusEvent::System(controller) => {
+                    if let Some(&midi) = MIDI_CONTROLLERS.get(controller as usize) {
+                        track.event(&[0xB0 | channel, midi, 0]);
+                    }
+                }
+                MusEvent::Controller {
+                    controller: 0,
+                    value,
+                } => track.event(&[0xC0 | channel, value]),
+                MusEvent::Controller { controller, value } => {
+                    if let Some(&midi) = MIDI_CONTROLLERS.get(controller as usize) {
+                        track.event(&[0xB0 | channel, midi, value]);
+                    }
+                }
+                MusEvent::EndOfMeasure => {}
+                MusEvent::ScoreEnd => break,
+            }
+
+            track.pending_delay += timed.delay;
+        }
+
+        // End of track.
+        track.event(&[0xFF, 0x2F, 0x00]);
+
+        let mut midi = Vec::with_capacity(22 + track.bytes.len());
+        midi.extend_from_slice(MIDI_MAGIC);
+        midi.extend_from_slice(&6u32.to_be_bytes());
+        // Format 0, 1 track, 70 ticks per quarter note.
+        midi.extend_from_slice(&0u16.to_be_bytes());
+        midi.extend_from_slice(&1u16.to_be_bytes());
+        midi.extend_from_slice(&70u16.to_be_bytes());
+        midi.extend_from_slice(b"MTrk");
+        midi.extend_from_slice(&(track.bytes.len() as u32).to_be_bytes());
+        midi.extend_from_slice(&track.bytes);
+        midi
+    }
+}
+
+#[derive(Default)]
+struct MidiTrack {
+    bytes: Vec<u8>,
+    /// Ticks since the last event written.
+    pending_delay: u32,
+}
+
+impl MidiTrack {
+    fn event(&mut self, event: &[u8]) {
+        // Delta times are big-endian base-128, with the high bit on every byte
+        // but the last.
+        let delay = std::mem::take(&mut self.pending_delay);
+        let mut groups = vec![(delay & 0x7F) as u8];
+        let mut rest = delay >> 7;
+        while rest > 0 {
+            groups.push((rest & 0x7F) as u8 | 0x80);
+            rest >>= 7;
+        }
+        self.bytes.extend(groups.iter().rev());
+        self.bytes.extend_from_slice(event);
+    }
+}
+
+impl Music {
+    pub fn parse(lump_name: &str, bytes: &[u8]) -> Result<Self, WadError> {
+        if bytes.starts_with(MUS_MAGIC) {
+            Ok(Music::Mus(Mus::parse(lump_name, bytes)?))
+        } else if bytes.starts_with(MIDI_MAGIC) {
+            Ok(Music::Midi(bytes.to_vec()))
+        } else {
+            Err(WadError::UnsupportedMusic(lump_name.to_string()))
+        }
+    }
+
+    pub fn to_midi(&self) -> Vec<u8> {
+        match self {
+            Music::Mus(mus) => mus.to_midi(),
+            Music::Midi(midi) => midi.clone(),
+        }
+    }
+}
+
+impl Wad {
+    /// Parses a music lump, like `D_E1M1` or `MUS_E1M1`.
+    pub fn parse_music(&self, lump_name: &str) -> Result<Music, WadError> {
+        let lump = lump_from_namespace(&LumpNamespace::Global, &lump_name.to_uppercase(), self)?;
+        Music::parse(&lump.name, lump.bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::WadBuilder;
+
+    fn mus(score: &[u8]) -> Vec<u8> {
+        let mut bytes = MUS_MAGIC.to_vec();
+        // Score length, score start, primary channels, secondary channels,
+        // 1 instrument, reserved, and the instrument.
+        for value in [score.len() as u16, 18, 1, 0, 1, 0, 30] {
+            bytes.extend(value.to_le_bytes());
+        }
+        bytes.extend(score);
+        bytes
+    }
+
+    #[test]
+    fn mus_to_midi() {
+        let bytes = mus(&[
+            // Play note 60 at volume 100 on channel 0, then wait 70 ticks.
+            0x90,
+            0x80 | 60,
+            100,
+            70,
+            // Play note 36 on the percussion channel, at the default volume.
+            0x1F,
+            36,
+            // Release note 60, then wait 128 ticks.
+            0x80,
+            60,
+            0x81,
+            0x00,
+            // Score end.
+            0x60,
+            // Garbage after the end is ignored.
+            0xFF,
+        ]);
+
+        let mus = Mus::parse("D_TEST", &bytes).unwrap();
+        assert_eq!(mus.instruments, vec![30]);
+        assert_eq!(
+            mus.events,
+            vec![
+                MusTimedEvent {
+                    channel: 0,
+                    event: MusEvent::PlayNote {
+                        note: 60,
+                        volume: Some(100)
+                    },
+                    delay: 70,
+                },
+                MusTimedEvent {
+                    channel: 15,
+                    event: MusEvent::PlayNote {
+                        note: 36,
+                        volume: None
+                    },
+                    delay: 0,
+                },
+                MusTimedEvent {
+                    channel: 0,
+                    event: MusEvent::ReleaseNote { note: 60 },
+                    delay: 128,
+                },
+                MusTimedEvent {
+                    channel: 0,
+                    event: MusEvent::ScoreEnd,
+                    delay: 0,
+                },
+            ]
+        );
+
+        #[rustfmt::skip]
+        let expected = [
+            b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 0, 0, 1, 0, 70,
+            b'M', b'T', b'r', b'k', 0, 0, 0, 21,
+            0, 0xB0, 0x7B, 0,
+            0, 0x90, 60, 100,
+            70, 0x99, 36, 127,
+            0, 0x80, 60, 0,
+            0x81, 0x00, 0xFF, 0x2F, 0x00,
+        ];
+        assert_eq!(mus.to_midi(), expected);
+
+        // MIDI lumps are passed through.
+        let mut builder = WadBuilder::new(false);
+        builder
+            .add_lump(&LumpNamespace::Global, "D_MUS", bytes.clone())
+            .unwrap()
+            .add_lump(&LumpNamespace::Global, "D_MIDI", expected.to_vec())
+            .unwrap()
+            .add_lump(&LumpNamespace::Global, "D_OGG", b"OggS".to_vec())
+            .unwrap();
+        let wad = builder.build().unwrap();
+        assert_eq!(wad.parse_music("D_MUS").unwrap().to_midi(), expected);
+        assert_eq!(
+            wad.parse_music("D_MIDI").unwrap(),
+            Music::Midi(expected.to_vec())
+        );
+        assert!(matches!(
+            wad.parse_music("D_OGG"),
+            Err(WadError::UnsupportedMusic(_))
+        ));
+
+        // Controller values above 127 are clamped rather than masked.
+        let controller = super::tests::mus(&[0x40, 7, 200, 0x60]);
+        let clamped = Mus::parse("D_TEST", &controller).unwrap();
+        assert_eq!(
+            clamped.events[0].event,
+            MusEvent::Controller {
+                controller: 7,
+                value: 127
+            }
+        );
+
+        // A truncated delay.
+        assert!(matches!(
+            Mus::parse("D_TEST", &bytes[..21]),
+            Err(WadError::CorruptedLump { offset: 21, .. })
+        ));
+    }
+}
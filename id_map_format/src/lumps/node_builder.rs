@@ -0,0 +1,768 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    bam_angle, Map, Node, NodeBounds, NodeChild, NodeFormat, NodeVertex, Seg, Subsector, Vertex,
+};
+
+/// Points closer than this to a partition line are on it.
+const EPSILON: f64 = 1.0 / 128.0;
+
+/// How many partition lines are tried at each node. Larger sets of segs are
+/// sampled evenly, like BSP and ZenNode do, so building stays fast on big maps.
+const MAX_CANDIDATES: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NodeBuilderOptions {
+    /// The format to build for.
+    ///
+    /// Vanilla appends the vertices created by splits to VERTEXES, rounded to
+    /// whole units. Extended formats keep them exact in `node_vertices`, and
+    /// [NodeFormat::ExtendedGl] also closes every subsector with minisegs.
+    pub format: NodeFormat,
+    /// How many segs of imbalance between the two sides of a partition are
+    /// worth splitting one seg to avoid.
+    pub split_cost: u32,
+}
+
+impl Default for NodeBuilderOptions {
+    fn default() -> Self {
+        Self {
+            format: NodeFormat::Vanilla,
+            split_cost: 8,
+        }
+    }
+}
+
+/// A seg while the tree is being built, between two of [Builder::vertices].
+#[derive(Debug, Clone, Copy)]
+struct BuildSeg {
+    start: u32,
+    end: u32,
+    linedef_idx: u32,
+    is_left_side: bool,
+    /// Distance from the start of the linedef's side to the start of the seg.
+    offset: f64,
+    sector_idx: Option<u16>,
+    /// The line the seg lies on, worked out once for its linedef side.
+    partition: Partition,
+}
+
+/// A partition line, as it's stored in a [Node].
+#[derive(Debug, Clone, Copy)]
+struct Partition {
+    x: i16,
+    y: i16,
+    dx: i16,
+    dy: i16,
+}
+
+enum Side {
+    Right,
+    Left,
+    /// Crosses the line at this fraction of the way from its start.
+    Split(f64),
+}
+
+impl Partition {
+    /// Distance from the line, positive on the left like
+    /// [Node::is_point_on_left].
+    fn distance(&self, (x, y): (f64, f64)) -> f64 {
+        let (dx, dy) = (self.dx as f64, self.dy as f64);
+        (dx * (y - self.y as f64) - dy * (x - self.x as f64)) / dx.hypot(dy)
+    }
+
+    fn classify(&self, start: (f64, f64), end: (f64, f64)) -> Side {
+        let (a, b) = (self.distance(start), self.distance(end));
+
+        if a.abs() < EPSILON && b.abs() < EPSILON {
+            // On the line: facing the same way is the right side.
+            let dot = (end.0 - start.0) * self.dx as f64 + (end.1 - start.1) * self.dy as f64;
+            return match dot > 0.0 {
+                true => Side::Right,
+                false => Side::Left,
+            };
+        }
+        if a > -EPSILON && b > -EPSILON {
+            return Side::Left;
+        }
+        if a < EPSILON && b < EPSILON {
+            return Side::Right;
+        }
+
+        Side::Split(a / (a - b))
+    }
+}
+
+struct Builder<'a> {
+    map: &'a Map,
+    split_cost: f64,
+    is_gl: bool,
+
+    /// The map's vertices, followed by the ones created by splits.
+    vertices: Vec<(f64, f64)>,
+    /// Vertices by their 16.16 fixed point position, so both sides of a line
+    /// are split at the same vertex.
+    vertex_lookup: HashMap<(i64, i64), u32>,
+
+    segs: Vec<Seg>,
+    subsectors: Vec<Subsector>,
+    nodes: Vec<Node>,
+}
+
+impl Map {
+    /// Builds SEGS, SSECTORS and NODES from the map's geometry, replacing any
+    /// BSP data it already has. Like [crate::Blockmap::generate], this is for
+    /// maps that don't ship with nodes.
+    ///
+    /// Partitions are chosen along the map's own lines, preferring the one
+    /// that best balances the segs on each side against how many it splits.
+    /// Subsectors are convex, and each belongs to a single sector.
+    pub fn build_nodes(&mut self, options: &NodeBuilderOptions) {
+        let is_gl = matches!(options.format, NodeFormat::ExtendedGl { .. });
+        let mut builder = Builder {
+            map: self,
+            split_cost: options.split_cost as f64,
+            is_gl,
+            vertices: vec![],
+            vertex_lookup: HashMap::new(),
+            segs: vec![],
+            subsectors: vec![],
+            nodes: vec![],
+        };
+        // Every map vertex keeps its index, even if another shares its position.
+        for (index, vertex) in self.vertices.iter().enumerate() {
+            let (x, y) = (vertex.x as i64, vertex.y as i64);
+            builder.vertices.push((x as f64, y as f64));
+            builder
+                .vertex_lookup
+                .entry((x << 16, y << 16))
+                .or_insert(index as u32);
+        }
+
+        let segs = builder.initial_segs();
+        if !segs.is_empty() {
+            builder.build(segs);
+        }
+        if is_gl {
+            builder.link_partners();
+        }
+
+        let Builder {
+            vertices,
+            segs,
+            subsectors,
+            nodes,
+            ..
+        } = builder;
+
+        let new_vertices = &vertices[self.vertices.len()..];
+        match options.format {
+            NodeFormat::Vanilla => {
+                self.vertices
+                    .extend(new_vertices.iter().map(|(x, y)| Vertex {
+                        x: x.round() as i16,
+                        y: y.round() as i16,
+                    }));
+                self.node_vertices = vec![];
+            }
+            _ => {
+                self.node_vertices = new_vertices
+                    .iter()
+                    .map(|(x, y)| NodeVertex {
+                        x: (x * 65536.0).round() as i32,
+                        y: (y * 65536.0).round() as i32,
+                    })
+                    .collect();
+            }
+        }
+
+        self.segs = Some(segs);
+        self.subsectors = Some(subsectors);
+        self.nodes = Some(nodes);
+        self.node_format = options.format;
+    }
+}
+
+impl Builder<'_> {
+    fn add_vertex(&mut self, (x, y): (f64, f64)) -> u32 {
+        let key = ((x * 65536.0).round() as i64, (y * 65536.0).round() as i64);
+        if let Some(index) = self.vertex_lookup.get(&key) {
+            return *index;
+        }
+
+        let index = self.vertices.len() as u32;
+        self.vertices
+            .push((key.0 as f64 / 65536.0, key.1 as f64 / 65536.0));
+        self.vertex_lookup.insert(key, index);
+        index
+    }
+
+    fn position(&self, vertex_idx: u32) -> (f64, f64) {
+        self.vertices[vertex_idx as usize]
+    }
+
+    /// A seg for each side of each linedef, skipping broken ones.
+    fn initial_segs(&self) -> Vec<BuildSeg> {
+        let mut segs = Vec::new();
+        for (linedef_idx, linedef) in self.map.linedefs.iter().enumerate() {
+            let (start, end) = (
+                linedef.start_vertex_idx as u32,
+                linedef.end_vertex_idx as u32,
+            );
+            if start as usize >= self.map.vertices.len()
+                || end as usize >= self.map.vertices.len()
+                || self.position(start) == self.position(end)
+            {
+                continue;
+            }
+
+            let sides = [
+                (linedef.right_sidedef_idx, false, start, end),
+                (linedef.left_sidedef_idx, true, end, start),
+            ];
+            for (sidedef_idx, is_left_side, start, end) in sides {
+                let Some(sidedef_idx) = sidedef_idx else {
+                    continue;
+                };
+                segs.push(BuildSeg {
+                    start,
+                    end,
+                    linedef_idx: linedef_idx as u32,
+                    is_left_side,
+                    offset: 0.0,
+                    sector_idx: self
+                        .map
+                        .sidedefs
+                        .get(sidedef_idx as usize)
+                        .map(|sidedef| sidedef.sector_idx),
+                    partition: self.partition(start, end),
+                });
+            }
+        }
+        segs
+    }
+
+    /// The line through two map vertices, as it's stored in a node.
+    fn partition(&self, start: u32, end: u32) -> Partition {
+        let (start, end) = (
+            self.map.vertices[start as usize],
+            self.map.vertices[end as usize],
+        );
+
+        // Lines longer than an i16 can hold keep their direction at half size.
+        let (mut dx, mut dy) = (end.x as i32 - start.x as i32, end.y as i32 - start.y as i32);
+        while dx.abs() > i16::MAX as i32 || dy.abs() > i16::MAX as i32 {
+            (dx, dy) = (dx / 2, dy / 2);
+        }
+
+        Partition {
+            x: start.x,
+            y: start.y,
+            dx: dx as i16,
+            dy: dy as i16,
+        }
+    }
+
+    fn classify(&self, partition: &Partition, seg: &BuildSeg) -> Side {
+        partition.classify(self.position(seg.start), self.position(seg.end))
+    }
+
+    /// Convex, with every seg facing in, and all in one sector.
+    fn is_leaf(&self, segs: &[BuildSeg]) -> bool {
+        if segs.iter().any(|seg| seg.sector_idx != segs[0].sector_idx) {
+            return false;
+        }
+
+        segs.iter().all(|seg| {
+            segs.iter()
+                .all(|other| matches!(self.classify(&seg.partition, other), Side::Right))
+        })
+    }
+
+    /// The partition with the lowest cost that leaves segs on both sides.
+    fn choose_partition(&self, segs: &[BuildSeg]) -> Option<Partition> {
+        let step = segs.len().div_ceil(MAX_CANDIDATES);
+        match self.best_partition(segs, step) {
+            // Every seg is worth trying before giving up on splitting the set.
+            None if step > 1 => self.best_partition(segs, 1),
+            best => best,
+        }
+    }
+
+    /// The best partition along every `step`th seg.
+    fn best_partition(&self, segs: &[BuildSeg], step: usize) -> Option<Partition> {
+        let mut best: Option<(f64, Partition)> = None;
+        let mut tried = HashSet::new();
+
+        for candidate in segs.iter().step_by(step) {
+            // Segs of the same linedef side share a line.
+            if !tried.insert((candidate.linedef_idx, candidate.is_left_side)) {
+                continue;
+            }
+
+            let partition = &candidate.partition;
+            let (mut right, mut left, mut splits) = (0, 0, 0);
+            let mut abandoned = false;
+            for (i, seg) in segs.iter().enumerate() {
+                match self.classify(partition, seg) {
+                    Side::Right => right += 1,
+                    Side::Left => left += 1,
+                    Side::Split(_) => {
+                        right += 1;
+                        left += 1;
+                        splits += 1;
+                    }
+                }
+
+                // Stop once the remaining segs can't bring the cost under the
+                // best so far.
+                let remaining = (segs.len() - i - 1) as f64;
+                let lowest_cost = ((right as f64 - left as f64).abs() - remaining).max(0.0)
+                    + splits as f64 * self.split_cost;
+                if best.is_some_and(|(best_cost, _)| lowest_cost >= best_cost) {
+                    abandoned = true;
+                    break;
+                }
+            }
+            if abandoned || right == 0 || left == 0 {
+                continue;
+            }
+
+            let cost = (right as f64 - left as f64).abs() + splits as f64 * self.split_cost;
+            if best.is_none_or(|(best_cost, _)| cost < best_cost) {
+                best = Some((cost, *partition));
+            }
+        }
+
+        best.map(|(_, partition)| partition)
+    }
+
+    /// Divides the segs along the partition, splitting the ones that cross it.
+    fn split(
+        &mut self,
+        partition: &Partition,
+        segs: Vec<BuildSeg>,
+    ) -> (Vec<BuildSeg>, Vec<BuildSeg>) {
+        let (mut right, mut left) = (Vec::new(), Vec::new());
+        for seg in segs {
+            match self.classify(partition, &seg) {
+                Side::Right => right.push(seg),
+                Side::Left => left.push(seg),
+                Side::Split(t) => {
+                    let (start, end) = (self.position(seg.start), self.position(seg.end));
+                    let mid = self.add_vertex((
+                        start.0 + (end.0 - start.0) * t,
+                        start.1 + (end.1 - start.1) * t,
+                    ));
+
+                    // Too close to an end to split, so it stays whole on the
+                    // side its other end is on.
+                    if mid == seg.start || mid == seg.end {
+                        match partition.distance(start) + partition.distance(end) > 0.0 {
+                            true => left.push(seg),
+                            false => right.push(seg),
+                        }
+                        continue;
+                    }
+
+                    let mid_position = self.position(mid);
+                    let first = BuildSeg { end: mid, ..seg };
+                    let second = BuildSeg {
+                        start: mid,
+                        offset: seg.offset
+                            + (mid_position.0 - start.0).hypot(mid_position.1 - start.1),
+                        ..seg
+                    };
+                    match partition.distance(start) > 0.0 {
+                        true => {
+                            left.push(first);
+                            right.push(second);
+                        }
+                        false => {
+                            right.push(first);
+                            left.push(second);
+                        }
+                    }
+                }
+            }
+        }
+        (right, left)
+    }
+
+    fn build(&mut self, segs: Vec<BuildSeg>) -> NodeChild {
+        let partition = match self.is_leaf(&segs) {
+            true => None,
+            false => self.choose_partition(&segs),
+        };
+        let Some(partition) = partition else {
+            return self.add_subsector(segs);
+        };
+
+        let (right, left) = self.split(&partition, segs);
+        if right.is_empty() || left.is_empty() {
+            return self.add_subsector([right, left].concat());
+        }
+
+        let right_bounds = self.bounds(&right);
+        let left_bounds = self.bounds(&left);
+        let right_child = self.build(right);
+        let left_child = self.build(left);
+
+        self.nodes.push(Node {
+            x: partition.x,
+            y: partition.y,
+            dx: partition.dx,
+            dy: partition.dy,
+            right_bounds,
+            left_bounds,
+            right_child,
+            left_child,
+        });
+        NodeChild::Node(self.nodes.len() as u32 - 1)
+    }
+
+    fn bounds(&self, segs: &[BuildSeg]) -> NodeBounds {
+        let (mut min_x, mut min_y) = (f64::MAX, f64::MAX);
+        let (mut max_x, mut max_y) = (f64::MIN, f64::MIN);
+        for vertex_idx in segs.iter().flat_map(|seg| [seg.start, seg.end]) {
+            let (x, y) = self.position(vertex_idx);
+            (min_x, min_y) = (min_x.min(x), min_y.min(y));
+            (max_x, max_y) = (max_x.max(x), max_y.max(y));
+        }
+
+        NodeBounds {
+            top: max_y.ceil() as i16,
+            bottom: min_y.floor() as i16,
+            left: min_x.floor() as i16,
+            right: max_x.ceil() as i16,
+        }
+    }
+
+    fn add_subsector(&mut self, mut segs: Vec<BuildSeg>) -> NodeChild {
+        let first_seg_idx = self.segs.len() as u32;
+
+        if self.is_gl {
+            // GL subsectors go clockwise, with minisegs closing the gaps.
+            let points = segs.iter().flat_map(|seg| [seg.start, seg.end]);
+            let count = segs.len() as f64 * 2.0;
+            let (center_x, center_y) = points
+                .map(|vertex_idx| self.position(vertex_idx))
+                .fold((0.0, 0.0), |(x, y), (px, py)| {
+                    (x + px / count, y + py / count)
+                });
+            let angle = |seg: &BuildSeg| {
+                let (start, end) = (self.position(seg.start), self.position(seg.end));
+                let (x, y) = ((start.0 + end.0) / 2.0, (start.1 + end.1) / 2.0);
+                (y - center_y).atan2(x - center_x)
+            };
+            segs.sort_by(|a, b| angle(b).total_cmp(&angle(a)));
+
+            for (i, seg) in segs.iter().enumerate() {
+                self.push_seg(seg);
+                let next = &segs[(i + 1) % segs.len()];
+                if seg.end != next.start {
+                    self.push_miniseg(seg.end, next.start);
+                }
+            }
+        } else {
+            for seg in &segs {
+                self.push_seg(seg);
+            }
+        }
+
+        self.subsectors.push(Subsector {
+            num_segs: self.segs.len() as u32 - first_seg_idx,
+            first_seg_idx,
+        });
+        NodeChild::Subsector(self.subsectors.len() as u32 - 1)
+    }
+
+    fn seg_angle(&self, start: u32, end: u32) -> i16 {
+        let (start, end) = (self.position(start), self.position(end));
+        bam_angle((end.0 - start.0) as f32, (end.1 - start.1) as f32)
+    }
+
+    fn push_seg(&mut self, seg: &BuildSeg) {
+        // The angle of the whole linedef side, so split segs keep it exactly.
+        let partition = seg.partition;
+        self.segs.push(Seg {
+            start_vertex_idx: seg.start,
+            end_vertex_idx: seg.end,
+            angle: bam_angle(partition.dx as f32, partition.dy as f32),
+            linedef_idx: Some(seg.linedef_idx),
+            is_left_side: seg.is_left_side,
+            offset: seg.offset.round() as i32 as i16,
+            partner_seg_idx: None,
+        });
+    }
+
+    fn push_miniseg(&mut self, start: u32, end: u32) {
+        self.segs.push(Seg {
+            start_vertex_idx: start,
+            end_vertex_idx: end,
+            angle: self.seg_angle(start, end),
+            linedef_idx: None,
+            is_left_side: false,
+            offset: 0,
+            partner_seg_idx: None,
+        });
+    }
+
+    /// Pairs each seg with the one running the other way between the same
+    /// vertices: the other side of a two-sided linedef, or the miniseg in the
+    /// neighboring subsector.
+    fn link_partners(&mut self) {
+        let by_vertices: HashMap<(u32, u32), u32> = self
+            .segs
+            .iter()
+            .enumerate()
+            .map(|(i, seg)| ((seg.start_vertex_idx, seg.end_vertex_idx), i as u32))
+            .collect();
+
+        for i in 0..self.segs.len() {
+            let seg = &self.segs[i];
+            let partner = by_vertices
+                .get(&(seg.end_vertex_idx, seg.start_vertex_idx))
+                .copied()
+                .filter(|partner| self.segs[*partner as usize].linedef_idx == seg.linedef_idx);
+            self.segs[i].partner_seg_idx = partner;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Linedef, NodeFormat, Reject, Sector, Sidedef, WadBuilder};
+
+    /// Builds a map from lines between `points`, as `(start, end, right
+    /// sector, left sector)`.
+    fn map_from_lines(points: &[(i16, i16)], lines: &[(u16, u16, u16, Option<u16>)]) -> Map {
+        let mut sidedefs = Vec::new();
+        let mut side = |sector_idx| {
+            sidedefs.push(Sidedef {
+                x_offset: 0,
+                y_offset: 0,
+                upper_texture: "-".to_string(),
+                lower_texture: "-".to_string(),
+                middle_texture: "STARTAN3".to_string(),
+                sector_idx,
+            });
+            sidedefs.len() as u16 - 1
+        };
+        let linedefs = lines
+            .iter()
+            .map(|(start, end, right, left)| Linedef {
+                start_vertex_idx: *start,
+                end_vertex_idx: *end,
+                flags: match left {
+                    Some(_) => 4,
+                    None => 1,
+                },
+                line_type: 0,
+                sector_tag: 0,
+                right_sidedef_idx: Some(side(*right)),
+                left_sidedef_idx: left.map(&mut side),
+            })
+            .collect();
+        let num_sectors = lines
+            .iter()
+            .flat_map(|(_, _, right, left)| [Some(*right), *left])
+            .flatten()
+            .max()
+            .unwrap() as usize
+            + 1;
+
+        Map {
+            name: "MAP01".to_string(),
+            things: vec![],
+            sectors: (0..num_sectors)
+                .map(|_| Sector {
+                    floor_height: 0,
+                    ceiling_height: 128,
+                    floor_flat: "FLOOR4_8".to_string(),
+                    ceiling_flat: "CEIL3_5".to_string(),
+                    light_level: 160,
+                    special_type: 0,
+                    sector_tag: 0,
+                })
+                .collect(),
+            sidedefs,
+            linedefs,
+            vertices: points
+                .iter()
+                .map(|(x, y)| Vertex { x: *x, y: *y })
+                .collect(),
+            segs: None,
+            subsectors: None,
+            nodes: None,
+            node_vertices: vec![],
+            node_format: NodeFormat::Vanilla,
+            blockmap: None,
+            reject: Reject::all_visible(num_sectors),
+            udmf: None,
+        }
+    }
+
+    /// A 128x64 room split into two sectors by a two-sided line at x = 64.
+    fn two_rooms() -> Map {
+        map_from_lines(
+            &[(0, 0), (64, 0), (128, 0), (128, 64), (64, 64), (0, 64)],
+            &[
+                (0, 5, 0, None),
+                (5, 4, 0, None),
+                (1, 0, 0, None),
+                (4, 3, 1, None),
+                (3, 2, 1, None),
+                (2, 1, 1, None),
+                (4, 1, 0, Some(1)),
+            ],
+        )
+    }
+
+    /// An L-shaped room, which can't be one subsector.
+    fn l_room() -> Map {
+        map_from_lines(
+            &[(0, 0), (0, 128), (64, 128), (64, 64), (128, 64), (128, 0)],
+            &[
+                (0, 1, 0, None),
+                (1, 2, 0, None),
+                (2, 3, 0, None),
+                (3, 4, 0, None),
+                (4, 5, 0, None),
+                (5, 0, 0, None),
+            ],
+        )
+    }
+
+    /// The sector of the subsector containing the point.
+    fn sector_at(map: &Map, x: f32, y: f32) -> u16 {
+        let subsector =
+            map.subsectors.as_ref().unwrap()[map.find_subsector(x, y).unwrap() as usize];
+        let seg = &map.segs.as_ref().unwrap()[subsector.first_seg_idx as usize];
+        let linedef = &map.linedefs[seg.linedef_idx.unwrap() as usize];
+        let sidedef_idx = match seg.is_left_side {
+            true => linedef.left_sidedef_idx,
+            false => linedef.right_sidedef_idx,
+        };
+        map.sidedefs[sidedef_idx.unwrap() as usize].sector_idx
+    }
+
+    #[test]
+    fn build_vanilla_nodes() {
+        let mut map = map_from_lines(
+            &[(0, 0), (0, 64), (64, 64), (64, 0)],
+            &[
+                (0, 1, 0, None),
+                (1, 2, 0, None),
+                (2, 3, 0, None),
+                (3, 0, 0, None),
+            ],
+        );
+        map.build_nodes(&NodeBuilderOptions::default());
+        // A convex room is a single subsector, with no nodes.
+        assert_eq!(map.nodes.as_ref().unwrap().len(), 0);
+        assert_eq!(map.subsectors.as_ref().unwrap().len(), 1);
+        assert_eq!(map.find_subsector(32.0, 32.0), Some(0));
+        let mut builder = WadBuilder::new(false);
+        builder.set_map_from(&map).unwrap();
+        let parsed = builder.build().unwrap().parse_map("MAP01").unwrap();
+        assert_eq!(parsed.nodes, Some(vec![]));
+
+        let mut map = two_rooms();
+        map.build_nodes(&NodeBuilderOptions::default());
+        assert_eq!(map.nodes.as_ref().unwrap().len(), 1);
+        assert_eq!(map.segs.as_ref().unwrap().len(), 8);
+        assert_eq!(sector_at(&map, 32.0, 32.0), 0);
+        assert_eq!(sector_at(&map, 96.0, 32.0), 1);
+
+        // The nodes survive being written and read back.
+        let mut builder = WadBuilder::new(false);
+        builder.set_map_from(&map).unwrap();
+        let parsed = builder.build().unwrap().parse_map("MAP01").unwrap();
+        assert_eq!(parsed.nodes, map.nodes);
+        assert_eq!(parsed.segs, map.segs);
+
+        // Splitting the L adds vertices, which vanilla keeps in VERTEXES.
+        let mut map = l_room();
+        map.build_nodes(&NodeBuilderOptions {
+            split_cost: 0,
+            ..Default::default()
+        });
+        assert!(map.vertices.len() > 6);
+        assert_eq!(map.node_vertices, vec![]);
+        assert!(map.subsectors.as_ref().unwrap().len() >= 2);
+        for (x, y) in [(32.0, 32.0), (32.0, 100.0), (100.0, 32.0)] {
+            assert_eq!(sector_at(&map, x, y), 0);
+        }
+        assert!(map.bsp_lumps().is_ok());
+    }
+
+    #[test]
+    fn build_gl_nodes() {
+        let format = NodeFormat::ExtendedGl {
+            version: 2,
+            compressed: false,
+        };
+        let options = NodeBuilderOptions {
+            format,
+            ..Default::default()
+        };
+        let assert_closed = |map: &Map| {
+            let segs = map.segs.as_ref().unwrap();
+            for subsector in map.subsectors.as_ref().unwrap() {
+                let first = subsector.first_seg_idx as usize;
+                let run = &segs[first..first + subsector.num_segs as usize];
+                for (i, seg) in run.iter().enumerate() {
+                    assert_eq!(
+                        seg.end_vertex_idx,
+                        run[(i + 1) % run.len()].start_vertex_idx
+                    );
+                }
+            }
+        };
+
+        // Splitting the L leaves gaps along the partition, closed by minisegs
+        // that are each other's partners.
+        let mut map = l_room();
+        map.build_nodes(&options);
+        assert_eq!(map.node_format, format);
+        assert!(!map.node_vertices.is_empty());
+        assert_closed(&map);
+        let segs = map.segs.as_ref().unwrap();
+        let minisegs: Vec<_> = segs
+            .iter()
+            .filter(|seg| seg.linedef_idx.is_none())
+            .collect();
+        assert!(!minisegs.is_empty());
+        for miniseg in minisegs {
+            let partner = &segs[miniseg.partner_seg_idx.unwrap() as usize];
+            assert_eq!(partner.start_vertex_idx, miniseg.end_vertex_idx);
+            assert_eq!(partner.linedef_idx, None);
+        }
+
+        let mut map = two_rooms();
+        map.build_nodes(&options);
+        assert_closed(&map);
+        let segs = map.segs.as_ref().unwrap();
+
+        // The two sides of the two-sided line are partners.
+        let sides: Vec<_> = segs
+            .iter()
+            .enumerate()
+            .filter(|(_, seg)| seg.linedef_idx == Some(6))
+            .map(|(i, seg)| (i as u32, seg.partner_seg_idx))
+            .collect();
+        assert_eq!(sides.len(), 2);
+        assert_eq!(sides[0].1, Some(sides[1].0));
+        assert_eq!(sides[1].1, Some(sides[0].0));
+
+        let mut builder = WadBuilder::new(false);
+        builder.set_map_from(&map).unwrap();
+        let parsed = builder.build().unwrap().parse_map("MAP01").unwrap();
+        assert_eq!(parsed.segs, map.segs);
+        assert_eq!(parsed.node_vertices, map.node_vertices);
+        assert_eq!(sector_at(&parsed, 32.0, 32.0), 0);
+        assert_eq!(sector_at(&parsed, 96.0, 32.0), 1);
+    }
+}
@@ -0,0 +1,556 @@
+use crate::{write_extended_nodes, Lump, Map, WadError};
+
+/// Segs are the pieces of linedefs left after the node builder splits them
+/// along partition lines.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Seg {
+    pub start_vertex_idx: u32,
+    pub end_vertex_idx: u32,
+
+    /// Binary angle measurement: 0 is east, 0x4000 is north.
+    pub angle: i16,
+
+    /// [None] for GL "minisegs", which close off subsectors without a linedef.
+    pub linedef_idx: Option<u32>,
+    /// If true, the seg runs along the linedef's left side instead of its right.
+    pub is_left_side: bool,
+
+    /// Distance along the linedef to the start of the seg.
+    pub offset: i16,
+
+    /// GL nodes only: the seg running the opposite way on the other side.
+    pub partner_seg_idx: Option<u32>,
+}
+
+/// Vertices created by the node builder when splitting segs, in 16.16 fixed point.
+///
+/// Only extended node formats have these: vanilla node builders append
+/// (rounded) vertices to VERTEXES instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NodeVertex {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl NodeVertex {
+    pub fn x_f32(&self) -> f32 {
+        self.x as f32 / 65536.
+    }
+
+    pub fn y_f32(&self) -> f32 {
+        self.y as f32 / 65536.
+    }
+}
+
+/// How the map's BSP data is stored.
+///
+/// Reference:
+/// - https://zdoom.org/wiki/Node
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NodeFormat {
+    /// Separate SEGS, SSECTORS and NODES lumps.
+    #[default]
+    Vanilla,
+    /// ZDoom extended nodes, stored in the NODES lump as XNOD (or ZNOD if compressed).
+    Extended { compressed: bool },
+    /// ZDoom extended GL nodes, stored in the SSECTORS lump as XGLN/XGL2 (or
+    /// ZGLN/ZGL2 if compressed).
+    ///
+    /// Version 2 widens linedef indices to 32 bits.
+    ExtendedGl { version: u8, compressed: bool },
+}
+
+/// Subsectors are convex regions of a sector, made up of a run of segs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Subsector {
+    pub num_segs: u32,
+    pub first_seg_idx: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NodeBounds {
+    pub top: i16,
+    pub bottom: i16,
+    pub left: i16,
+    pub right: i16,
+}
+
+/// Children of a node are either another node, or a leaf subsector.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeChild {
+    Node(u32),
+    Subsector(u32),
+}
+
+/// Nodes split the map in two along a partition line, forming a binary tree.
+///
+/// The last node in the lump is the root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Node {
+    /// Start of the partition line.
+    pub x: i16,
+    pub y: i16,
+    /// Direction of the partition line.
+    pub dx: i16,
+    pub dy: i16,
+
+    pub right_bounds: NodeBounds,
+    pub left_bounds: NodeBounds,
+
+    pub right_child: NodeChild,
+    pub left_child: NodeChild,
+}
+
+impl Node {
+    /// Returns true if the point is on the left side of the partition line.
+    ///
+    /// Points exactly on the line are on the left, like vanilla's
+    /// `R_PointOnSide`.
+    pub fn is_point_on_left(&self, x: f32, y: f32) -> bool {
+        let cross = self.dx as f32 * (y - self.y as f32) - self.dy as f32 * (x - self.x as f32);
+        cross >= 0.
+    }
+}
+
+impl NodeChild {
+    fn from_u16(child: u16) -> Self {
+        // The high bit marks a subsector.
+        match child & 0x8000 {
+            0 => NodeChild::Node(child as u32),
+            _ => NodeChild::Subsector((child & 0x7FFF) as u32),
+        }
+    }
+
+    fn to_u16(self) -> Option<u16> {
+        match self {
+            NodeChild::Node(idx) if idx < 0x8000 => Some(idx as u16),
+            NodeChild::Subsector(idx) if idx < 0x8000 => Some(idx as u16 | 0x8000),
+            _ => None,
+        }
+    }
+}
+
+pub(crate) fn seg_vertex(
+    vertices: &[crate::Vertex],
+    node_vertices: &[NodeVertex],
+    vertex_idx: u32,
+) -> Option<(f32, f32)> {
+    let vertex_idx = vertex_idx as usize;
+    match vertices.get(vertex_idx) {
+        Some(vertex) => Some((vertex.x as f32, vertex.y as f32)),
+        None => node_vertices
+            .get(vertex_idx - vertices.len())
+            .map(|vertex| (vertex.x_f32(), vertex.y_f32())),
+    }
+}
+
+/// References:
+/// - "The Unofficial Doom Specs": `docs/dmsp1666.txt`, 4-4 through 4-6.
+pub(crate) fn parse_segs(lump: &Lump) -> Result<Vec<Seg>, WadError> {
+    let lump_bytes = lump.bytes();
+
+    if !lump.size.is_multiple_of(12) {
+        return Err(WadError::corrupted(&lump.name, lump.size - lump.size % 12));
+    }
+
+    let num_segs = lump.size / 12;
+    let mut segs: Vec<Seg> = Vec::with_capacity(num_segs);
+
+    for i in 0..num_segs {
+        let seg_offset = i * 12;
+        let seg_bytes = &lump_bytes[seg_offset..seg_offset + 12];
+
+        let start_vertex_idx = u16_le!(&seg_bytes[0..2]) as u32;
+        let end_vertex_idx = u16_le!(&seg_bytes[2..4]) as u32;
+
+        let angle = i16_le!(&seg_bytes[4..6]);
+
+        let linedef_idx = Some(u16_le!(&seg_bytes[6..8]) as u32);
+        let is_left_side = u16_le!(&seg_bytes[8..10]) != 0;
+
+        let offset = i16_le!(&seg_bytes[10..12]);
+
+        segs.push(Seg {
+            start_vertex_idx,
+            end_vertex_idx,
+            angle,
+            linedef_idx,
+            is_left_side,
+            offset,
+            partner_seg_idx: None,
+        });
+    }
+
+    Ok(segs)
+}
+
+pub(crate) fn parse_subsectors(lump: &Lump) -> Result<Vec<Subsector>, WadError> {
+    let lump_bytes = lump.bytes();
+
+    if !lump.size.is_multiple_of(4) {
+        return Err(WadError::corrupted(&lump.name, lump.size - lump.size % 4));
+    }
+
+    let num_subsectors = lump.size / 4;
+    let mut subsectors: Vec<Subsector> = Vec::with_capacity(num_subsectors);
+
+    for i in 0..num_subsectors {
+        let subsector_offset = i * 4;
+        let subsector_bytes = &lump_bytes[subsector_offset..subsector_offset + 4];
+
+        let num_segs = u16_le!(&subsector_bytes[0..2]) as u32;
+        let first_seg_idx = u16_le!(&subsector_bytes[2..4]) as u32;
+
+        subsectors.push(Subsector {
+            num_segs,
+            first_seg_idx,
+        });
+    }
+
+    Ok(subsectors)
+}
+
+pub(crate) fn parse_nodes(lump: &Lump) -> Result<Vec<Node>, WadError> {
+    let lump_bytes = lump.bytes();
+
+    if !lump.size.is_multiple_of(28) {
+        return Err(WadError::corrupted(&lump.name, lump.size - lump.size % 28));
+    }
+
+    let parse_bounds = |bytes: &[u8]| NodeBounds {
+        top: i16_le!(&bytes[0..2]),
+        bottom: i16_le!(&bytes[2..4]),
+        left: i16_le!(&bytes[4..6]),
+        right: i16_le!(&bytes[6..8]),
+    };
+
+    let num_nodes = lump.size / 28;
+    let mut nodes: Vec<Node> = Vec::with_capacity(num_nodes);
+
+    for i in 0..num_nodes {
+        let node_offset = i * 28;
+        let node_bytes = &lump_bytes[node_offset..node_offset + 28];
+
+        let x = i16_le!(&node_bytes[0..2]);
+        let y = i16_le!(&node_bytes[2..4]);
+        let dx = i16_le!(&node_bytes[4..6]);
+        let dy = i16_le!(&node_bytes[6..8]);
+
+        let right_bounds = parse_bounds(&node_bytes[8..16]);
+        let left_bounds = parse_bounds(&node_bytes[16..24]);
+
+        let right_child = NodeChild::from_u16(u16_le!(&node_bytes[24..26]));
+        let left_child = NodeChild::from_u16(u16_le!(&node_bytes[26..28]));
+
+        nodes.push(Node {
+            x,
+            y,
+            dx,
+            dy,
+            right_bounds,
+            left_bounds,
+            right_child,
+            left_child,
+        });
+    }
+
+    Ok(nodes)
+}
+
+fn write_segs(segs: &[Seg]) -> Result<Vec<u8>, WadError> {
+    let to_u16 = |idx: u32| u16::try_from(idx).map_err(|_| WadError::LumpOverflow("SEGS".into()));
+
+    let mut bytes: Vec<u8> = Vec::with_capacity(segs.len() * 12);
+    for seg in segs {
+        bytes.extend_from_slice(&to_u16(seg.start_vertex_idx)?.to_le_bytes());
+        bytes.extend_from_slice(&to_u16(seg.end_vertex_idx)?.to_le_bytes());
+
+        bytes.extend_from_slice(&seg.angle.to_le_bytes());
+
+        // Minisegs are rejected before we get here.
+        bytes.extend_from_slice(&to_u16(seg.linedef_idx.unwrap_or(u32::MAX))?.to_le_bytes());
+        bytes.extend_from_slice(&(seg.is_left_side as u16).to_le_bytes());
+
+        bytes.extend_from_slice(&seg.offset.to_le_bytes());
+    }
+
+    Ok(bytes)
+}
+
+fn write_subsectors(subsectors: &[Subsector]) -> Result<Vec<u8>, WadError> {
+    let to_u16 =
+        |idx: u32| u16::try_from(idx).map_err(|_| WadError::LumpOverflow("SSECTORS".into()));
+
+    let mut bytes: Vec<u8> = Vec::with_capacity(subsectors.len() * 4);
+    for subsector in subsectors {
+        bytes.extend_from_slice(&to_u16(subsector.num_segs)?.to_le_bytes());
+        bytes.extend_from_slice(&to_u16(subsector.first_seg_idx)?.to_le_bytes());
+    }
+
+    Ok(bytes)
+}
+
+fn write_nodes(nodes: &[Node]) -> Result<Vec<u8>, WadError> {
+    let write_bounds = |bytes: &mut Vec<u8>, bounds: &NodeBounds| {
+        bytes.extend_from_slice(&bounds.top.to_le_bytes());
+        bytes.extend_from_slice(&bounds.bottom.to_le_bytes());
+        bytes.extend_from_slice(&bounds.left.to_le_bytes());
+        bytes.extend_from_slice(&bounds.right.to_le_bytes());
+    };
+
+    let mut bytes: Vec<u8> = Vec::with_capacity(nodes.len() * 28);
+    for node in nodes {
+        bytes.extend_from_slice(&node.x.to_le_bytes());
+        bytes.extend_from_slice(&node.y.to_le_bytes());
+        bytes.extend_from_slice(&node.dx.to_le_bytes());
+        bytes.extend_from_slice(&node.dy.to_le_bytes());
+
+        write_bounds(&mut bytes, &node.right_bounds);
+        write_bounds(&mut bytes, &node.left_bounds);
+
+        for child in [node.right_child, node.left_child] {
+            let child = child
+                .to_u16()
+                .ok_or(WadError::LumpOverflow("NODES".into()))?;
+            bytes.extend_from_slice(&child.to_le_bytes());
+        }
+    }
+
+    Ok(bytes)
+}
+
+impl Map {
+    /// Position of a seg vertex.
+    ///
+    /// Indices past the end of `vertices` continue into `node_vertices`.
+    pub fn seg_vertex(&self, vertex_idx: u32) -> Option<(f32, f32)> {
+        seg_vertex(&self.vertices, &self.node_vertices, vertex_idx)
+    }
+
+    /// Encodes the map's BSP data in its [NodeFormat].
+    ///
+    /// For vanilla maps, only the lumps that are present are written.
+    pub fn bsp_lumps(&self) -> Result<Vec<(String, Vec<u8>)>, WadError> {
+        let mut lumps = Vec::new();
+
+        match self.node_format {
+            NodeFormat::Vanilla => {
+                if !self.node_vertices.is_empty() {
+                    return Err(WadError::IncompatibleNodeFormat(self.name.clone()));
+                }
+
+                if let Some(segs) = &self.segs {
+                    if segs.iter().any(|seg| seg.linedef_idx.is_none()) {
+                        return Err(WadError::IncompatibleNodeFormat(self.name.clone()));
+                    }
+                    lumps.push(("SEGS".to_string(), write_segs(segs)?));
+                }
+                if let Some(subsectors) = &self.subsectors {
+                    lumps.push(("SSECTORS".to_string(), write_subsectors(subsectors)?));
+                }
+                if let Some(nodes) = &self.nodes {
+                    lumps.push(("NODES".to_string(), write_nodes(nodes)?));
+                }
+            }
+            // Extended formats leave the other lumps empty.
+            NodeFormat::Extended { .. } => {
+                lumps.push(("SEGS".to_string(), vec![]));
+                lumps.push(("SSECTORS".to_string(), vec![]));
+                lumps.push(("NODES".to_string(), write_extended_nodes(self)?));
+            }
+            NodeFormat::ExtendedGl { .. } => {
+                lumps.push(("SEGS".to_string(), vec![]));
+                lumps.push(("SSECTORS".to_string(), write_extended_nodes(self)?));
+                lumps.push(("NODES".to_string(), vec![]));
+            }
+        }
+
+        Ok(lumps)
+    }
+
+    /// Walks the BSP tree to find the subsector containing the point.
+    ///
+    /// Returns [None] if the map has no nodes.
+    pub fn find_subsector(&self, x: f32, y: f32) -> Option<u32> {
+        let nodes = self.nodes.as_ref()?;
+
+        // A map with a single subsector has no nodes at all.
+        if nodes.is_empty() {
+            return match self.subsectors.as_ref()?.is_empty() {
+                true => None,
+                false => Some(0),
+            };
+        }
+
+        // A path through a valid tree visits each node at most once, so a
+        // longer one means a corrupt tree that loops back on itself.
+        let mut child = NodeChild::Node(nodes.len() as u32 - 1);
+        for _ in 0..=nodes.len() {
+            match child {
+                NodeChild::Subsector(idx) => return Some(idx),
+                NodeChild::Node(idx) => {
+                    let node = nodes.get(idx as usize)?;
+                    child = match node.is_point_on_left(x, y) {
+                        true => node.left_child,
+                        false => node.right_child,
+                    };
+                }
+            }
+        }
+        None
+    }
+
+    /// Visits every subsector in front-to-back order from the viewpoint, by
+    /// walking the near side of each partition first.
+    ///
+    /// Does nothing if the map has no nodes.
+    pub fn for_each_subsector_front_to_back<F: FnMut(u32)>(&self, x: f32, y: f32, mut callback: F) {
+        let nodes = match self.nodes.as_ref() {
+            Some(nodes) => nodes,
+            None => return,
+        };
+
+        if nodes.is_empty() {
+            if self.subsectors.as_ref().is_some_and(|s| !s.is_empty()) {
+                callback(0);
+            }
+            return;
+        }
+
+        // As in find_subsector, visiting more nodes than there are means a
+        // corrupt tree that loops back on itself.
+        let mut nodes_left = nodes.len();
+        let mut stack = vec![NodeChild::Node(nodes.len() as u32 - 1)];
+        while let Some(child) = stack.pop() {
+            match child {
+                NodeChild::Subsector(idx) => callback(idx),
+                NodeChild::Node(idx) => {
+                    let node = match nodes.get(idx as usize) {
+                        Some(node) => node,
+                        None => continue,
+                    };
+                    if nodes_left == 0 {
+                        return;
+                    }
+                    nodes_left -= 1;
+
+                    // Push the far side first, so the near side is popped first.
+                    match node.is_point_on_left(x, y) {
+                        true => {
+                            stack.push(node.right_child);
+                            stack.push(node.left_child);
+                        }
+                        false => {
+                            stack.push(node.left_child);
+                            stack.push(node.right_child);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Reject, WadBuilder};
+
+    /// A 128x128 room split down the middle at x = 64.
+    fn split_room() -> Map {
+        let bounds = |left, right| NodeBounds {
+            top: 128,
+            bottom: 0,
+            left,
+            right,
+        };
+
+        let seg = |start_vertex_idx, end_vertex_idx, linedef_idx| Seg {
+            start_vertex_idx,
+            end_vertex_idx,
+            angle: 0,
+            linedef_idx: Some(linedef_idx),
+            is_left_side: false,
+            offset: 0,
+            partner_seg_idx: None,
+        };
+
+        Map {
+            name: "MAP01".to_string(),
+            things: vec![],
+            sectors: vec![],
+            sidedefs: vec![],
+            linedefs: vec![],
+            vertices: vec![],
+            segs: Some(vec![seg(0, 1, 0), seg(1, 2, 1), seg(2, 3, 2), seg(3, 0, 3)]),
+            subsectors: Some(vec![
+                Subsector {
+                    num_segs: 2,
+                    first_seg_idx: 0,
+                },
+                Subsector {
+                    num_segs: 2,
+                    first_seg_idx: 2,
+                },
+            ]),
+            nodes: Some(vec![Node {
+                x: 64,
+                y: 0,
+                dx: 0,
+                dy: 128,
+                right_bounds: bounds(64, 128),
+                left_bounds: bounds(0, 64),
+                right_child: NodeChild::Subsector(0),
+                left_child: NodeChild::Subsector(1),
+            }]),
+            node_vertices: vec![],
+            node_format: NodeFormat::Vanilla,
+            blockmap: None,
+            reject: Reject::all_visible(0),
+            udmf: None,
+        }
+    }
+
+    #[test]
+    fn parse_write_parse() {
+        let map = split_room();
+
+        let mut builder = WadBuilder::new(false);
+        builder.set_map_from(&map).unwrap();
+
+        let wad = builder.build().unwrap();
+        assert_eq!(
+            wad.lump_names_in_order,
+            vec![
+                "MAP01", "THINGS", "LINEDEFS", "SIDEDEFS", "VERTEXES", "SEGS", "SSECTORS", "NODES",
+                "SECTORS", "REJECT", "BLOCKMAP"
+            ]
+        );
+        assert_eq!(wad.parse_map("MAP01").unwrap(), map);
+    }
+
+    #[test]
+    fn traversal() {
+        let map = split_room();
+
+        assert_eq!(map.find_subsector(100., 10.), Some(0));
+        assert_eq!(map.find_subsector(10., 10.), Some(1));
+        // On the partition line itself.
+        assert_eq!(map.find_subsector(64., 10.), Some(1));
+
+        let mut order = Vec::new();
+        map.for_each_subsector_front_to_back(10., 10., |idx| order.push(idx));
+        assert_eq!(order, vec![1, 0]);
+
+        // A node that's its own child doesn't loop forever.
+        let mut map = split_room();
+        let nodes = map.nodes.as_mut().unwrap();
+        nodes[0].left_child = NodeChild::Node(0);
+        assert_eq!(map.find_subsector(10., 10.), None);
+        let mut order = Vec::new();
+        map.for_each_subsector_front_to_back(10., 10., |idx| order.push(idx));
+        assert_eq!(order, vec![]);
+        // The right side still leads out of the tree.
+        assert_eq!(map.find_subsector(100., 10.), Some(0));
+    }
+}
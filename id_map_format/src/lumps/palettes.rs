@@ -1,8 +1,11 @@
 use crate::{lump_from_namespace, LumpNamespace, Wad, WadError};
 
+/// 256 RGB colors.
+pub type Palette = [(u8, u8, u8); 256];
+
 /// This is a list of all the palettes in the WAD.
 /// The first one, index 0, is the "normal" one except during different game states.
-pub type Palettes = Vec<[(u8, u8, u8); 256]>;
+pub type Palettes = Vec<Palette>;
 
 impl Wad {
     pub fn parse_palettes(&self) -> Result<Palettes, WadError> {
@@ -10,7 +13,10 @@ impl Wad {
         let lump_bytes = lump.bytes();
 
         if lump.size % (256 * 3) != 0 {
-            return Err(WadError::CorruptedLump(lump.name.clone()));
+            return Err(WadError::corrupted(
+                &lump.name,
+                lump.size - lump.size % (256 * 3),
+            ));
         }
 
         let num_palettes = lump.size / (256 * 3);
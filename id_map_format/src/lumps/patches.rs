@@ -1,4 +1,7 @@
-use crate::{lump_from_namespace, Lump, LumpNamespace, Texture, Wad, WadError};
+use crate::{
+    helpers::ByteReader, is_png, lump_from_namespace, Lump, LumpNamespace, Palette, PngImage,
+    Texture, Wad, WadError,
+};
 
 #[derive(Debug)]
 pub struct PatchColumnSpan {
@@ -22,7 +25,25 @@ pub struct Patch {
 }
 
 impl Wad {
+    /// PNG patches are quantized to this WAD's PLAYPAL. PWADs usually rely on
+    /// the IWAD's, which [Self::parse_patch_with_palette] takes instead.
     pub fn parse_patch(&self, patch_name: &str) -> Result<Patch, WadError> {
+        self.parse_patch_inner(patch_name, None)
+    }
+
+    pub fn parse_patch_with_palette(
+        &self,
+        patch_name: &str,
+        palette: &Palette,
+    ) -> Result<Patch, WadError> {
+        self.parse_patch_inner(patch_name, Some(palette))
+    }
+
+    fn parse_patch_inner(
+        &self,
+        patch_name: &str,
+        palette: Option<&Palette>,
+    ) -> Result<Patch, WadError> {
         // DOOM technically never reads the START/END lumps for patches,
         // so they're "not required."
         //
@@ -41,63 +62,86 @@ impl Wad {
                 },
             };
 
-        let lump_bytes = lump.bytes();
+        self.parse_picture_or_png(lump, palette)
+    }
+
+    /// Parses a picture lump, or decodes and quantizes it if it's a PNG.
+    ///
+    /// Without a `palette`, this WAD's first PLAYPAL palette is used.
+    pub(crate) fn parse_picture_or_png(
+        &self,
+        lump: &Lump,
+        palette: Option<&Palette>,
+    ) -> Result<Patch, WadError> {
+        if !is_png(lump.bytes()) {
+            return parse_picture(lump);
+        }
+
+        let image = PngImage::decode(&lump.name, lump.bytes())?;
+        match palette {
+            Some(palette) => Ok(image.to_patch(palette)),
+            None => Ok(image.to_patch(&self.parse_palettes()?[0])),
+        }
+    }
 
-        let width = u16_le!(&lump_bytes[0..2]) as u32;
-        let height = u16_le!(&lump_bytes[2..4]) as u32;
+    pub fn parse_patches_for_texture(&self, texture: &Texture) -> Result<Vec<Patch>, WadError> {
+        let mut patches: Vec<Patch> = Vec::new();
+        for patch_entry in &texture.patch_entry {
+            let patch = self.parse_patch(&patch_entry.patch_name)?;
+            patches.push(patch);
+        }
 
-        let x_center: i16 = i16_le!(&lump_bytes[4..6]);
-        let y_center: i16 = i16_le!(&lump_bytes[6..8]);
+        Ok(patches)
+    }
+}
 
-        let mut columns: Vec<Vec<PatchColumnSpan>> = Vec::with_capacity(width as usize);
+/// Patches, sprites, and other graphics share the same picture format.
+pub(crate) fn parse_picture(lump: &Lump) -> Result<Patch, WadError> {
+    let lump_bytes = lump.bytes();
+    let mut reader = ByteReader::new(&lump.name, lump_bytes);
 
-        for i in 0..(width) {
-            let col_lookup_offset = (8 + i * 4) as usize;
-            let col_lookup =
-                u32_le!(&lump_bytes[col_lookup_offset..col_lookup_offset + 4]) as usize;
+    let width = reader.u16()? as u32;
+    let height = reader.u16()? as u32;
 
-            let mut colspans: Vec<PatchColumnSpan> = Vec::new();
+    let x_center: i16 = reader.i16()?;
+    let y_center: i16 = reader.i16()?;
 
-            let mut curr_offset = col_lookup;
-            loop {
-                let span_bytes = &lump_bytes[curr_offset..];
-                let y_offset = span_bytes[0];
-                if y_offset == 255 {
-                    break;
-                }
+    let mut columns: Vec<Vec<PatchColumnSpan>> = Vec::with_capacity(width as usize);
 
-                let num_palette_indicies = span_bytes[1] as usize;
-                // 2 is a garbage byte.
+    for _ in 0..(width) {
+        let col_lookup = reader.u32()? as usize;
 
-                let palette_indices: Vec<u8> = span_bytes[3..3 + num_palette_indicies].to_vec();
-                colspans.push(PatchColumnSpan {
-                    y_offset: y_offset as u16,
-                    palette_indices,
-                });
+        let mut colspans: Vec<PatchColumnSpan> = Vec::new();
 
-                // Last byte is garbage.
-                curr_offset += 4 + num_palette_indicies;
+        let mut span_reader = ByteReader::new_at(&lump.name, lump_bytes, col_lookup);
+        loop {
+            let y_offset = span_reader.u8()?;
+            if y_offset == 255 {
+                break;
             }
 
-            columns.push(colspans);
-        }
+            let num_palette_indicies = span_reader.u8()? as usize;
+            // 2 is a garbage byte.
+            span_reader.take(1)?;
 
-        Ok(Patch {
-            width,
-            height,
-            x_center,
-            y_center,
-            columns,
-        })
-    }
+            let palette_indices: Vec<u8> = span_reader.take(num_palette_indicies)?.to_vec();
+            colspans.push(PatchColumnSpan {
+                y_offset: y_offset as u16,
+                palette_indices,
+            });
 
-    pub fn parse_patches_for_texture(&self, texture: &Texture) -> Result<Vec<Patch>, WadError> {
-        let mut patches: Vec<Patch> = Vec::new();
-        for patch_entry in &texture.patch_entry {
-            let patch = self.parse_patch(&patch_entry.patch_name)?;
-            patches.push(patch);
+            // Last byte is garbage.
+            span_reader.take(1)?;
         }
 
-        Ok(patches)
+        columns.push(colspans);
     }
+
+    Ok(Patch {
+        width,
+        height,
+        x_center,
+        y_center,
+        columns,
+    })
 }
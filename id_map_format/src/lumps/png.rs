@@ -0,0 +1,531 @@
+use std::{collections::HashMap, io::Read};
+
+use flate2::read::ZlibDecoder;
+
+use crate::{Palette, Patch, PatchColumnSpan, WadError};
+
+pub const PNG_SIGNATURE: &[u8; 8] = b"\x89PNG\r\n\x1a\n";
+
+/// Whether a lump is a PNG rather than one of DOOM's own formats.
+pub fn is_png(bytes: &[u8]) -> bool {
+    bytes.starts_with(PNG_SIGNATURE)
+}
+
+/// A decoded PNG, as true-color pixels.
+///
+/// The renderer only draws palette images, so PNG graphics are quantized to
+/// the PLAYPAL with [Self::to_patch] or [Self::to_palette_indices].
+///
+/// References:
+/// - https://www.w3.org/TR/png/
+/// - https://zdoom.org/wiki/GrAb
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PngImage {
+    pub width: u32,
+    pub height: u32,
+    /// Row-major RGBA.
+    pub rgba: Vec<u8>,
+    /// The offsets from a `grAb` chunk, which are a patch's `x_center` and
+    /// `y_center`.
+    pub grab: Option<(i32, i32)>,
+}
+
+struct Header {
+    width: u32,
+    height: u32,
+    bit_depth: u8,
+    color_type: u8,
+    interlaced: bool,
+}
+
+impl Header {
+    fn channels(&self) -> usize {
+        match self.color_type {
+            2 => 3,
+            4 => 2,
+            6 => 4,
+            _ => 1,
+        }
+    }
+
+    /// The distance between a byte and the same byte of the pixel before it,
+    /// for filtering.
+    fn filter_distance(&self) -> usize {
+        (self.channels() * self.bit_depth as usize).div_ceil(8)
+    }
+
+    fn row_len(&self, width: usize) -> usize {
+        (width * self.channels() * self.bit_depth as usize).div_ceil(8)
+    }
+}
+
+/// The starting pixel and pixel steps of the 7 Adam7 passes, as
+/// `(x, y, dx, dy)`.
+const ADAM7_PASSES: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+impl PngImage {
+    pub fn decode(lump_name: &str, bytes: &[u8]) -> Result<Self, WadError> {
+        if !is_png(bytes) {
+            return Err(WadError::corrupted(lump_name, 0));
+        }
+
+        let mut header = None;
+        let mut palette: Vec<[u8; 4]> = Vec::new();
+        let mut transparent_color: Option<Vec<u16>> = None;
+        let mut grab = None;
+        let mut compressed = Vec::new();
+        let mut data_offset = None;
+
+        // Chunks are a big-endian length, a type, the data, and a CRC.
+        let mut offset = PNG_SIGNATURE.len();
+        loop {
+            let corrupted = || WadError::corrupted(lump_name, offset);
+            let chunk_header = bytes.get(offset..offset + 8).ok_or_else(corrupted)?;
+            let len = u32::from_be_bytes(chunk_header[..4].try_into().unwrap()) as usize;
+            let kind = &chunk_header[4..8];
+            let data = offset
+                .checked_add(8 + len)
+                .and_then(|end| bytes.get(offset + 8..end))
+                .ok_or_else(corrupted)?;
+
+            match kind {
+                b"IHDR" if data.len() >= 13 => {
+                    let header_value = Header {
+                        width: u32::from_be_bytes(data[0..4].try_into().unwrap()),
+                        height: u32::from_be_bytes(data[4..8].try_into().unwrap()),
+                        bit_depth: data[8],
+                        color_type: data[9],
+                        interlaced: data[12] == 1,
+                    };
+                    let valid_depth = match header_value.color_type {
+                        0 => matches!(header_value.bit_depth, 1 | 2 | 4 | 8 | 16),
+                        3 => matches!(header_value.bit_depth, 1 | 2 | 4 | 8),
+                        2 | 4 | 6 => matches!(header_value.bit_depth, 8 | 16),
+                        _ => false,
+                    };
+                    // Big enough for any hi-res texture, small enough not to
+                    // allocate gigabytes for a corrupted header.
+                    let valid_size = (1..=16384).contains(&header_value.width)
+                        && (1..=16384).contains(&header_value.height);
+                    if !valid_depth || !valid_size {
+                        return Err(corrupted());
+                    }
+                    header = Some(header_value);
+                }
+                b"PLTE" => {
+                    palette = data
+                        .chunks_exact(3)
+                        .map(|c| [c[0], c[1], c[2], 0xFF])
+                        .collect();
+                }
+                b"tRNS" => match header.as_ref().map(|header| header.color_type) {
+                    Some(3) => {
+                        for (entry, alpha) in palette.iter_mut().zip(data) {
+                            entry[3] = *alpha;
+                        }
+                    }
+                    Some(0 | 2) => {
+                        let values = data
+                            .chunks_exact(2)
+                            .map(|c| u16::from_be_bytes([c[0], c[1]]));
+                        transparent_color = Some(values.collect());
+                    }
+                    _ => {}
+                },
+                b"grAb" if data.len() >= 8 => {
+                    grab = Some((
+                        i32::from_be_bytes(data[0..4].try_into().unwrap()),
+                        i32::from_be_bytes(data[4..8].try_into().unwrap()),
+                    ));
+                }
+                b"IDAT" => {
+                    data_offset.get_or_insert(offset);
+                    compressed.extend_from_slice(data);
+                }
+                b"IEND" => break,
+                _ => {}
+            }
+
+            offset += 12 + len;
+        }
+
+        let header = header.ok_or_else(|| WadError::corrupted(lump_name, PNG_SIGNATURE.len()))?;
+        let data_offset = data_offset.ok_or_else(|| WadError::corrupted(lump_name, offset))?;
+        let corrupted = || WadError::corrupted(lump_name, data_offset);
+
+        let (width, height) = (header.width as usize, header.height as usize);
+        let passes: &[(usize, usize, usize, usize)] = match header.interlaced {
+            true => &ADAM7_PASSES,
+            false => &[(0, 0, 1, 1)],
+        };
+        let pass_sizes = |&(x0, y0, dx, dy): &(usize, usize, usize, usize)| {
+            let pass_width = width.saturating_sub(x0).div_ceil(dx);
+            let pass_height = height.saturating_sub(y0).div_ceil(dy);
+            (pass_width, pass_height, header.row_len(pass_width))
+        };
+
+        // Each row starts with its filter type byte. Knowing the size up front
+        // means corrupted data can't inflate without bound, and is rejected
+        // before the image is allocated.
+        let expected_len: usize = passes
+            .iter()
+            .map(pass_sizes)
+            .filter(|(pass_width, pass_height, _)| *pass_width > 0 && *pass_height > 0)
+            .map(|(_, pass_height, row_len)| (row_len + 1) * pass_height)
+            .sum();
+        let mut filtered = Vec::new();
+        ZlibDecoder::new(compressed.as_slice())
+            .take(expected_len as u64 + 1)
+            .read_to_end(&mut filtered)
+            .map_err(|_| corrupted())?;
+        if filtered.len() != expected_len {
+            return Err(corrupted());
+        }
+
+        let mut rgba = vec![0u8; width * height * 4];
+
+        let mut filtered = filtered.as_slice();
+        for pass in passes {
+            let (x0, y0, dx, dy) = *pass;
+            let (pass_width, pass_height, row_len) = pass_sizes(pass);
+            if pass_width == 0 || pass_height == 0 {
+                continue;
+            }
+
+            let (pass, rest) = filtered.split_at((row_len + 1) * pass_height);
+            filtered = rest;
+
+            let rows = unfilter(pass, row_len, header.filter_distance()).ok_or_else(corrupted)?;
+            for (pass_y, row) in rows.chunks_exact(row_len).enumerate() {
+                for pass_x in 0..pass_width {
+                    let pixel = read_pixel(&header, row, pass_x, &palette, &transparent_color);
+                    let (x, y) = (x0 + pass_x * dx, y0 + pass_y * dy);
+                    let i = (y * width + x) * 4;
+                    rgba[i..i + 4].copy_from_slice(&pixel);
+                }
+            }
+        }
+
+        Ok(Self {
+            width: header.width,
+            height: header.height,
+            rgba,
+            grab,
+        })
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let i = ((y * self.width + x) * 4) as usize;
+        self.rgba[i..i + 4].try_into().unwrap()
+    }
+
+    /// Maps every pixel to its nearest palette color, ignoring alpha, in
+    /// row-major order. This is how PNG flats are read.
+    pub fn to_palette_indices(&self, palette: &Palette) -> Vec<u8> {
+        let mut quantizer = Quantizer::new(palette);
+        self.rgba
+            .chunks_exact(4)
+            .map(|pixel| quantizer.index([pixel[0], pixel[1], pixel[2]]))
+            .collect()
+    }
+
+    /// Quantizes the image into a patch. Pixels that are less than half
+    /// opaque are left out, like the gaps between a patch's posts.
+    pub fn to_patch(&self, palette: &Palette) -> Patch {
+        let mut quantizer = Quantizer::new(palette);
+
+        let columns = (0..self.width)
+            .map(|x| {
+                let mut spans: Vec<PatchColumnSpan> = Vec::new();
+                let mut in_span = false;
+                for y in 0..self.height {
+                    let [r, g, b, a] = self.pixel(x, y);
+                    if a < 0x80 {
+                        in_span = false;
+                        continue;
+                    }
+
+                    if !in_span {
+                        spans.push(PatchColumnSpan {
+                            y_offset: y as u16,
+                            palette_indices: Vec::new(),
+                        });
+                        in_span = true;
+                    }
+                    let span = spans.last_mut().unwrap();
+                    span.palette_indices.push(quantizer.index([r, g, b]));
+                }
+                spans
+            })
+            .collect();
+
+        let (x_center, y_center) = self.grab.unwrap_or((0, 0));
+        Patch {
+            width: self.width,
+            height: self.height,
+            x_center: x_center.clamp(i16::MIN as i32, i16::MAX as i32) as i16,
+            y_center: y_center.clamp(i16::MIN as i32, i16::MAX as i32) as i16,
+            columns,
+        }
+    }
+}
+
+/// Undoes the per-row filters, returning the rows without their filter bytes.
+fn unfilter(pass: &[u8], row_len: usize, distance: usize) -> Option<Vec<u8>> {
+    let mut rows = Vec::with_capacity(pass.len());
+    let mut previous = vec![0u8; row_len];
+
+    for filtered_row in pass.chunks_exact(row_len + 1) {
+        let (filter, filtered_row) = (filtered_row[0], &filtered_row[1..]);
+        let mut row = filtered_row.to_vec();
+
+        for i in 0..row_len {
+            let left = if i >= distance { row[i - distance] } else { 0 };
+            let up = previous[i];
+            let up_left = if i >= distance {
+                previous[i - distance]
+            } else {
+                0
+            };
+
+            let predicted = match filter {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((left as u16 + up as u16) / 2) as u8,
+                4 => paeth(left, up, up_left),
+                _ => return None,
+            };
+            row[i] = row[i].wrapping_add(predicted);
+        }
+
+        rows.extend_from_slice(&row);
+        previous = row;
+    }
+
+    Some(rows)
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = (
+        (p - a as i16).abs(),
+        (p - b as i16).abs(),
+        (p - c as i16).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// Reads the `x`th pixel of an unfiltered row as RGBA.
+fn read_pixel(
+    header: &Header,
+    row: &[u8],
+    x: usize,
+    palette: &[[u8; 4]],
+    transparent_color: &Option<Vec<u16>>,
+) -> [u8; 4] {
+    let depth = header.bit_depth as usize;
+    let sample = |channel: usize| -> u16 {
+        let index = x * header.channels() + channel;
+        match depth {
+            16 => u16::from_be_bytes([row[index * 2], row[index * 2 + 1]]),
+            8 => row[index] as u16,
+            // Samples smaller than a byte are packed from the high bit down.
+            _ => {
+                let bit = index * depth;
+                let shift = 8 - depth - bit % 8;
+                ((row[bit / 8] >> shift) & ((1 << depth) - 1)) as u16
+            }
+        }
+    };
+    // Scales a sample to 8 bits.
+    let scale = |value: u16| -> u8 {
+        match depth {
+            16 => (value >> 8) as u8,
+            _ => (value as u32 * 255 / ((1 << depth) - 1)) as u8,
+        }
+    };
+    let alpha = |samples: &[u16]| match transparent_color {
+        Some(transparent) if transparent.as_slice() == samples => 0,
+        _ => 0xFF,
+    };
+
+    match header.color_type {
+        0 => {
+            let gray = sample(0);
+            let value = scale(gray);
+            [value, value, value, alpha(&[gray])]
+        }
+        2 => {
+            let rgb = [sample(0), sample(1), sample(2)];
+            [scale(rgb[0]), scale(rgb[1]), scale(rgb[2]), alpha(&rgb)]
+        }
+        3 => palette
+            .get(sample(0) as usize)
+            .copied()
+            .unwrap_or([0, 0, 0, 0xFF]),
+        4 => {
+            let value = scale(sample(0));
+            [value, value, value, scale(sample(1))]
+        }
+        _ => [
+            scale(sample(0)),
+            scale(sample(1)),
+            scale(sample(2)),
+            scale(sample(3)),
+        ],
+    }
+}
+
+/// Finds the nearest palette color, remembering colors it has seen already.
+struct Quantizer<'a> {
+    palette: &'a Palette,
+    cache: HashMap<[u8; 3], u8>,
+}
+
+impl<'a> Quantizer<'a> {
+    fn new(palette: &'a Palette) -> Self {
+        Self {
+            palette,
+            cache: HashMap::new(),
+        }
+    }
+
+    fn index(&mut self, rgb: [u8; 3]) -> u8 {
+        *self
+            .cache
+            .entry(rgb)
+            .or_insert_with(|| nearest_palette_index(self.palette, rgb))
+    }
+}
+
+/// The palette index of the color closest to `rgb`. Ties go to the lowest
+/// index.
+pub fn nearest_palette_index(palette: &Palette, rgb: [u8; 3]) -> u8 {
+    let distance = |(r, g, b): (u8, u8, u8)| {
+        let dr = r as i32 - rgb[0] as i32;
+        let dg = g as i32 - rgb[1] as i32;
+        let db = b as i32 - rgb[2] as i32;
+        dr * dr + dg * dg + db * db
+    };
+
+    palette
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, color)| distance(**color))
+        .map(|(i, _)| i as u8)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use flate2::{write::ZlibEncoder, Compression};
+    use std::io::Write;
+
+    use super::*;
+
+    fn chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+        png.extend((data.len() as u32).to_be_bytes());
+        png.extend(kind);
+        png.extend(data);
+        // Decoding doesn't check CRCs.
+        png.extend([0; 4]);
+    }
+
+    /// Builds a PNG from filtered rows, each starting with its filter type.
+    pub(crate) fn build_png(
+        width: u32,
+        height: u32,
+        bit_depth: u8,
+        color_type: u8,
+        extra_chunks: &[(&[u8; 4], Vec<u8>)],
+        rows: &[u8],
+    ) -> Vec<u8> {
+        let mut png = PNG_SIGNATURE.to_vec();
+        let mut ihdr = Vec::new();
+        ihdr.extend(width.to_be_bytes());
+        ihdr.extend(height.to_be_bytes());
+        ihdr.extend([bit_depth, color_type, 0, 0, 0]);
+        chunk(&mut png, b"IHDR", &ihdr);
+
+        for (kind, data) in extra_chunks {
+            chunk(&mut png, kind, data);
+        }
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(rows).unwrap();
+        chunk(&mut png, b"IDAT", &encoder.finish().unwrap());
+        chunk(&mut png, b"IEND", &[]);
+        png
+    }
+
+    #[test]
+    fn decode_png() {
+        // 2x2 RGBA, with the second row Sub filtered: its second pixel is
+        // stored as the difference from the first.
+        let rows = [
+            0, 255, 0, 0, 255, 0, 255, 0, 255, //
+            1, 0, 0, 255, 255, 0, 0, 0, 0,
+        ];
+        let grab = [5i32.to_be_bytes(), (-3i32).to_be_bytes()].concat();
+        let png = build_png(2, 2, 8, 6, &[(b"grAb", grab)], &rows);
+
+        let image = PngImage::decode("PNGTEST", &png).unwrap();
+        assert_eq!(image.grab, Some((5, -3)));
+        assert_eq!(image.pixel(0, 0), [255, 0, 0, 255]);
+        assert_eq!(image.pixel(1, 0), [0, 255, 0, 255]);
+        assert_eq!(image.pixel(0, 1), [0, 0, 255, 255]);
+        assert_eq!(image.pixel(1, 1), [0, 0, 255, 255]);
+
+        let mut palette = [(0, 0, 0); 256];
+        palette[1] = (250, 0, 0);
+        palette[2] = (0, 250, 0);
+        palette[3] = (0, 0, 250);
+        assert_eq!(image.to_palette_indices(&palette), vec![1, 2, 3, 3]);
+
+        // 2-bit palette image, with a transparent entry.
+        let plte = vec![0, 0, 0, 250, 0, 0, 0, 250, 0];
+        let png = build_png(
+            3,
+            2,
+            2,
+            3,
+            &[(b"PLTE", plte), (b"tRNS", vec![0])],
+            &[0, 0b0001_1000, 0, 0b0001_0000],
+        );
+        let patch = PngImage::decode("PNGTEST", &png)
+            .unwrap()
+            .to_patch(&palette);
+        assert_eq!((patch.width, patch.height), (3, 2));
+        assert!(patch.columns[0].is_empty());
+        assert_eq!(patch.columns[1][0].palette_indices, vec![1, 1]);
+        assert_eq!(patch.columns[2][0].y_offset, 0);
+        assert_eq!(patch.columns[2][0].palette_indices, vec![2]);
+
+        assert!(matches!(
+            PngImage::decode("PNGTEST", &png[..40]),
+            Err(WadError::CorruptedLump { .. })
+        ));
+
+        // A huge header with too little data is rejected before allocating.
+        let png = build_png(16384, 16384, 8, 6, &[], &[0; 64]);
+        assert!(matches!(
+            PngImage::decode("PNGTEST", &png),
+            Err(WadError::CorruptedLump { .. })
+        ));
+    }
+}
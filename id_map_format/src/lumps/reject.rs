@@ -0,0 +1,142 @@
+use crate::Lump;
+
+/// The reject table is a bit matrix of which sectors can't possibly see each
+/// other. It lets the engine skip line-of-sight checks between them.
+///
+/// Bit `from * num_sectors + to` is set when sector `from` can't see sector `to`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reject {
+    pub num_sectors: usize,
+    /// Empty when the map has no REJECT lump, so every sector can see every
+    /// other sector.
+    pub bits: Vec<u8>,
+}
+
+impl Reject {
+    /// A reject table where every sector can see every other sector.
+    ///
+    /// This is what we use when the map's REJECT lump is too short.
+    pub fn all_visible(num_sectors: usize) -> Self {
+        Self {
+            num_sectors,
+            bits: vec![0; Self::byte_len(num_sectors)],
+        }
+    }
+
+    /// Returns whether sector `from` may be able to see sector `to`.
+    ///
+    /// Sectors outside the table are assumed to be visible.
+    pub fn can_see(&self, from: usize, to: usize) -> bool {
+        if from >= self.num_sectors || to >= self.num_sectors {
+            return true;
+        }
+
+        let bit_idx = from * self.num_sectors + to;
+        self.bits
+            .get(bit_idx / 8)
+            .is_none_or(|byte| byte & (1 << (bit_idx % 8)) == 0)
+    }
+
+    pub fn set_can_see(&mut self, from: usize, to: usize, can_see: bool) {
+        if from >= self.num_sectors || to >= self.num_sectors {
+            return;
+        }
+
+        // A missing table is filled in before it's changed.
+        self.bits.resize(Self::byte_len(self.num_sectors), 0);
+        let bit_idx = from * self.num_sectors + to;
+        if can_see {
+            self.bits[bit_idx / 8] &= !(1 << (bit_idx % 8));
+        } else {
+            self.bits[bit_idx / 8] |= 1 << (bit_idx % 8);
+        }
+    }
+
+    fn byte_len(num_sectors: usize) -> usize {
+        (num_sectors * num_sectors).div_ceil(8)
+    }
+}
+
+/// References:
+/// - "The Unofficial Doom Specs": `docs/dmsp1666.txt`, 4-9.
+/// - https://doomwiki.org/wiki/Reject
+pub(crate) fn parse_reject(lump: Option<&Lump>, num_sectors: usize) -> Reject {
+    let byte_len = Reject::byte_len(num_sectors);
+
+    // Vanilla reads past the end of a short REJECT lump into whatever happens
+    // to be in memory. We treat it like an empty one instead.
+    match lump {
+        Some(lump) if lump.size >= byte_len => Reject {
+            num_sectors,
+            bits: lump.bytes()[..byte_len].to_vec(),
+        },
+        Some(_) => Reject::all_visible(num_sectors),
+        None => Reject {
+            num_sectors,
+            bits: vec![],
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Linedef, Map, NodeFormat, Sector, WadBuilder};
+
+    fn sector() -> Sector {
+        Sector {
+            floor_height: 0,
+            ceiling_height: 128,
+            floor_flat: "FLOOR4_8".to_string(),
+            ceiling_flat: "CEIL3_5".to_string(),
+            light_level: 160,
+            special_type: 0,
+            sector_tag: 0,
+        }
+    }
+
+    #[test]
+    fn parse_write_parse() {
+        let mut map = Map {
+            name: "MAP01".to_string(),
+            things: vec![],
+            sectors: vec![sector(), sector(), sector()],
+            sidedefs: vec![],
+            linedefs: Vec::<Linedef>::new(),
+            vertices: vec![],
+            segs: None,
+            subsectors: None,
+            nodes: None,
+            node_vertices: vec![],
+            node_format: NodeFormat::Vanilla,
+            blockmap: None,
+            reject: Reject::all_visible(3),
+            udmf: None,
+        };
+
+        // 3 sectors need 9 bits, so the table spans 2 bytes.
+        assert_eq!(map.reject.bits.len(), 2);
+
+        map.reject.set_can_see(0, 2, false);
+        map.reject.set_can_see(2, 2, false);
+        assert!(!map.reject.can_see(0, 2));
+        assert!(map.reject.can_see(2, 0));
+        assert!(map.reject.can_see(7, 0));
+
+        let mut builder = WadBuilder::new(false);
+        builder.set_map_from(&map).unwrap();
+        let parsed = builder.build().unwrap().parse_map("MAP01").unwrap();
+        assert_eq!(parsed.reject, map.reject);
+
+        // A truncated REJECT lump is ignored.
+        builder
+            .replace_lump(
+                &crate::LumpNamespace::Map("MAP01".to_string()),
+                "REJECT",
+                vec![0xFF],
+            )
+            .unwrap();
+        let parsed = builder.build().unwrap().parse_map("MAP01").unwrap();
+        assert_eq!(parsed.reject, Reject::all_visible(3));
+    }
+}
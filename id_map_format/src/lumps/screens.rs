@@ -0,0 +1,177 @@
+use crate::{lump_from_namespace, LumpNamespace, Patch, PatchColumnSpan, Wad, WadError};
+
+pub const RAW_SCREEN_WIDTH: u32 = 320;
+pub const RAW_SCREEN_HEIGHT: u32 = 200;
+const RAW_SCREEN_SIZE: usize = (RAW_SCREEN_WIDTH * RAW_SCREEN_HEIGHT) as usize;
+
+/// A full-screen graphic stored as plain palette indices, like Heretic's
+/// `TITLE` and `HELP1`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawScreen {
+    pub width: u32,
+    pub height: u32,
+    /// Row-major, unlike [Patch].
+    pub pixels: Vec<u8>,
+}
+
+/// A graphic lump, in whichever format it was stored in.
+#[derive(Debug)]
+pub enum Graphic {
+    Patch(Patch),
+    Raw(RawScreen),
+}
+
+impl RawScreen {
+    pub fn pixel(&self, x: u32, y: u32) -> u8 {
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    /// Converts the screen to a patch with one solid span per column, so it can
+    /// be drawn like any other graphic.
+    pub fn to_patch(&self) -> Patch {
+        let columns = (0..self.width)
+            .map(|x| {
+                vec![PatchColumnSpan {
+                    y_offset: 0,
+                    palette_indices: (0..self.height).map(|y| self.pixel(x, y)).collect(),
+                }]
+            })
+            .collect();
+
+        Patch {
+            width: self.width,
+            height: self.height,
+            x_center: 0,
+            y_center: 0,
+            columns,
+        }
+    }
+}
+
+impl Graphic {
+    pub fn width(&self) -> u32 {
+        match self {
+            Graphic::Patch(patch) => patch.width,
+            Graphic::Raw(screen) => screen.width,
+        }
+    }
+
+    pub fn height(&self) -> u32 {
+        match self {
+            Graphic::Patch(patch) => patch.height,
+            Graphic::Raw(screen) => screen.height,
+        }
+    }
+
+    pub fn into_patch(self) -> Patch {
+        match self {
+            Graphic::Patch(patch) => patch,
+            Graphic::Raw(screen) => screen.to_patch(),
+        }
+    }
+}
+
+/// Whether a lump looks like a raw 320x200 screen rather than a picture.
+///
+/// Raw screens are exactly 64000 bytes, which a picture can be too, so we
+/// also check that the bytes don't make sense as a picture header.
+pub fn is_raw_screen(bytes: &[u8]) -> bool {
+    bytes.len() == RAW_SCREEN_SIZE && !is_plausible_picture(bytes)
+}
+
+fn is_plausible_picture(bytes: &[u8]) -> bool {
+    let u16_at = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]) as usize;
+    if bytes.len() < 8 {
+        return false;
+    }
+
+    let (width, height) = (u16_at(0), u16_at(2));
+    let columns_start = 8 + width * 4;
+    if width == 0 || height == 0 || width > 4096 || height > 4096 || columns_start > bytes.len() {
+        return false;
+    }
+
+    (0..width).all(|x| {
+        let offset = 8 + x * 4;
+        let column = u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap()) as usize;
+        (columns_start..bytes.len()).contains(&column)
+    })
+}
+
+impl Wad {
+    /// Parses a graphic like `TITLEPIC`, `HELP1` or `INTERPIC` that may be a
+    /// picture or a raw screen.
+    ///
+    /// References:
+    /// - https://doomwiki.org/wiki/Raw_image
+    pub fn parse_graphic(&self, lump_name: &str) -> Result<Graphic, WadError> {
+        let lump_name = lump_name.to_uppercase();
+        let lump = match lump_from_namespace(&LumpNamespace::Global, &lump_name, self) {
+            Ok(lump) => lump,
+            Err(_) => lump_from_namespace(&LumpNamespace::Patch, &lump_name, self)?,
+        };
+
+        let bytes = lump.bytes();
+        if is_raw_screen(bytes) {
+            return Ok(Graphic::Raw(RawScreen {
+                width: RAW_SCREEN_WIDTH,
+                height: RAW_SCREEN_HEIGHT,
+                pixels: bytes.to_vec(),
+            }));
+        }
+
+        Ok(Graphic::Patch(self.parse_picture_or_png(lump, None)?))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::WadBuilder;
+
+    /// A picture with the same single span in every column.
+    pub(crate) fn tiny_picture(width: u16, indices: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend(width.to_le_bytes());
+        bytes.extend((indices.len() as u16).to_le_bytes());
+        bytes.extend([0; 4]);
+        let column_start = 8 + width as u32 * 4;
+        for _ in 0..width {
+            bytes.extend(column_start.to_le_bytes());
+        }
+        bytes.extend([0, indices.len() as u8, 0]);
+        bytes.extend(indices);
+        bytes.extend([0, 0xFF]);
+        bytes
+    }
+
+    #[test]
+    fn raw_screens() {
+        let raw: Vec<u8> = (0..RAW_SCREEN_SIZE).map(|i| (i % 251) as u8).collect();
+        // A picture padded out to the same size must still be a picture.
+        let mut padded = tiny_picture(1, &[7, 8]);
+        padded.resize(RAW_SCREEN_SIZE, 0);
+
+        let mut builder = WadBuilder::new(false);
+        builder
+            .add_lump(&LumpNamespace::Global, "TITLE", raw)
+            .unwrap()
+            .add_lump(&LumpNamespace::Global, "TITLEPIC", padded)
+            .unwrap();
+        let wad = builder.build().unwrap();
+
+        let Graphic::Raw(screen) = wad.parse_graphic("TITLE").unwrap() else {
+            panic!("expected a raw screen");
+        };
+        assert_eq!((screen.width, screen.height), (320, 200));
+        assert_eq!(screen.pixel(1, 1), (321 % 251) as u8);
+
+        let patch = screen.to_patch();
+        assert_eq!(patch.columns.len(), 320);
+        assert_eq!(patch.columns[1][0].palette_indices[1], (321 % 251) as u8);
+
+        let graphic = wad.parse_graphic("titlepic").unwrap();
+        assert!(matches!(graphic, Graphic::Patch(_)));
+        assert_eq!((graphic.width(), graphic.height()), (1, 2));
+    }
+}
@@ -0,0 +1,268 @@
+use crate::{helpers::ByteReader, lump_from_namespace, LumpNamespace, Wad, WadError};
+
+/// A digital sound effect, like `DSPISTOL`, in the DMX format.
+///
+/// References:
+/// - https://doomwiki.org/wiki/Sound
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DmxSound {
+    pub sample_rate: u16,
+    /// Unsigned 8-bit mono samples, without the padding DMX adds around them.
+    pub samples: Vec<u8>,
+}
+
+/// A PC speaker sound effect, like `DPPISTOL`.
+///
+/// References:
+/// - https://doomwiki.org/wiki/PC_speaker_sound_effects
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PcSpeakerSound {
+    /// One tone per 1/140th of a second. 0 is silence.
+    pub tones: Vec<u8>,
+}
+
+/// A decoded sound lump.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Sound {
+    Digital(DmxSound),
+    PcSpeaker(PcSpeakerSound),
+}
+
+const DMX_FORMAT: u16 = 3;
+const PC_SPEAKER_FORMAT: u16 = 0;
+
+/// DMX pads samples with 16 bytes on each side, which are never played.
+const DMX_PADDING: usize = 16;
+
+/// PC speaker tones are played at 140 Hz.
+pub const PC_SPEAKER_TONE_RATE: u32 = 140;
+
+/// The frequency of the PC's programmable interval timer, which is divided to
+/// get the speaker's tone.
+const PIT_FREQUENCY: f32 = 1_193_181.0;
+
+impl DmxSound {
+    pub fn parse(lump_name: &str, bytes: &[u8]) -> Result<Self, WadError> {
+        let mut reader = ByteReader::new(lump_name, bytes);
+        if reader.u16()? != DMX_FORMAT {
+            return Err(WadError::corrupted(lump_name, 0));
+        }
+
+        let sample_rate = reader.u16()?;
+        let sample_count = reader.u32()? as usize;
+        let samples = reader.take(sample_count)?;
+
+        // Lumps made by other tools sometimes leave the padding out.
+        let samples = match samples.len() >= DMX_PADDING * 2 {
+            true => &samples[DMX_PADDING..samples.len() - DMX_PADDING],
+            false => samples,
+        };
+
+        Ok(Self {
+            sample_rate,
+            samples: samples.to_vec(),
+        })
+    }
+
+    pub fn duration_secs(&self) -> f32 {
+        self.samples.len() as f32 / self.sample_rate.max(1) as f32
+    }
+
+    /// An 8-bit mono WAV file.
+    pub fn to_wav(&self) -> Vec<u8> {
+        write_wav(self.sample_rate as u32, &self.samples)
+    }
+}
+
+impl PcSpeakerSound {
+    pub fn parse(lump_name: &str, bytes: &[u8]) -> Result<Self, WadError> {
+        let mut reader = ByteReader::new(lump_name, bytes);
+        if reader.u16()? != PC_SPEAKER_FORMAT {
+            return Err(WadError::corrupted(lump_name, 0));
+        }
+
+        let tone_count = reader.u16()? as usize;
+        let tones = reader.take(tone_count)?;
+
+        Ok(Self {
+            tones: tones.to_vec(),
+        })
+    }
+
+    /// The frequency a tone plays at, in Hz, or [None] for silence.
+    ///
+    /// DMX looks tones up in a table of timer divisors that goes up in
+    /// quarter-tones from about 175 Hz. This rebuilds it from that pattern
+    /// rather than storing it.
+    pub fn frequency(tone: u8) -> Option<f32> {
+        if tone == 0 {
+            return None;
+        }
+
+        let frequency = 175.0 * 2f32.powf((tone.min(127) - 1) as f32 / 24.0);
+        // The speaker can only play frequencies the timer divides into evenly.
+        let divisor = (PIT_FREQUENCY / frequency).round();
+        Some(PIT_FREQUENCY / divisor)
+    }
+
+    pub fn duration_secs(&self) -> f32 {
+        self.tones.len() as f32 / PC_SPEAKER_TONE_RATE as f32
+    }
+
+    /// Renders the tones as a square wave, as unsigned 8-bit mono samples.
+    pub fn render(&self, sample_rate: u32) -> Vec<u8> {
+        let sample_count = self.tones.len() * sample_rate as usize / PC_SPEAKER_TONE_RATE as usize;
+        let mut samples = Vec::with_capacity(sample_count);
+
+        // Kept across tones, so changing the pitch doesn't click.
+        let mut phase = 0.0f32;
+        for i in 0..sample_count {
+            let tone = self.tones[i * PC_SPEAKER_TONE_RATE as usize / sample_rate as usize];
+            let sample = match Self::frequency(tone) {
+                Some(frequency) => {
+                    phase = (phase + frequency / sample_rate as f32).fract();
+                    if phase < 0.5 {
+                        0xC0
+                    } else {
+                        0x40
+                    }
+                }
+                None => 0x80,
+            };
+            samples.push(sample);
+        }
+
+        samples
+    }
+
+    /// An 8-bit mono WAV file of [Self::render].
+    pub fn to_wav(&self, sample_rate: u32) -> Vec<u8> {
+        write_wav(sample_rate, &self.render(sample_rate))
+    }
+}
+
+impl Sound {
+    /// Tells the formats apart by the format number both start with.
+    pub fn parse(lump_name: &str, bytes: &[u8]) -> Result<Self, WadError> {
+        let mut reader = ByteReader::new(lump_name, bytes);
+        match reader.u16()? {
+            DMX_FORMAT => Ok(Sound::Digital(DmxSound::parse(lump_name, bytes)?)),
+            PC_SPEAKER_FORMAT => Ok(Sound::PcSpeaker(PcSpeakerSound::parse(lump_name, bytes)?)),
+            _ => Err(WadError::corrupted(lump_name, 0)),
+        }
+    }
+
+    /// PC speaker sounds are rendered at 11025 Hz, the lowest DMX rate.
+    pub fn to_wav(&self) -> Vec<u8> {
+        match self {
+            Sound::Digital(sound) => sound.to_wav(),
+            Sound::PcSpeaker(sound) => sound.to_wav(11025),
+        }
+    }
+}
+
+/// Writes unsigned 8-bit mono samples as a WAV file.
+fn write_wav(sample_rate: u32, samples: &[u8]) -> Vec<u8> {
+    // Chunks are padded to an even length.
+    let padding = samples.len() % 2;
+
+    let mut wav = Vec::with_capacity(44 + samples.len() + padding);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&((36 + samples.len() + padding) as u32).to_le_bytes());
+    wav.extend_from_slice(b"WAVE");
+
+    wav.extend_from_slice(b"fmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    // PCM, 1 channel.
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&sample_rate.to_le_bytes());
+    // Bytes per second, bytes per sample, bits per sample.
+    wav.extend_from_slice(&sample_rate.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&8u16.to_le_bytes());
+
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&(samples.len() as u32).to_le_bytes());
+    wav.extend_from_slice(samples);
+    wav.resize(wav.len() + padding, 0);
+    wav
+}
+
+impl Wad {
+    /// Parses a sound lump, like `DSPISTOL` or `DPPISTOL`.
+    pub fn parse_sound(&self, lump_name: &str) -> Result<Sound, WadError> {
+        let lump = lump_from_namespace(&LumpNamespace::Global, &lump_name.to_uppercase(), self)?;
+        Sound::parse(&lump.name, lump.bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::WadBuilder;
+
+    #[test]
+    fn parse_sounds() {
+        let mut dmx = Vec::new();
+        dmx.extend(DMX_FORMAT.to_le_bytes());
+        dmx.extend(11025u16.to_le_bytes());
+        dmx.extend(35u32.to_le_bytes());
+        dmx.extend([0x80; DMX_PADDING]);
+        dmx.extend([0x00, 0x80, 0xFF]);
+        dmx.extend([0x80; DMX_PADDING]);
+
+        let mut pc_speaker = Vec::new();
+        pc_speaker.extend(PC_SPEAKER_FORMAT.to_le_bytes());
+        pc_speaker.extend(3u16.to_le_bytes());
+        pc_speaker.extend([1, 0, 25]);
+
+        let mut builder = WadBuilder::new(false);
+        builder
+            .add_lump(&LumpNamespace::Global, "DSTEST", dmx.clone())
+            .unwrap()
+            .add_lump(&LumpNamespace::Global, "DPTEST", pc_speaker)
+            .unwrap()
+            .add_lump(&LumpNamespace::Global, "DSBAD", vec![1, 0, 0, 0])
+            .unwrap();
+        let wad = builder.build().unwrap();
+
+        let Sound::Digital(sound) = wad.parse_sound("DSTEST").unwrap() else {
+            panic!("expected a digital sound");
+        };
+        assert_eq!(sound.sample_rate, 11025);
+        assert_eq!(sound.samples, vec![0x00, 0x80, 0xFF]);
+
+        let wav = sound.to_wav();
+        assert_eq!(&wav[..4], b"RIFF");
+        assert_eq!(u32::from_le_bytes(wav[4..8].try_into().unwrap()), 40);
+        assert_eq!(&wav[8..16], b"WAVEfmt ");
+        assert_eq!(u32::from_le_bytes(wav[24..28].try_into().unwrap()), 11025);
+        assert_eq!(&wav[36..40], b"data");
+        assert_eq!(u32::from_le_bytes(wav[40..44].try_into().unwrap()), 3);
+        assert_eq!(&wav[44..], &[0x00, 0x80, 0xFF, 0x00]);
+
+        let Sound::PcSpeaker(sound) = wad.parse_sound("DPTEST").unwrap() else {
+            panic!("expected a PC speaker sound");
+        };
+        assert_eq!(sound.tones, vec![1, 0, 25]);
+        assert!((PcSpeakerSound::frequency(1).unwrap() - 175.0).abs() < 0.1);
+        // 24 quarter-tones up is an octave.
+        assert!((PcSpeakerSound::frequency(25).unwrap() - 350.0).abs() < 0.1);
+
+        let samples = sound.render(1400);
+        assert_eq!(samples.len(), 30);
+        assert!(samples[10..20].iter().all(|&s| s == 0x80));
+        assert!(samples[..10].iter().any(|&s| s != 0x80));
+
+        assert!(matches!(
+            wad.parse_sound("DSBAD"),
+            Err(WadError::CorruptedLump { offset: 0, .. })
+        ));
+        // A sample count past the end of the lump.
+        assert!(matches!(
+            DmxSound::parse("DSTEST", &dmx[..20]),
+            Err(WadError::CorruptedLump { offset: 8, .. })
+        ));
+    }
+}
@@ -0,0 +1,213 @@
+use std::collections::BTreeMap;
+
+use crate::{lump_from_namespace, LumpNamespace, Palette, Patch, Wad, WadError};
+
+/// The lump to draw for one rotation of a frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpriteRotation {
+    pub lump_name: String,
+    /// Whether the lump is drawn flipped horizontally, for the second half of
+    /// a pair like `TROOA2A8`.
+    pub mirrored: bool,
+}
+
+/// A frame of a sprite, seen from 8 angles.
+///
+/// Index 0 is the front of the thing, and the rest go counter-clockwise
+/// around it in 45° steps: rotation `1` in a lump name is index 0, `8` is
+/// index 7. Rotation `0` fills all of them with the same lump.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SpriteFrame {
+    pub rotations: [Option<SpriteRotation>; 8],
+}
+
+impl SpriteFrame {
+    /// Whether the frame looks different depending on the viewing angle.
+    pub fn is_rotated(&self) -> bool {
+        self.rotations.iter().any(|r| r != &self.rotations[0])
+    }
+
+    /// Picks the rotation the way vanilla does.
+    ///
+    /// `viewer_to_thing` is the angle of the line from the viewer to the
+    /// thing, and `thing_angle` is where the thing is facing. Both are in
+    /// degrees, counter-clockwise from east, like [crate::Thing::angle].
+    pub fn rotation_for_angle(
+        &self,
+        viewer_to_thing: f32,
+        thing_angle: f32,
+    ) -> Option<&SpriteRotation> {
+        // Each rotation covers 45°, centered on its angle. Looking at the
+        // front of a thing means looking in the opposite direction, hence 180°.
+        let relative = (viewer_to_thing - thing_angle + 180.0 + 22.5).rem_euclid(360.0);
+        let idx = ((relative / 45.0) as usize).min(7);
+        self.rotations[idx].as_ref()
+    }
+}
+
+/// All the frames of a sprite, like `TROO`, keyed by frame letter.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SpriteDef {
+    pub name: String,
+    pub frames: BTreeMap<char, SpriteFrame>,
+}
+
+/// Sprites by their 4-character name.
+pub type SpriteDefs = BTreeMap<String, SpriteDef>;
+
+/// Splits a sprite lump name into its sprite name, and up to two
+/// `(frame, rotation)` pairs.
+///
+/// Frames go from `A` up to `]`, the 29 characters vanilla allows.
+fn parse_sprite_lump_name(lump_name: &str) -> Option<(&str, Vec<(char, u8)>)> {
+    if !lump_name.is_ascii() || !matches!(lump_name.len(), 6 | 8) {
+        return None;
+    }
+
+    let bytes = lump_name.as_bytes();
+    let frame_rotation = |i: usize| {
+        let frame = bytes[i] as char;
+        let rotation = (bytes[i + 1] as char).to_digit(10)?;
+        (('A'..=']').contains(&frame) && rotation <= 8).then_some((frame, rotation as u8))
+    };
+
+    let mut pairs = vec![frame_rotation(4)?];
+    if bytes.len() == 8 {
+        pairs.push(frame_rotation(6)?);
+    }
+    Some((&lump_name[..4], pairs))
+}
+
+impl SpriteDef {
+    fn install(&mut self, lump_name: &str, frame: char, rotation: u8, mirrored: bool) {
+        let sprite_rotation = Some(SpriteRotation {
+            lump_name: lump_name.to_string(),
+            mirrored,
+        });
+
+        let sprite_frame = self.frames.entry(frame).or_default();
+        match rotation {
+            0 => sprite_frame.rotations = std::array::from_fn(|_| sprite_rotation.clone()),
+            r => sprite_frame.rotations[(r - 1) as usize] = sprite_rotation,
+        }
+    }
+}
+
+impl Wad {
+    /// Groups the lumps between `S_START` and `S_END` into sprites.
+    ///
+    /// Lumps that don't follow the naming scheme, like `TROOA`, are skipped.
+    ///
+    /// References:
+    /// - https://doomwiki.org/wiki/Sprite
+    pub fn parse_sprite_defs(&self) -> SpriteDefs {
+        let mut sprite_defs = SpriteDefs::new();
+        self.extend_sprite_defs(&mut sprite_defs);
+        sprite_defs
+    }
+
+    /// Adds this WAD's sprites on top of `sprite_defs`, so a PWAD can replace
+    /// single frames or rotations of IWAD sprites.
+    pub fn extend_sprite_defs(&self, sprite_defs: &mut SpriteDefs) {
+        let lumps = self
+            .lumps_in_order
+            .iter()
+            .filter(|lump| lump.namespace == LumpNamespace::Sprite);
+
+        for lump in lumps {
+            let Some((name, pairs)) = parse_sprite_lump_name(&lump.name) else {
+                continue;
+            };
+
+            let sprite_def = sprite_defs
+                .entry(name.to_string())
+                .or_insert_with(|| SpriteDef {
+                    name: name.to_string(),
+                    frames: BTreeMap::new(),
+                });
+
+            for (i, (frame, rotation)) in pairs.into_iter().enumerate() {
+                sprite_def.install(&lump.name, frame, rotation, i == 1);
+            }
+        }
+    }
+
+    /// Sprites are stored as pictures, like patches, and can be PNGs too.
+    pub fn parse_sprite(&self, lump_name: &str) -> Result<Patch, WadError> {
+        let lump = lump_from_namespace(&LumpNamespace::Sprite, &lump_name.to_uppercase(), self)?;
+        self.parse_picture_or_png(lump, None)
+    }
+
+    /// Like [Self::parse_patch_with_palette], for sprites.
+    pub fn parse_sprite_with_palette(
+        &self,
+        lump_name: &str,
+        palette: &Palette,
+    ) -> Result<Patch, WadError> {
+        let lump = lump_from_namespace(&LumpNamespace::Sprite, &lump_name.to_uppercase(), self)?;
+        self.parse_picture_or_png(lump, Some(palette))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::WadBuilder;
+
+    #[test]
+    fn sprite_frames_and_rotations() {
+        let mut builder = WadBuilder::new(false);
+        for name in [
+            "TROOA1", "TROOA2A8", "TROOA3A7", "TROOA4A6", "TROOA5", "TROOB0", "BAR1A0", "TROOZ",
+        ] {
+            builder
+                .add_lump(&LumpNamespace::Sprite, name, vec![])
+                .unwrap();
+        }
+        let wad = builder.build().unwrap();
+
+        let sprite_defs = wad.parse_sprite_defs();
+        assert_eq!(sprite_defs.keys().collect::<Vec<_>>(), vec!["BAR1", "TROO"]);
+
+        let troo = &sprite_defs["TROO"];
+        assert_eq!(troo.frames.keys().collect::<Vec<_>>(), vec![&'A', &'B']);
+
+        let a = &troo.frames[&'A'];
+        assert!(a.is_rotated());
+        let lump = |idx: usize| {
+            let rotation = a.rotations[idx].as_ref().unwrap();
+            (rotation.lump_name.as_str(), rotation.mirrored)
+        };
+        assert_eq!(lump(0), ("TROOA1", false));
+        assert_eq!(lump(1), ("TROOA2A8", false));
+        assert_eq!(lump(7), ("TROOA2A8", true));
+        assert_eq!(lump(5), ("TROOA4A6", true));
+        assert!(!troo.frames[&'B'].is_rotated());
+
+        // A thing facing east, seen from the east, shows its front.
+        let front = a.rotation_for_angle(180.0, 0.0).unwrap();
+        assert_eq!(front.lump_name, "TROOA1");
+        // Seen from the north, it shows its left side (rotation 3).
+        let side = a.rotation_for_angle(270.0, 0.0).unwrap();
+        assert_eq!(side.lump_name, "TROOA3A7");
+        // Seen from behind.
+        let back = a.rotation_for_angle(0.0, 0.0).unwrap();
+        assert_eq!(back.lump_name, "TROOA5");
+
+        // A PWAD can replace a single rotation.
+        let mut pwad = WadBuilder::new(false);
+        pwad.add_lump(&LumpNamespace::Sprite, "TROOA5", vec![])
+            .unwrap()
+            .add_lump(&LumpNamespace::Sprite, "TROOB1", vec![])
+            .unwrap();
+        let mut merged = sprite_defs.clone();
+        pwad.build().unwrap().extend_sprite_defs(&mut merged);
+        assert_eq!(
+            merged["TROO"].frames[&'A'],
+            sprite_defs["TROO"].frames[&'A']
+        );
+        let b = &merged["TROO"].frames[&'B'];
+        assert_eq!(b.rotations[0].as_ref().unwrap().lump_name, "TROOB1");
+        assert_eq!(b.rotations[1].as_ref().unwrap().lump_name, "TROOB0");
+    }
+}
@@ -1,6 +1,9 @@
 use indexmap::IndexMap;
 
-use crate::{helpers::parse_bytes_cstr, lump_from_namespace, Lump, LumpNamespace, Wad, WadError};
+use crate::{
+    helpers::{parse_name_at, ByteReader},
+    lump_from_namespace, Lump, LumpNamespace, Wad, WadError,
+};
 
 #[derive(Debug)]
 pub struct TexturePatchEntry {
@@ -28,26 +31,20 @@ impl Wad {
         let lump = lump_from_namespace(&LumpNamespace::Global, "PNAMES", self)?;
         let lump_bytes = lump.bytes();
 
+        // The patch count comes first, so that's where we report a mismatch.
         if lump.size % 8 != 4 {
-            return Err(WadError::CorruptedLump(lump.name.clone()));
+            return Err(WadError::corrupted(&lump.name, 0));
         }
 
         let num_patch_names = u32_le!(lump_bytes[0..4]) as usize;
         if lump.size != num_patch_names * 8 + 4 {
-            return Err(WadError::CorruptedLump(lump.name.clone()));
+            return Err(WadError::corrupted(&lump.name, 0));
         }
 
         let mut patch_names: Vec<String> = Vec::new();
         for i in 0..num_patch_names {
             let patch_name_offset = 4 + i * 8;
-            let name = match parse_bytes_cstr(&lump_bytes[patch_name_offset..patch_name_offset + 8])
-            {
-                Ok(middle_texture) => match middle_texture.to_str() {
-                    Ok(middle_texture) => middle_texture.to_string(),
-                    Err(_) => return Err(WadError::CorruptedString),
-                },
-                Err(_) => return Err(WadError::CorruptedBytes),
-            };
+            let name = parse_name_at(&lump.name, lump_bytes, patch_name_offset)?;
 
             patch_names.push(name);
         }
@@ -80,44 +77,41 @@ impl Wad {
             let lump_bytes = lump.bytes();
             let is_priority = lump.name == "TEXTURE1";
 
-            let num_textures = u32_le!(&lump_bytes[0..4]) as usize;
-            let texture_offsets = &lump_bytes[4..4 + num_textures * 4];
+            let num_textures = ByteReader::new(&lump.name, lump_bytes).u32()? as usize;
 
             for i in 0..num_textures {
-                let texture_offset = u32_le!(&texture_offsets[i * 4..i * 4 + 4]) as usize;
-                let texture_bytes = &lump_bytes[texture_offset..];
+                let texture_offset =
+                    ByteReader::new_at(&lump.name, lump_bytes, 4 + i * 4).u32()? as usize;
+                let mut texture_reader = ByteReader::new_at(&lump.name, lump_bytes, texture_offset);
 
-                let name = match parse_bytes_cstr(&texture_bytes[0..8]) {
-                    Ok(name) => match name.to_str() {
-                        Ok(name) => name.to_string(),
-                        Err(_) => return Err(WadError::CorruptedString),
-                    },
-                    Err(_) => return Err(WadError::CorruptedBytes),
-                };
+                let name = parse_name_at(&lump.name, lump_bytes, texture_reader.offset())?;
+                texture_reader.take(8)?;
 
                 // 8..10 and 10..12 are unused.
+                texture_reader.take(4)?;
 
-                let width = u16_le!(&texture_bytes[12..14]);
-                let height = u16_le!(&texture_bytes[14..16]);
+                let width = texture_reader.u16()?;
+                let height = texture_reader.u16()?;
 
                 // 16..20 is unused—and removed in the Strife specification.
+                texture_reader.take(4)?;
 
-                let num_patches = u16_le!(&texture_bytes[20..22]) as usize;
+                let num_patches = texture_reader.u16()? as usize;
                 let mut patch_entry = Vec::new();
 
-                for j in 0..num_patches {
-                    let patch_bytes = &texture_bytes[22 + (j * 10)..22 + (j * 10 + 10)];
-
-                    let x_offset = i16_le!(&patch_bytes[0..2]);
-                    let y_offset = i16_le!(&patch_bytes[2..4]);
+                for _ in 0..num_patches {
+                    let x_offset = texture_reader.i16()?;
+                    let y_offset = texture_reader.i16()?;
 
-                    let patch_idx = u16_le!(&patch_bytes[4..6]) as usize;
+                    let patch_idx_offset = texture_reader.offset();
+                    let patch_idx = texture_reader.u16()? as usize;
                     let patch_name = match patch_names.get(patch_idx) {
                         Some(patch) => patch.clone(),
-                        None => return Err(WadError::TexturePatchNotFound),
+                        None => return Err(WadError::corrupted(&lump.name, patch_idx_offset)),
                     };
 
                     // 6..10 is unused—and removed in the Strife specification.
+                    texture_reader.take(4)?;
 
                     patch_entry.push(TexturePatchEntry {
                         x_offset,
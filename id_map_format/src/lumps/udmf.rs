@@ -0,0 +1,824 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    write_blockmap, write_extended_nodes, Linedef, Lump, Map, MapGeometry, NodeFormat, Sector,
+    Sidedef, Thing, Vertex, WadError,
+};
+
+/// A value assigned to a key in TEXTMAP.
+#[derive(Debug, Clone, PartialEq)]
+pub enum UdmfValue {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    String(String),
+}
+
+/// Keys are lowercase, since UDMF identifiers are case-insensitive.
+pub type UdmfProperties = BTreeMap<String, UdmfValue>;
+
+/// A vertex or thing position at its full UDMF precision.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UdmfVertex {
+    pub x: f32,
+    pub y: f32,
+}
+
+/// The parts of a UDMF map that don't fit the binary map structure.
+///
+/// The property lists run parallel to the map's things, linedefs, etc... and
+/// only hold keys we don't otherwise parse.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct UdmfData {
+    pub namespace: String,
+    /// Top-level assignments, other than `namespace`.
+    pub global: UdmfProperties,
+
+    /// `Map::vertices` holds these rounded to the nearest unit.
+    pub vertices: Vec<UdmfVertex>,
+    /// Likewise for the positions of `Map::things`.
+    pub thing_positions: Vec<UdmfVertex>,
+
+    pub thing_properties: Vec<UdmfProperties>,
+    pub linedef_properties: Vec<UdmfProperties>,
+    pub sidedef_properties: Vec<UdmfProperties>,
+    pub sector_properties: Vec<UdmfProperties>,
+    pub vertex_properties: Vec<UdmfProperties>,
+}
+
+/// Linedef flags in the Doom namespace, and their bit in [Linedef::flags].
+const LINEDEF_FLAGS: [(&str, u16); 10] = [
+    ("blocking", 0x0001),
+    ("blockmonsters", 0x0002),
+    ("twosided", 0x0004),
+    ("dontpegtop", 0x0008),
+    ("dontpegbottom", 0x0010),
+    ("secret", 0x0020),
+    ("blocksound", 0x0040),
+    ("dontdraw", 0x0080),
+    ("mapped", 0x0100),
+    ("passuse", 0x0200),
+];
+
+/// Thing flags in the Doom namespace, and their bit in [Thing::spawn_flags].
+///
+/// UDMF has five skill levels where the binary format has three, so skill 1
+/// follows skill 2 and skill 5 follows skill 4.
+const THING_FLAGS: [(&str, u16); 7] = [
+    ("skill1", 0x0001),
+    ("skill2", 0x0001),
+    ("skill3", 0x0002),
+    ("skill4", 0x0004),
+    ("skill5", 0x0004),
+    ("ambush", 0x0008),
+    ("friend", 0x0080),
+];
+
+/// Thing flags that are set in UDMF when the binary bit is clear.
+const INVERTED_THING_FLAGS: [(&str, u16); 3] =
+    [("single", 0x0010), ("dm", 0x0020), ("coop", 0x0040)];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Identifier(String),
+    Value(UdmfValue),
+    Equals,
+    Semicolon,
+    OpenBrace,
+    CloseBrace,
+}
+
+struct Tokenizer<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    line: usize,
+}
+
+impl<'a> Tokenizer<'a> {
+    fn new(text: &'a str) -> Self {
+        Self {
+            chars: text.chars().peekable(),
+            line: 1,
+        }
+    }
+
+    fn error(&self, message: &str) -> WadError {
+        WadError::InvalidTextmap(format!("line {}: {}", self.line, message))
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.next();
+        if c == Some('\n') {
+            self.line += 1;
+        }
+        c
+    }
+
+    fn skip_whitespace_and_comments(&mut self) -> Result<(), WadError> {
+        loop {
+            match self.chars.peek() {
+                Some(c) if c.is_whitespace() => {
+                    self.bump();
+                }
+                Some('/') => {
+                    self.bump();
+                    match self.bump() {
+                        Some('/') => while !matches!(self.bump(), Some('\n') | None) {},
+                        Some('*') => loop {
+                            match self.bump() {
+                                Some('*') if self.chars.peek() == Some(&'/') => {
+                                    self.bump();
+                                    break;
+                                }
+                                Some(_) => {}
+                                None => return Err(self.error("unterminated comment")),
+                            }
+                        },
+                        _ => return Err(self.error("unexpected '/'")),
+                    }
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn next_token(&mut self) -> Result<Option<Token>, WadError> {
+        self.skip_whitespace_and_comments()?;
+
+        let c = match self.chars.peek() {
+            Some(c) => *c,
+            None => return Ok(None),
+        };
+
+        let token = match c {
+            '=' => {
+                self.bump();
+                Token::Equals
+            }
+            ';' => {
+                self.bump();
+                Token::Semicolon
+            }
+            '{' => {
+                self.bump();
+                Token::OpenBrace
+            }
+            '}' => {
+                self.bump();
+                Token::CloseBrace
+            }
+            '"' => Token::Value(UdmfValue::String(self.string()?)),
+            c if c.is_ascii_digit() || c == '+' || c == '-' || c == '.' => {
+                Token::Value(self.number()?)
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let mut identifier = String::new();
+                while let Some(c) = self.chars.peek() {
+                    if !c.is_ascii_alphanumeric() && *c != '_' {
+                        break;
+                    }
+                    identifier.push(c.to_ascii_lowercase());
+                    self.bump();
+                }
+
+                match identifier.as_str() {
+                    "true" => Token::Value(UdmfValue::Bool(true)),
+                    "false" => Token::Value(UdmfValue::Bool(false)),
+                    _ => Token::Identifier(identifier),
+                }
+            }
+            _ => return Err(self.error(&format!("unexpected character {:?}", c))),
+        };
+
+        Ok(Some(token))
+    }
+
+    fn string(&mut self) -> Result<String, WadError> {
+        // Skip the opening quote.
+        self.bump();
+
+        let mut string = String::new();
+        loop {
+            match self.bump() {
+                Some('"') => return Ok(string),
+                Some('\\') => match self.bump() {
+                    Some(c) => string.push(c),
+                    None => return Err(self.error("unterminated string")),
+                },
+                Some(c) => string.push(c),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<UdmfValue, WadError> {
+        let mut text = String::new();
+        while let Some(c) = self.chars.peek() {
+            if !c.is_ascii_alphanumeric() && !matches!(c, '.' | '+' | '-') {
+                break;
+            }
+            // Signs only appear at the start, or after an exponent.
+            if matches!(c, '+' | '-') && !(text.is_empty() || text.ends_with(['e', 'E'])) {
+                break;
+            }
+            text.push(*c);
+            self.bump();
+        }
+
+        let invalid = || self.error(&format!("invalid number {:?}", text));
+
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, text.strip_prefix('+').unwrap_or(&text)),
+        };
+
+        let int = if let Some(hex) = digits
+            .strip_prefix("0x")
+            .or_else(|| digits.strip_prefix("0X"))
+        {
+            i64::from_str_radix(hex, 16).map_err(|_| invalid())?
+        } else if digits.contains(['.', 'e', 'E']) {
+            return text.parse().map(UdmfValue::Float).map_err(|_| invalid());
+        } else if digits.len() > 1 && digits.starts_with('0') {
+            i64::from_str_radix(&digits[1..], 8).map_err(|_| invalid())?
+        } else {
+            digits.parse().map_err(|_| invalid())?
+        };
+
+        Ok(UdmfValue::Int(if negative { -int } else { int }))
+    }
+}
+
+/// A TEXTMAP is a list of top-level assignments and blocks of assignments.
+#[derive(Debug, Default)]
+struct Textmap {
+    global: UdmfProperties,
+    blocks: Vec<(String, UdmfProperties)>,
+}
+
+fn parse_textmap_syntax(text: &str) -> Result<Textmap, WadError> {
+    let mut tokenizer = Tokenizer::new(text);
+    let mut textmap = Textmap::default();
+
+    // The properties of the block we're in, if any.
+    let mut block: Option<(String, UdmfProperties)> = None;
+
+    while let Some(token) = tokenizer.next_token()? {
+        match token {
+            Token::Identifier(identifier) => match tokenizer.next_token()? {
+                Some(Token::Equals) => {
+                    let value = match tokenizer.next_token()? {
+                        Some(Token::Value(value)) => value,
+                        _ => return Err(tokenizer.error("expected a value")),
+                    };
+                    if tokenizer.next_token()? != Some(Token::Semicolon) {
+                        return Err(tokenizer.error("expected ';'"));
+                    }
+
+                    let properties = match &mut block {
+                        Some((_, properties)) => properties,
+                        None => &mut textmap.global,
+                    };
+                    properties.insert(identifier, value);
+                }
+                Some(Token::OpenBrace) if block.is_none() => {
+                    block = Some((identifier, UdmfProperties::new()));
+                }
+                _ => return Err(tokenizer.error("expected '=' or '{'")),
+            },
+            Token::CloseBrace => match block.take() {
+                Some(block) => textmap.blocks.push(block),
+                None => return Err(tokenizer.error("unexpected '}'")),
+            },
+            _ => return Err(tokenizer.error("expected an identifier")),
+        }
+    }
+
+    if block.is_some() {
+        return Err(tokenizer.error("unterminated block"));
+    }
+
+    Ok(textmap)
+}
+
+/// Reads typed fields out of a block, leaving the keys we don't know about.
+struct BlockReader<'a> {
+    block_name: &'a str,
+    properties: UdmfProperties,
+}
+
+impl BlockReader<'_> {
+    fn error(&self, key: &str) -> WadError {
+        WadError::InvalidTextmap(format!("invalid {} in {}", key, self.block_name))
+    }
+
+    fn int<T: TryFrom<i64>>(&mut self, key: &str, default: Option<T>) -> Result<T, WadError> {
+        match self.properties.remove(key) {
+            Some(UdmfValue::Int(value)) => T::try_from(value).map_err(|_| self.error(key)),
+            None => default.ok_or_else(|| self.error(key)),
+            Some(_) => Err(self.error(key)),
+        }
+    }
+
+    fn float(&mut self, key: &str) -> Result<f32, WadError> {
+        match self.properties.remove(key) {
+            Some(UdmfValue::Float(value)) => Ok(value as f32),
+            Some(UdmfValue::Int(value)) => Ok(value as f32),
+            _ => Err(self.error(key)),
+        }
+    }
+
+    fn bool(&mut self, key: &str) -> Result<bool, WadError> {
+        match self.properties.remove(key) {
+            Some(UdmfValue::Bool(value)) => Ok(value),
+            None => Ok(false),
+            Some(_) => Err(self.error(key)),
+        }
+    }
+
+    fn string(&mut self, key: &str, default: Option<&str>) -> Result<String, WadError> {
+        match self.properties.remove(key) {
+            Some(UdmfValue::String(value)) => Ok(value),
+            None => default.map(str::to_string).ok_or_else(|| self.error(key)),
+            Some(_) => Err(self.error(key)),
+        }
+    }
+
+    /// Reads a sidedef reference, where -1 means none.
+    fn sidedef_idx(&mut self, key: &str) -> Result<Option<u16>, WadError> {
+        match self.int::<i64>(key, Some(-1))? {
+            -1 => Ok(None),
+            idx => u16::try_from(idx).map(Some).map_err(|_| self.error(key)),
+        }
+    }
+}
+
+/// Parses a TEXTMAP lump, interpreting fields as the Doom namespace does.
+///
+/// References:
+/// - https://github.com/ZDoom/gzdoom/blob/master/specs/udmf.txt
+/// - https://doomwiki.org/wiki/UDMF
+pub(crate) fn parse_textmap(lump: &Lump) -> Result<(MapGeometry, UdmfData), WadError> {
+    let text = std::str::from_utf8(lump.bytes()).map_err(|_| WadError::CorruptedString)?;
+    let textmap = parse_textmap_syntax(text)?;
+
+    let mut udmf = UdmfData {
+        global: textmap.global,
+        ..Default::default()
+    };
+    udmf.namespace = match udmf.global.remove("namespace") {
+        Some(UdmfValue::String(namespace)) => namespace,
+        _ => return Err(WadError::InvalidTextmap("missing namespace".to_string())),
+    };
+
+    let mut geometry = MapGeometry {
+        things: vec![],
+        sectors: vec![],
+        sidedefs: vec![],
+        linedefs: vec![],
+        vertices: vec![],
+    };
+
+    for (block_name, properties) in textmap.blocks {
+        let mut block = BlockReader {
+            block_name: &block_name,
+            properties,
+        };
+
+        match block_name.as_str() {
+            "thing" => {
+                let mut spawn_flags = 0;
+                for (key, bit) in THING_FLAGS {
+                    if block.bool(key)? {
+                        spawn_flags |= bit;
+                    }
+                }
+                for (key, bit) in INVERTED_THING_FLAGS {
+                    if !block.bool(key)? {
+                        spawn_flags |= bit;
+                    }
+                }
+
+                let position = UdmfVertex {
+                    x: block.float("x")?,
+                    y: block.float("y")?,
+                };
+
+                geometry.things.push(Thing {
+                    x: position.x.round() as i16,
+                    y: position.y.round() as i16,
+                    angle: block.int::<i64>("angle", Some(0))?.rem_euclid(360) as u16,
+                    thing_type: block.int("type", None)?,
+                    spawn_flags,
+                });
+                udmf.thing_positions.push(position);
+                udmf.thing_properties.push(block.properties);
+            }
+            "vertex" => {
+                let vertex = UdmfVertex {
+                    x: block.float("x")?,
+                    y: block.float("y")?,
+                };
+
+                geometry.vertices.push(Vertex {
+                    x: vertex.x.round() as i16,
+                    y: vertex.y.round() as i16,
+                });
+                udmf.vertices.push(vertex);
+                udmf.vertex_properties.push(block.properties);
+            }
+            "linedef" => {
+                let mut flags = 0;
+                for (key, bit) in LINEDEF_FLAGS {
+                    if block.bool(key)? {
+                        flags |= bit;
+                    }
+                }
+
+                geometry.linedefs.push(Linedef {
+                    start_vertex_idx: block.int("v1", None)?,
+                    end_vertex_idx: block.int("v2", None)?,
+                    flags,
+                    line_type: block.int("special", Some(0))?,
+                    // In the Doom namespace, the line's ID is its sector tag.
+                    sector_tag: match block.int::<i64>("id", Some(-1))? {
+                        -1 => 0,
+                        tag => u16::try_from(tag).map_err(|_| block.error("id"))?,
+                    },
+                    right_sidedef_idx: block.sidedef_idx("sidefront")?,
+                    left_sidedef_idx: block.sidedef_idx("sideback")?,
+                });
+                udmf.linedef_properties.push(block.properties);
+            }
+            "sidedef" => {
+                geometry.sidedefs.push(Sidedef {
+                    x_offset: block.int("offsetx", Some(0))?,
+                    y_offset: block.int("offsety", Some(0))?,
+                    upper_texture: block.string("texturetop", Some("-"))?,
+                    lower_texture: block.string("texturebottom", Some("-"))?,
+                    middle_texture: block.string("texturemiddle", Some("-"))?,
+                    sector_idx: block.int("sector", None)?,
+                });
+                udmf.sidedef_properties.push(block.properties);
+            }
+            "sector" => {
+                geometry.sectors.push(Sector {
+                    floor_height: block.int("heightfloor", Some(0))?,
+                    ceiling_height: block.int("heightceiling", Some(0))?,
+                    floor_flat: block.string("texturefloor", None)?,
+                    ceiling_flat: block.string("textureceiling", None)?,
+                    light_level: block.int("lightlevel", Some(160))?,
+                    special_type: block.int("special", Some(0))?,
+                    sector_tag: block.int("id", Some(0))?,
+                });
+                udmf.sector_properties.push(block.properties);
+            }
+            // Unknown blocks are ignored, as the spec requires.
+            _ => {}
+        }
+    }
+
+    Ok((geometry, udmf))
+}
+
+fn write_value(text: &mut String, key: &str, value: &UdmfValue) {
+    let value = match value {
+        UdmfValue::Int(value) => value.to_string(),
+        // Debug formatting always includes a decimal point.
+        UdmfValue::Float(value) => format!("{:?}", value),
+        UdmfValue::Bool(value) => value.to_string(),
+        UdmfValue::String(value) => {
+            format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+        }
+    };
+    text.push_str(&format!("{} = {};\n", key, value));
+}
+
+fn write_block(
+    text: &mut String,
+    block_name: &str,
+    mut fields: UdmfProperties,
+    extra: Option<&UdmfProperties>,
+) {
+    if let Some(extra) = extra {
+        for (key, value) in extra {
+            fields.entry(key.clone()).or_insert_with(|| value.clone());
+        }
+    }
+
+    text.push_str(block_name);
+    text.push_str("\n{\n");
+    for (key, value) in &fields {
+        write_value(text, key, value);
+    }
+    text.push_str("}\n\n");
+}
+
+/// Prefers the full precision position, unless it has since been moved.
+fn precise_position(udmf_position: Option<&UdmfVertex>, x: i16, y: i16) -> (f64, f64) {
+    match udmf_position {
+        Some(position) if position.x.round() as i16 == x && position.y.round() as i16 == y => {
+            (position.x as f64, position.y as f64)
+        }
+        _ => (x as f64, y as f64),
+    }
+}
+
+fn write_flags(fields: &mut UdmfProperties, flags: &[(&str, u16)], bits: u16, inverted: bool) {
+    for (key, bit) in flags {
+        if (bits & bit != 0) != inverted {
+            fields.insert(key.to_string(), UdmfValue::Bool(true));
+        }
+    }
+}
+
+impl Map {
+    /// Encodes a UDMF map as TEXTMAP, followed by ZNODES, REJECT and BLOCKMAP
+    /// (if present) and the ENDMAP marker.
+    pub(crate) fn udmf_lumps(&self, udmf: &UdmfData) -> Result<Vec<(String, Vec<u8>)>, WadError> {
+        use UdmfValue::{Float, Int};
+
+        let mut text = String::new();
+
+        let mut global = udmf.global.clone();
+        global.insert(
+            "namespace".to_string(),
+            UdmfValue::String(udmf.namespace.clone()),
+        );
+        for (key, value) in &global {
+            write_value(&mut text, key, value);
+        }
+        text.push('\n');
+
+        for (i, thing) in self.things.iter().enumerate() {
+            let (x, y) = precise_position(udmf.thing_positions.get(i), thing.x, thing.y);
+            let mut fields = UdmfProperties::from([
+                ("x".to_string(), Float(x)),
+                ("y".to_string(), Float(y)),
+                ("angle".to_string(), Int(thing.angle as i64)),
+                ("type".to_string(), Int(thing.thing_type as i64)),
+            ]);
+            write_flags(&mut fields, &THING_FLAGS, thing.spawn_flags, false);
+            write_flags(&mut fields, &INVERTED_THING_FLAGS, thing.spawn_flags, true);
+
+            write_block(&mut text, "thing", fields, udmf.thing_properties.get(i));
+        }
+
+        for (i, vertex) in self.vertices.iter().enumerate() {
+            let (x, y) = precise_position(udmf.vertices.get(i), vertex.x, vertex.y);
+            let fields =
+                UdmfProperties::from([("x".to_string(), Float(x)), ("y".to_string(), Float(y))]);
+
+            write_block(&mut text, "vertex", fields, udmf.vertex_properties.get(i));
+        }
+
+        for (i, linedef) in self.linedefs.iter().enumerate() {
+            let sidedef_idx = |idx: Option<u16>| Int(idx.map(|idx| idx as i64).unwrap_or(-1));
+
+            let mut fields = UdmfProperties::from([
+                ("v1".to_string(), Int(linedef.start_vertex_idx as i64)),
+                ("v2".to_string(), Int(linedef.end_vertex_idx as i64)),
+                (
+                    "sidefront".to_string(),
+                    sidedef_idx(linedef.right_sidedef_idx),
+                ),
+                (
+                    "sideback".to_string(),
+                    sidedef_idx(linedef.left_sidedef_idx),
+                ),
+            ]);
+            if linedef.line_type != 0 {
+                fields.insert("special".to_string(), Int(linedef.line_type as i64));
+            }
+            if linedef.sector_tag != 0 {
+                fields.insert("id".to_string(), Int(linedef.sector_tag as i64));
+            }
+            write_flags(&mut fields, &LINEDEF_FLAGS, linedef.flags, false);
+
+            write_block(&mut text, "linedef", fields, udmf.linedef_properties.get(i));
+        }
+
+        for (i, sidedef) in self.sidedefs.iter().enumerate() {
+            let fields = UdmfProperties::from([
+                ("offsetx".to_string(), Int(sidedef.x_offset as i64)),
+                ("offsety".to_string(), Int(sidedef.y_offset as i64)),
+                (
+                    "texturetop".to_string(),
+                    UdmfValue::String(sidedef.upper_texture.clone()),
+                ),
+                (
+                    "texturebottom".to_string(),
+                    UdmfValue::String(sidedef.lower_texture.clone()),
+                ),
+                (
+                    "texturemiddle".to_string(),
+                    UdmfValue::String(sidedef.middle_texture.clone()),
+                ),
+                ("sector".to_string(), Int(sidedef.sector_idx as i64)),
+            ]);
+
+            write_block(&mut text, "sidedef", fields, udmf.sidedef_properties.get(i));
+        }
+
+        for (i, sector) in self.sectors.iter().enumerate() {
+            let fields = UdmfProperties::from([
+                ("heightfloor".to_string(), Int(sector.floor_height as i64)),
+                (
+                    "heightceiling".to_string(),
+                    Int(sector.ceiling_height as i64),
+                ),
+                (
+                    "texturefloor".to_string(),
+                    UdmfValue::String(sector.floor_flat.clone()),
+                ),
+                (
+                    "textureceiling".to_string(),
+                    UdmfValue::String(sector.ceiling_flat.clone()),
+                ),
+                ("lightlevel".to_string(), Int(sector.light_level as i64)),
+                ("special".to_string(), Int(sector.special_type as i64)),
+                ("id".to_string(), Int(sector.sector_tag as i64)),
+            ]);
+
+            write_block(&mut text, "sector", fields, udmf.sector_properties.get(i));
+        }
+
+        let mut lumps = vec![("TEXTMAP".to_string(), text.into_bytes())];
+
+        // UDMF maps can only store extended nodes, in ZNODES.
+        match self.node_format {
+            NodeFormat::Vanilla => {
+                if self.segs.is_some() || self.subsectors.is_some() || self.nodes.is_some() {
+                    return Err(WadError::IncompatibleNodeFormat(self.name.clone()));
+                }
+            }
+            _ => lumps.push(("ZNODES".to_string(), write_extended_nodes(self)?)),
+        }
+
+        lumps.push(("REJECT".to_string(), self.reject.bits.clone()));
+        if let Some(blockmap) = &self.blockmap {
+            lumps.push(("BLOCKMAP".to_string(), write_blockmap(blockmap)?));
+        }
+
+        lumps.push(("ENDMAP".to_string(), vec![]));
+
+        Ok(lumps)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::WadBuilder;
+
+    const TEXTMAP: &str = r#"
+        // A triangle, with some ZDoom extensions mixed in.
+        namespace = "doom";
+
+        thing { x = 32.0; y = 16.0; angle = 90; type = 1; skill1 = true; skill2 = true;
+                skill3 = true; skill4 = true; skill5 = true; single = true; coop = true; }
+
+        vertex { x = 0.0; y = 0.0; }
+        vertex { x = 128.5; y = 0.0; }
+        vertex { x = 0.0; y = 0x80; zfloor = 8.0; }
+
+        linedef { v1 = 0; v2 = 1; sidefront = 0; blocking = true; }
+        linedef { v1 = 1; v2 = 2; sidefront = 1; special = 1; id = 3; }
+        /* The last line has a ZDoom-only comment. */
+        linedef { v1 = 2; v2 = 0; sidefront = 2; comment = "Hello \"world\""; }
+
+        sidedef { sector = 0; texturemiddle = "STARTAN3"; }
+        sidedef { sector = 0; texturemiddle = "STARTAN3"; offsetx = -16; }
+        sidedef { sector = 0; texturemiddle = "STARTAN3"; }
+
+        sector { texturefloor = "FLOOR4_8"; textureceiling = "CEIL3_5"; heightceiling = 128; }
+
+        UnknownBlock { something = 1; }
+    "#;
+
+    fn udmf_wad() -> WadBuilder {
+        let mut builder = WadBuilder::new(false);
+        builder
+            .set_map(
+                "MAP01",
+                [
+                    ("TEXTMAP".to_string(), TEXTMAP.as_bytes().to_vec()),
+                    ("ENDMAP".to_string(), vec![]),
+                ],
+            )
+            .unwrap();
+        builder
+    }
+
+    #[test]
+    fn parse_doom_namespace() {
+        let wad = udmf_wad().build().unwrap();
+        assert_eq!(wad.map_names(), vec!["MAP01".to_string()]);
+
+        let map = wad.parse_map("MAP01").unwrap();
+        let udmf = map.udmf.as_ref().unwrap();
+        assert_eq!(udmf.namespace, "doom");
+
+        assert_eq!(
+            map.things,
+            vec![Thing {
+                x: 32,
+                y: 16,
+                angle: 90,
+                thing_type: 1,
+                // Not in deathmatch.
+                spawn_flags: 0x7 | 0x20,
+            }]
+        );
+
+        assert_eq!(map.vertices[1], Vertex { x: 129, y: 0 });
+        assert_eq!(map.vertices[2], Vertex { x: 0, y: 128 });
+        assert_eq!(udmf.vertices[1], UdmfVertex { x: 128.5, y: 0.0 });
+        assert_eq!(udmf.vertex_properties[2]["zfloor"], UdmfValue::Float(8.0));
+
+        assert_eq!(map.linedefs[0].flags, 0x1);
+        assert_eq!(map.linedefs[0].left_sidedef_idx, None);
+        assert_eq!(map.linedefs[1].sector_tag, 3);
+        assert_eq!(
+            udmf.linedef_properties[2]["comment"],
+            UdmfValue::String("Hello \"world\"".to_string())
+        );
+
+        assert_eq!(map.sidedefs[1].x_offset, -16);
+        assert_eq!(map.sidedefs[1].upper_texture, "-");
+        assert_eq!(map.sectors[0].light_level, 160);
+        // Without a REJECT lump, every sector can see every other.
+        assert!(map.reject.bits.is_empty());
+        assert!(map.reject.can_see(0, 0));
+    }
+
+    #[test]
+    fn parse_write_parse() {
+        let map = udmf_wad().build().unwrap().parse_map("MAP01").unwrap();
+
+        let mut builder = WadBuilder::new(false);
+        builder.set_map_from(&map).unwrap();
+
+        let wad = builder.build().unwrap();
+        assert_eq!(
+            wad.lump_names_in_order,
+            vec!["MAP01", "TEXTMAP", "REJECT", "ENDMAP"]
+        );
+        assert_eq!(wad.parse_map("MAP01").unwrap(), map);
+    }
+
+    #[test]
+    fn fractional_thing_positions_survive_a_round_trip() {
+        let text = r#"namespace = "doom"; thing { x = 31.75; y = -16.25; type = 1; }"#;
+        let mut builder = WadBuilder::new(false);
+        builder
+            .set_map(
+                "MAP01",
+                [
+                    ("TEXTMAP".to_string(), text.as_bytes().to_vec()),
+                    ("ENDMAP".to_string(), vec![]),
+                ],
+            )
+            .unwrap();
+        let map = builder.build().unwrap().parse_map("MAP01").unwrap();
+        assert_eq!((map.things[0].x, map.things[0].y), (32, -16));
+
+        let mut builder = WadBuilder::new(false);
+        builder.set_map_from(&map).unwrap();
+        let parsed = builder.build().unwrap().parse_map("MAP01").unwrap();
+
+        assert_eq!(
+            parsed.udmf.unwrap().thing_positions,
+            vec![UdmfVertex {
+                x: 31.75,
+                y: -16.25
+            }]
+        );
+    }
+
+    #[test]
+    fn syntax_errors() {
+        for text in [
+            "namespace = \"doom\"",
+            "namespace = \"doom\"; thing { x = 1.0; ",
+            "namespace = \"doom\"; thing { x = \"1.0; }",
+            "namespace = \"doom\"; thing { thing { } }",
+        ] {
+            let mut builder = WadBuilder::new(false);
+            builder
+                .set_map(
+                    "MAP01",
+                    [
+                        ("TEXTMAP".to_string(), text.as_bytes().to_vec()),
+                        ("ENDMAP".to_string(), vec![]),
+                    ],
+                )
+                .unwrap();
+
+            let wad = builder.build().unwrap();
+            assert!(matches!(
+                wad.parse_map("MAP01"),
+                Err(WadError::InvalidTextmap(_))
+            ));
+        }
+    }
+}
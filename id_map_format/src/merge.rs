@@ -0,0 +1,200 @@
+use indexmap::IndexMap;
+
+use crate::{Lump, LumpNamespace, Wad};
+
+/// A resource that can be replaced by a later WAD.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ResourceKey {
+    Lump {
+        namespace: LumpNamespace,
+        name: String,
+    },
+    /// Maps are replaced as a whole, never lump by lump.
+    Map(String),
+}
+
+/// One copy of a resource, and which WAD it came from.
+#[derive(Debug, Clone, Copy)]
+pub struct LumpSource<'a> {
+    /// The WAD's position in the stack given to [MergedWads::new].
+    pub wad_index: usize,
+    /// For maps, this is the map marker.
+    pub lump: &'a Lump,
+}
+
+#[derive(Debug, Clone)]
+pub struct MergedResource<'a> {
+    /// The copy in use, from the last WAD that has one.
+    pub source: LumpSource<'a>,
+    /// The copies it replaces, from first to last loaded.
+    pub overridden: Vec<LumpSource<'a>>,
+}
+
+/// What an IWAD and a stack of PWADs look like once they're loaded together,
+/// with where every resource came from.
+///
+/// Later WADs win, and within a WAD the last lump of a name wins, like
+/// vanilla's lookups.
+#[derive(Debug, Clone, Default)]
+pub struct MergedWads<'a> {
+    /// In the order they first appear.
+    pub resources: IndexMap<ResourceKey, MergedResource<'a>>,
+}
+
+impl<'a> MergedWads<'a> {
+    /// `wads` is the IWAD followed by the PWADs, in load order.
+    pub fn new(wads: &[&'a Wad]) -> Self {
+        let mut resources: IndexMap<ResourceKey, MergedResource> = IndexMap::new();
+
+        for (wad_index, wad) in wads.iter().enumerate() {
+            for lump in &wad.lumps_in_order {
+                let key = match &lump.namespace {
+                    LumpNamespace::Map(map_name) if *map_name == lump.name => {
+                        ResourceKey::Map(map_name.clone())
+                    }
+                    // The rest of a map comes with its marker.
+                    LumpNamespace::Map(_) => continue,
+                    _ if is_marker(lump) => continue,
+                    namespace => ResourceKey::Lump {
+                        namespace: namespace.clone(),
+                        name: lump.name.to_uppercase(),
+                    },
+                };
+
+                let source = LumpSource { wad_index, lump };
+                match resources.get_mut(&key) {
+                    Some(resource) => {
+                        let replaced = std::mem::replace(&mut resource.source, source);
+                        resource.overridden.push(replaced);
+                    }
+                    None => {
+                        resources.insert(
+                            key,
+                            MergedResource {
+                                source,
+                                overridden: vec![],
+                            },
+                        );
+                    }
+                }
+            }
+        }
+
+        Self { resources }
+    }
+
+    pub fn get(&self, key: &ResourceKey) -> Option<&MergedResource<'a>> {
+        self.resources.get(key)
+    }
+
+    /// The lump in use for `name` in `namespace`.
+    pub fn lump(&self, namespace: &LumpNamespace, name: &str) -> Option<&'a Lump> {
+        let key = ResourceKey::Lump {
+            namespace: namespace.clone(),
+            name: name.to_uppercase(),
+        };
+        self.get(&key).map(|resource| resource.source.lump)
+    }
+
+    /// The index of the WAD whose copy of the map is used.
+    pub fn map_source(&self, map_name: &str) -> Option<usize> {
+        self.get(&ResourceKey::Map(map_name.to_uppercase()))
+            .map(|resource| resource.source.wad_index)
+    }
+
+    /// Resources that more than one WAD, or one WAD more than once, provides.
+    pub fn overrides(&self) -> impl Iterator<Item = (&ResourceKey, &MergedResource<'a>)> {
+        self.resources
+            .iter()
+            .filter(|(_, resource)| !resource.overridden.is_empty())
+    }
+}
+
+/// Empty `X_START`/`X_END` markers only delimit namespaces.
+pub(crate) fn is_marker(lump: &Lump) -> bool {
+    lump.size == 0 && (lump.name.ends_with("_START") || lump.name.ends_with("_END"))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::WadBuilder;
+
+    /// Adds a map with `thing_count` things and no geometry.
+    pub(crate) fn add_map(builder: &mut WadBuilder, map_name: &str, thing_count: usize) {
+        let namespace = LumpNamespace::Map(map_name.to_string());
+        builder
+            .add_lump(&namespace, "THINGS", vec![0; thing_count * 10])
+            .unwrap();
+        for lump_name in ["LINEDEFS", "SIDEDEFS", "VERTEXES", "SECTORS"] {
+            builder.add_lump(&namespace, lump_name, vec![]).unwrap();
+        }
+    }
+
+    #[test]
+    fn merge_wads() {
+        let mut builder = WadBuilder::new(true);
+        builder
+            .add_lump(&LumpNamespace::Global, "PLAYPAL", vec![1])
+            .unwrap()
+            .add_lump(&LumpNamespace::Global, "COLORMAP", vec![2])
+            .unwrap()
+            .add_lump(&LumpNamespace::Flat, "FLOOR0_1", vec![3])
+            .unwrap();
+        add_map(&mut builder, "E1M1", 1);
+        let iwad = builder.build().unwrap();
+
+        let mut builder = WadBuilder::new(false);
+        builder
+            .add_lump(&LumpNamespace::Global, "PLAYPAL", vec![4])
+            .unwrap();
+        add_map(&mut builder, "E1M1", 2);
+        let first = builder.build().unwrap();
+
+        let mut builder = WadBuilder::new(false);
+        builder
+            .add_lump(&LumpNamespace::Global, "PLAYPAL", vec![5])
+            .unwrap()
+            .add_lump(&LumpNamespace::Flat, "NUKAGE1", vec![6])
+            .unwrap();
+        let second = builder.build().unwrap();
+
+        let merged = MergedWads::new(&[&iwad, &first, &second]);
+
+        let playpal = merged
+            .get(&ResourceKey::Lump {
+                namespace: LumpNamespace::Global,
+                name: "PLAYPAL".to_string(),
+            })
+            .unwrap();
+        assert_eq!(playpal.source.wad_index, 2);
+        assert_eq!(playpal.source.lump.bytes(), &[5]);
+        let overridden: Vec<_> = playpal.overridden.iter().map(|s| s.wad_index).collect();
+        assert_eq!(overridden, vec![0, 1]);
+
+        assert_eq!(
+            merged
+                .lump(&LumpNamespace::Global, "colormap")
+                .unwrap()
+                .bytes(),
+            &[2]
+        );
+        assert_eq!(merged.map_source("e1m1"), Some(1));
+        assert!(merged.lump(&LumpNamespace::Flat, "NUKAGE1").is_some());
+        // Map lumps and markers aren't resources of their own.
+        assert!(merged.lump(&LumpNamespace::Global, "THINGS").is_none());
+        assert!(merged.lump(&LumpNamespace::Flat, "F_START").is_none());
+
+        let overrides: Vec<_> = merged.overrides().map(|(key, _)| key.clone()).collect();
+        assert_eq!(
+            overrides,
+            vec![
+                ResourceKey::Lump {
+                    namespace: LumpNamespace::Global,
+                    name: "PLAYPAL".to_string(),
+                },
+                ResourceKey::Map("E1M1".to_string()),
+            ]
+        );
+    }
+}
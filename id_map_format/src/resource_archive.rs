@@ -0,0 +1,297 @@
+use std::{
+    collections::HashMap,
+    io::{Cursor, Read},
+    sync::Arc,
+};
+
+use crate::{Lump, LumpNamespace, Wad, WadError};
+
+/// The most a single archive entry may inflate to. Resources are rarely more
+/// than a few megabytes, and it stops a zip bomb from exhausting memory.
+const MAX_ENTRY_LEN: u64 = 64 * 1024 * 1024;
+
+/// A file in a [ResourceArchive], addressed by its path.
+#[derive(Debug, Clone)]
+pub struct ArchiveEntry {
+    pub path: String,
+    pub bytes: Vec<u8>,
+}
+
+/// [ResourceArchive] holds the files of a PK3 (zip), where resources are
+/// organized by directory instead of marker lumps.
+///
+/// Use [ResourceArchive::to_wad] to map those directories onto namespaces, so
+/// the archive can be used anywhere a PWAD can.
+///
+/// References:
+/// - https://zdoom.org/wiki/Using_ZIPs_as_WAD_replacement
+#[derive(Debug, Clone, Default)]
+pub struct ResourceArchive {
+    pub entries: Vec<ArchiveEntry>,
+}
+
+/// Where a file in the archive ends up.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArchivePath {
+    Lump(LumpNamespace, String),
+    /// A WAD under `maps/`, holding the map of the same name.
+    EmbeddedMap(String),
+    /// Directories we don't have a namespace for.
+    Ignored,
+}
+
+impl ArchivePath {
+    pub fn from_path(path: &str) -> Self {
+        let (directory, file_name) = match path.split_once('/') {
+            Some((directory, rest)) => (
+                Some(directory.to_ascii_lowercase()),
+                rest.rsplit('/').next().unwrap_or(rest),
+            ),
+            None => (None, path),
+        };
+
+        // Lump names drop the extension, like ZDoom does.
+        let (stem, extension) = match file_name.split_once('.') {
+            Some((stem, extension)) => (stem, extension.to_ascii_lowercase()),
+            None => (file_name, String::new()),
+        };
+        if stem.is_empty() {
+            return Self::Ignored;
+        }
+        let lump_name = stem.to_uppercase();
+
+        let namespace = match directory.as_deref() {
+            None => LumpNamespace::Global,
+            Some("maps") if extension == "wad" => return Self::EmbeddedMap(lump_name),
+            Some("flats") => LumpNamespace::Flat,
+            // Standalone textures are graphics like patches, so they share the namespace.
+            Some("patches") | Some("textures") => LumpNamespace::Patch,
+            Some("sprites") => LumpNamespace::Sprite,
+            Some("graphics") | Some("sounds") | Some("music") => LumpNamespace::Global,
+            _ => return Self::Ignored,
+        };
+
+        Self::Lump(namespace, lump_name)
+    }
+}
+
+impl ResourceArchive {
+    pub fn from_pk3(bytes: &[u8]) -> Result<Self, WadError> {
+        let mut zip = zip::ZipArchive::new(Cursor::new(bytes))
+            .map_err(|_| WadError::corrupted("central directory", 0))?;
+
+        let mut entries = Vec::with_capacity(zip.len());
+        for i in 0..zip.len() {
+            let file = zip
+                .by_index(i)
+                .map_err(|_| WadError::corrupted(&format!("entry {i}"), 0))?;
+            if file.is_dir() {
+                continue;
+            }
+
+            // The size comes from the zip header, so it bounds the read but
+            // isn't trusted to pre-size the buffer.
+            let name = file.name().to_string();
+            let size = file.size();
+            if size > MAX_ENTRY_LEN {
+                return Err(WadError::corrupted(&name, 0));
+            }
+            let mut bytes = Vec::new();
+            file.take(size)
+                .read_to_end(&mut bytes)
+                .map_err(|_| WadError::corrupted(&name, 0))?;
+
+            entries.push(ArchiveEntry { path: name, bytes });
+        }
+
+        Ok(Self { entries })
+    }
+
+    /// Maps the archive onto namespaces, as if it were a PWAD.
+    ///
+    /// When two files map onto the same lump, the later one wins.
+    pub fn to_wad(self) -> Result<Wad, WadError> {
+        let mut lumps_in_order = Vec::with_capacity(self.entries.len());
+
+        for entry in self.entries {
+            match ArchivePath::from_path(&entry.path) {
+                ArchivePath::Lump(namespace, name) => {
+                    lumps_in_order.push(Lump {
+                        name,
+                        namespace,
+                        offset: 0,
+                        size: entry.bytes.len(),
+                        bytes: Arc::new(entry.bytes),
+                    });
+                }
+                ArchivePath::EmbeddedMap(map_name) => {
+                    let wad = Wad::new(entry.bytes)?;
+
+                    // ZDoom names the map after the file, whatever the marker says.
+                    let original_name = wad
+                        .map_names()
+                        .into_iter()
+                        .next()
+                        .ok_or_else(|| WadError::MapDoesNotExist(map_name.clone()))?;
+                    let original_namespace = LumpNamespace::Map(original_name.clone());
+                    let namespace = LumpNamespace::Map(map_name.clone());
+
+                    for mut lump in wad.lumps_in_order {
+                        if lump.namespace != original_namespace {
+                            continue;
+                        }
+
+                        if lump.name == original_name {
+                            lump.name = map_name.clone();
+                        }
+                        lump.namespace = namespace.clone();
+                        lumps_in_order.push(lump);
+                    }
+                }
+                ArchivePath::Ignored => {}
+            }
+        }
+
+        let mut lump_namespaces: HashMap<LumpNamespace, HashMap<String, Lump>> = HashMap::new();
+        for lump in &lumps_in_order {
+            lump_namespaces
+                .entry(lump.namespace.clone())
+                .or_default()
+                .insert(lump.name.clone(), lump.clone());
+        }
+
+        Ok(Wad {
+            is_iwad: false,
+            // There's no directory, the lumps come from the archive.
+            directory_offset: 0,
+            lump_names_in_order: lumps_in_order.iter().map(|l| l.name.clone()).collect(),
+            lumps_in_order,
+            lump_namespaces,
+        })
+    }
+}
+
+impl Wad {
+    /// Reads a PK3 into a [Wad], see [ResourceArchive::to_wad].
+    pub fn from_pk3(bytes: &[u8]) -> Result<Self, WadError> {
+        ResourceArchive::from_pk3(bytes)?.to_wad()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use zip::{write::SimpleFileOptions, ZipWriter};
+
+    use super::*;
+    use crate::{lump_from_namespace, WadBuilder};
+
+    fn pk3(files: &[(&str, Vec<u8>)]) -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for (path, bytes) in files {
+            zip.start_file(*path, SimpleFileOptions::default()).unwrap();
+            zip.write_all(bytes).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn archive_paths() {
+        use ArchivePath::*;
+
+        assert_eq!(
+            ArchivePath::from_path("mapinfo.txt"),
+            Lump(LumpNamespace::Global, "MAPINFO".to_string())
+        );
+        assert_eq!(
+            ArchivePath::from_path("Flats/water/fwater1.png"),
+            Lump(LumpNamespace::Flat, "FWATER1".to_string())
+        );
+        assert_eq!(
+            ArchivePath::from_path("textures/wall.png"),
+            Lump(LumpNamespace::Patch, "WALL".to_string())
+        );
+        assert_eq!(
+            ArchivePath::from_path("maps/e1m1.wad"),
+            EmbeddedMap("E1M1".to_string())
+        );
+        assert_eq!(ArchivePath::from_path("acs/script.o"), Ignored);
+    }
+
+    #[test]
+    fn pk3_to_wad() {
+        // The embedded map's marker doesn't match its file name.
+        let mut map_wad = WadBuilder::new(false);
+        map_wad
+            .set_map(
+                "MAP01",
+                ["THINGS", "LINEDEFS", "SIDEDEFS", "VERTEXES", "SECTORS"]
+                    .into_iter()
+                    .map(|name| (name.to_string(), vec![])),
+            )
+            .unwrap();
+
+        let bytes = pk3(&[
+            ("playpal.lmp", vec![1, 2, 3]),
+            ("flats/floor.lmp", vec![4]),
+            ("sprites/trooa1.lmp", vec![5]),
+            ("maps/map07.wad", map_wad.to_bytes()),
+            ("source/readme.txt", vec![6]),
+        ]);
+
+        let wad = Wad::from_pk3(&bytes).unwrap();
+        assert!(!wad.is_iwad);
+        assert_eq!(wad.map_names(), vec!["MAP07".to_string()]);
+        assert_eq!(
+            wad.lump_names_in_order,
+            vec![
+                "PLAYPAL", "FLOOR", "TROOA1", "MAP07", "THINGS", "LINEDEFS", "SIDEDEFS",
+                "VERTEXES", "SECTORS"
+            ]
+        );
+
+        let playpal = lump_from_namespace(&LumpNamespace::Global, "PLAYPAL", &wad).unwrap();
+        assert_eq!(playpal.bytes(), &[1, 2, 3]);
+        assert!(lump_from_namespace(&LumpNamespace::Flat, "FLOOR", &wad).is_ok());
+        assert!(lump_from_namespace(&LumpNamespace::Sprite, "TROOA1", &wad).is_ok());
+
+        let map = wad.parse_map("MAP07").unwrap();
+        assert_eq!(map.name, "MAP07");
+        assert!(map.things.is_empty());
+
+        // Written out as a WAD, flats and sprites get marker blocks.
+        let wad = Wad::new(wad.to_bytes().unwrap()).unwrap();
+        assert_eq!(
+            wad.lump_names_in_order,
+            vec![
+                "PLAYPAL", "MAP07", "THINGS", "LINEDEFS", "SIDEDEFS", "VERTEXES", "SECTORS",
+                "S_START", "TROOA1", "S_END", "F_START", "FLOOR", "F_END"
+            ]
+        );
+        assert!(lump_from_namespace(&LumpNamespace::Flat, "FLOOR", &wad).is_ok());
+        assert!(lump_from_namespace(&LumpNamespace::Sprite, "TROOA1", &wad).is_ok());
+
+        // File names that don't fit in a lump name can't be written.
+        let bytes = pk3(&[("textures/bigwallname.png", vec![7])]);
+        assert!(matches!(
+            Wad::from_pk3(&bytes).unwrap().to_bytes(),
+            Err(WadError::InvalidLumpName(name)) if name == "BIGWALLNAME"
+        ));
+    }
+
+    #[test]
+    fn corrupted_archives() {
+        assert!(matches!(
+            ResourceArchive::from_pk3(b"not a zip"),
+            Err(WadError::CorruptedLump { lump_name, .. }) if lump_name == "central directory"
+        ));
+
+        // A zip bomb is stopped before it's inflated.
+        let bytes = pk3(&[("bomb.lmp", vec![0; MAX_ENTRY_LEN as usize + 1])]);
+        assert!(matches!(
+            ResourceArchive::from_pk3(&bytes),
+            Err(WadError::CorruptedLump { lump_name, .. }) if lump_name == "bomb.lmp"
+        ));
+    }
+}
@@ -0,0 +1,542 @@
+use crate::{helpers::write_bytes_cstr, Lump, LumpNamespace, Wad, WadError};
+
+/// The order vanilla node builders write map lumps in.
+///
+/// This is a superset of `ORDERED_MAP_LUMP_NAMES`, with the optional lumps
+/// slotted in where DOOM expects them.
+pub(crate) const CANONICAL_MAP_LUMP_ORDER: [&str; 10] = [
+    "THINGS", "LINEDEFS", "SIDEDEFS", "VERTEXES", "SEGS", "SSECTORS", "NODES", "SECTORS", "REJECT",
+    "BLOCKMAP",
+];
+
+#[derive(Debug, Clone)]
+struct WadBuilderLump {
+    name: String,
+    namespace: LumpNamespace,
+    bytes: Vec<u8>,
+}
+
+/// [WadBuilder] is the inverse of [Wad::new]: it holds an ordered list of lumps
+/// and serializes them back into IWAD/PWAD bytes.
+///
+/// Lumps are addressed the same way as when reading, by namespace and name.
+/// Namespace markers (`P_START`, `F_END`, etc...) and map markers are created
+/// as needed, so the output always parses back into the same namespaces.
+#[derive(Debug, Clone)]
+pub struct WadBuilder {
+    pub is_iwad: bool,
+    lumps: Vec<WadBuilderLump>,
+}
+
+impl WadBuilder {
+    pub fn new(is_iwad: bool) -> Self {
+        Self {
+            is_iwad,
+            lumps: Vec::new(),
+        }
+    }
+
+    /// Copies every lump out of an existing WAD, keeping directory order.
+    ///
+    /// WADs read from a PK3 have no namespace markers, so their patches,
+    /// sprites and flats are gathered into marker blocks at the end.
+    pub fn from_wad(wad: &Wad) -> Result<Self, WadError> {
+        // Names from a WAD directory always fit, but PK3 file names may not.
+        for lump in &wad.lumps_in_order {
+            write_bytes_cstr(&lump.name)?;
+        }
+
+        let has_markers = |namespace: &LumpNamespace| {
+            wad.lumps_in_order.iter().any(|lump| {
+                lump.namespace == *namespace
+                    && (lump.name.ends_with("_START") || lump.name.ends_with("_END"))
+            })
+        };
+        let unmarked: Vec<LumpNamespace> = [
+            LumpNamespace::Patch,
+            LumpNamespace::Sprite,
+            LumpNamespace::Flat,
+        ]
+        .into_iter()
+        .filter(|namespace| !has_markers(namespace))
+        .collect();
+
+        let to_builder_lump = |lump: &Lump| WadBuilderLump {
+            name: lump.name.clone(),
+            namespace: lump.namespace.clone(),
+            bytes: lump.bytes().to_vec(),
+        };
+        let mut lumps: Vec<WadBuilderLump> = wad
+            .lumps_in_order
+            .iter()
+            .filter(|lump| !unmarked.contains(&lump.namespace))
+            .map(to_builder_lump)
+            .collect();
+
+        for namespace in unmarked {
+            let mut block = wad
+                .lumps_in_order
+                .iter()
+                .filter(|lump| lump.namespace == namespace)
+                .map(to_builder_lump)
+                .peekable();
+            if block.peek().is_none() {
+                continue;
+            }
+
+            let (start, end) = namespace_markers(&namespace);
+            let marker = |name: &str| WadBuilderLump {
+                name: name.to_string(),
+                namespace: namespace.clone(),
+                bytes: Vec::new(),
+            };
+            lumps.push(marker(start));
+            lumps.extend(block);
+            lumps.push(marker(end));
+        }
+
+        Ok(Self {
+            is_iwad: wad.is_iwad,
+            lumps,
+        })
+    }
+
+    /// Lump names in the order they'll be written, including markers.
+    pub fn lump_names(&self) -> Vec<String> {
+        self.lumps.iter().map(|lump| lump.name.clone()).collect()
+    }
+
+    pub fn get_lump(&self, namespace: &LumpNamespace, lump_name: &str) -> Option<&[u8]> {
+        self.position(namespace, lump_name)
+            .map(|i| self.lumps[i].bytes.as_slice())
+    }
+
+    /// Adds a new lump to the end of its namespace.
+    ///
+    /// Patches, sprites and flats go before the block's `_END` marker, creating
+    /// the block if it doesn't exist yet. Map lumps are kept in canonical order.
+    pub fn add_lump(
+        &mut self,
+        namespace: &LumpNamespace,
+        lump_name: &str,
+        bytes: Vec<u8>,
+    ) -> Result<&mut Self, WadError> {
+        let name = validate_lump_name(lump_name)?;
+        if self.position(namespace, &name).is_some() {
+            return Err(WadError::DuplicateLump(name));
+        }
+
+        let lump = WadBuilderLump {
+            name,
+            namespace: namespace.clone(),
+            bytes,
+        };
+
+        match namespace {
+            LumpNamespace::Global => self.lumps.push(lump),
+            LumpNamespace::Map(map_name) => {
+                let map_name = validate_lump_name(map_name)?;
+
+                // Create the map marker if this is the first lump in the map.
+                if !self.lumps.iter().any(|l| l.namespace == *namespace) {
+                    self.lumps.push(WadBuilderLump {
+                        name: map_name,
+                        namespace: namespace.clone(),
+                        bytes: Vec::new(),
+                    });
+                }
+
+                let insert_idx = self.map_insert_position(namespace, &lump.name);
+                self.lumps.insert(insert_idx, lump);
+            }
+            LumpNamespace::Patch | LumpNamespace::Sprite | LumpNamespace::Flat => {
+                match self.lumps.iter().rposition(|l| l.namespace == *namespace) {
+                    Some(last_idx) => {
+                        // Insert before the closing marker, if there is one.
+                        let insert_idx = match self.lumps[last_idx].name.ends_with("_END") {
+                            true => last_idx,
+                            false => last_idx + 1,
+                        };
+                        self.lumps.insert(insert_idx, lump);
+                    }
+                    None => {
+                        let (start, end) = namespace_markers(namespace);
+                        self.lumps.push(WadBuilderLump {
+                            name: start.to_string(),
+                            namespace: namespace.clone(),
+                            bytes: Vec::new(),
+                        });
+                        self.lumps.push(lump);
+                        self.lumps.push(WadBuilderLump {
+                            name: end.to_string(),
+                            namespace: namespace.clone(),
+                            bytes: Vec::new(),
+                        });
+                    }
+                }
+            }
+        }
+
+        Ok(self)
+    }
+
+    /// Replaces the contents of an existing lump, keeping its position.
+    pub fn replace_lump(
+        &mut self,
+        namespace: &LumpNamespace,
+        lump_name: &str,
+        bytes: Vec<u8>,
+    ) -> Result<&mut Self, WadError> {
+        let idx = self
+            .position(namespace, lump_name)
+            .ok_or(WadError::MissingLump(lump_name.to_string()))?;
+
+        self.lumps[idx].bytes = bytes;
+        Ok(self)
+    }
+
+    /// Removes a lump, returning its contents.
+    ///
+    /// Removing a map marker removes the entire map.
+    pub fn remove_lump(
+        &mut self,
+        namespace: &LumpNamespace,
+        lump_name: &str,
+    ) -> Result<Vec<u8>, WadError> {
+        let idx = self
+            .position(namespace, lump_name)
+            .ok_or(WadError::MissingLump(lump_name.to_string()))?;
+
+        if let LumpNamespace::Map(map_name) = namespace {
+            if map_name.eq_ignore_ascii_case(lump_name) {
+                let lump = self.lumps.remove(idx);
+                self.lumps.retain(|l| l.namespace != *namespace);
+                return Ok(lump.bytes);
+            }
+        }
+
+        Ok(self.lumps.remove(idx).bytes)
+    }
+
+    /// Moves a lump to `new_idx`, counted among the other lumps in its namespace
+    /// (not including markers).
+    ///
+    /// Map lumps always stay in canonical order, so they can't be moved.
+    pub fn move_lump(
+        &mut self,
+        namespace: &LumpNamespace,
+        lump_name: &str,
+        new_idx: usize,
+    ) -> Result<&mut Self, WadError> {
+        if let LumpNamespace::Map(map_name) = namespace {
+            return Err(WadError::MapLumpsNotReorderable(map_name.clone()));
+        }
+
+        let idx = self
+            .position(namespace, lump_name)
+            .ok_or(WadError::MissingLump(lump_name.to_string()))?;
+        let lump = self.lumps.remove(idx);
+
+        let siblings: Vec<usize> = self
+            .lumps
+            .iter()
+            .enumerate()
+            .filter(|(_, l)| l.namespace == *namespace && !is_marker(l))
+            .map(|(i, _)| i)
+            .collect();
+
+        let insert_idx = match siblings.get(new_idx) {
+            Some(sibling_idx) => *sibling_idx,
+            // Past the end: go after the last sibling (or back where we were).
+            None => match siblings.last() {
+                Some(last_idx) => last_idx + 1,
+                None => idx,
+            },
+        };
+
+        self.lumps.insert(insert_idx, lump);
+        Ok(self)
+    }
+
+    /// Replaces (or appends) an entire map block.
+    ///
+    /// The lumps are written in canonical order regardless of the order given.
+    pub fn set_map<I: IntoIterator<Item = (String, Vec<u8>)>>(
+        &mut self,
+        map_name: &str,
+        lumps: I,
+    ) -> Result<&mut Self, WadError> {
+        let map_name = validate_lump_name(map_name)?;
+        let namespace = LumpNamespace::Map(map_name.clone());
+
+        let mut map_lumps = vec![WadBuilderLump {
+            name: map_name,
+            namespace: namespace.clone(),
+            bytes: Vec::new(),
+        }];
+        for (name, bytes) in lumps {
+            map_lumps.push(WadBuilderLump {
+                name: validate_lump_name(&name)?,
+                namespace: namespace.clone(),
+                bytes,
+            });
+        }
+        map_lumps[1..].sort_by_key(|l| map_lump_rank(&l.name));
+
+        match self.lumps.iter().position(|l| l.namespace == namespace) {
+            Some(start_idx) => {
+                let end_idx = self.lumps[start_idx..]
+                    .iter()
+                    .position(|l| l.namespace != namespace)
+                    .map(|len| start_idx + len)
+                    .unwrap_or(self.lumps.len());
+                self.lumps.splice(start_idx..end_idx, map_lumps);
+            }
+            None => self.lumps.extend(map_lumps),
+        }
+
+        Ok(self)
+    }
+
+    /// Serializes the lumps into a WAD file.
+    ///
+    /// Lump data comes first, directly after the header, and the directory
+    /// is written at the end of the file.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let data_size: usize = self.lumps.iter().map(|lump| lump.bytes.len()).sum();
+        let dir_start_offset = 12 + data_size;
+
+        let mut bytes = Vec::with_capacity(dir_start_offset + self.lumps.len() * 16);
+
+        // Write header.
+        bytes.extend_from_slice(match self.is_iwad {
+            true => b"IWAD",
+            false => b"PWAD",
+        });
+        bytes.extend_from_slice(&(self.lumps.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&(dir_start_offset as u32).to_le_bytes());
+
+        // Write lump data.
+        let mut offsets = Vec::with_capacity(self.lumps.len());
+        for lump in &self.lumps {
+            offsets.push(bytes.len());
+            bytes.extend_from_slice(&lump.bytes);
+        }
+
+        // Write directory.
+        for (lump, offset) in self.lumps.iter().zip(offsets) {
+            // Names are checked as lumps are added.
+            let name = write_bytes_cstr(&lump.name).unwrap();
+
+            bytes.extend_from_slice(&(offset as u32).to_le_bytes());
+            bytes.extend_from_slice(&(lump.bytes.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(&name);
+        }
+
+        bytes
+    }
+
+    /// Serializes and re-parses the lumps, validating the result.
+    pub fn build(&self) -> Result<Wad, WadError> {
+        Wad::new(self.to_bytes())
+    }
+
+    fn position(&self, namespace: &LumpNamespace, lump_name: &str) -> Option<usize> {
+        self.lumps
+            .iter()
+            .position(|l| l.namespace == *namespace && l.name.eq_ignore_ascii_case(lump_name))
+    }
+
+    fn map_insert_position(&self, namespace: &LumpNamespace, lump_name: &str) -> usize {
+        let rank = map_lump_rank(lump_name);
+
+        // Skip over the map marker.
+        let start_idx = self
+            .lumps
+            .iter()
+            .position(|l| l.namespace == *namespace)
+            .unwrap();
+
+        let mut insert_idx = start_idx + 1;
+        while insert_idx < self.lumps.len()
+            && self.lumps[insert_idx].namespace == *namespace
+            && map_lump_rank(&self.lumps[insert_idx].name) <= rank
+        {
+            insert_idx += 1;
+        }
+
+        insert_idx
+    }
+}
+
+impl Wad {
+    /// Serializes the WAD back into bytes.
+    ///
+    /// See [WadBuilder] if you want to modify the WAD first.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, WadError> {
+        Ok(WadBuilder::from_wad(self)?.to_bytes())
+    }
+}
+
+/// Lump names are at most 8 ASCII characters, and are matched case-insensitively.
+fn validate_lump_name(lump_name: &str) -> Result<String, WadError> {
+    if lump_name.is_empty()
+        || lump_name.len() > 8
+        || !lump_name.bytes().all(|c| c.is_ascii_graphic())
+    {
+        return Err(WadError::InvalidLumpName(lump_name.to_string()));
+    }
+
+    Ok(lump_name.to_uppercase())
+}
+
+fn map_lump_rank(lump_name: &str) -> usize {
+    // UDMF maps start with TEXTMAP and end with ENDMAP, anything goes in between.
+    if lump_name.eq_ignore_ascii_case("TEXTMAP") {
+        return 0;
+    }
+    if lump_name.eq_ignore_ascii_case("ENDMAP") {
+        return usize::MAX;
+    }
+
+    CANONICAL_MAP_LUMP_ORDER
+        .iter()
+        .position(|name| name.eq_ignore_ascii_case(lump_name))
+        .unwrap_or(CANONICAL_MAP_LUMP_ORDER.len())
+}
+
+fn namespace_markers(namespace: &LumpNamespace) -> (&'static str, &'static str) {
+    match namespace {
+        LumpNamespace::Patch => ("P_START", "P_END"),
+        LumpNamespace::Sprite => ("S_START", "S_END"),
+        LumpNamespace::Flat => ("F_START", "F_END"),
+        _ => unreachable!("Only patches, sprites and flats have markers."),
+    }
+}
+
+fn is_marker(lump: &WadBuilderLump) -> bool {
+    match &lump.namespace {
+        LumpNamespace::Global => false,
+        LumpNamespace::Map(map_name) => lump.name.eq_ignore_ascii_case(map_name),
+        _ => lump.name.ends_with("_START") || lump.name.ends_with("_END"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patch_namespace_names(wad: &Wad) -> Vec<String> {
+        wad.lumps_in_order
+            .iter()
+            .filter(|l| l.namespace == LumpNamespace::Patch)
+            .map(|l| l.name.clone())
+            .collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut builder = WadBuilder::new(false);
+        builder
+            .add_lump(&LumpNamespace::Global, "DEHACKED", b"Patch File".to_vec())
+            .unwrap()
+            .add_lump(&LumpNamespace::Patch, "WALL00_1", vec![1, 2, 3])
+            .unwrap()
+            .add_lump(&LumpNamespace::Patch, "WALL00_2", vec![4, 5])
+            .unwrap();
+
+        let wad = builder.build().unwrap();
+        assert!(!wad.is_iwad);
+        assert_eq!(
+            wad.lump_names_in_order,
+            vec!["DEHACKED", "P_START", "WALL00_1", "WALL00_2", "P_END"]
+        );
+        assert_eq!(
+            crate::lump_from_namespace(&LumpNamespace::Patch, "WALL00_2", &wad)
+                .unwrap()
+                .bytes(),
+            &[4, 5]
+        );
+
+        // Re-serializing a parsed WAD should be byte-exact.
+        assert_eq!(wad.to_bytes().unwrap(), builder.to_bytes());
+    }
+
+    #[test]
+    fn map_lumps_stay_in_canonical_order() {
+        let mut builder = WadBuilder::new(false);
+        builder
+            .set_map(
+                "MAP01",
+                vec![
+                    ("SECTORS".to_string(), vec![0; 26]),
+                    ("VERTEXES".to_string(), vec![0; 4]),
+                    ("THINGS".to_string(), vec![0; 10]),
+                    ("SIDEDEFS".to_string(), vec![0; 30]),
+                    ("LINEDEFS".to_string(), vec![0; 14]),
+                ],
+            )
+            .unwrap();
+        builder
+            .add_lump(&LumpNamespace::Map("MAP01".to_string()), "NODES", vec![])
+            .unwrap();
+        builder
+            .add_lump(&LumpNamespace::Global, "ENDOOM", vec![])
+            .unwrap();
+
+        let wad = builder.build().unwrap();
+        assert_eq!(
+            wad.lump_names_in_order,
+            vec![
+                "MAP01", "THINGS", "LINEDEFS", "SIDEDEFS", "VERTEXES", "NODES", "SECTORS", "ENDOOM"
+            ]
+        );
+        assert_eq!(wad.map_names(), vec!["MAP01"]);
+
+        let map_namespace = LumpNamespace::Map("MAP01".to_string());
+        assert!(matches!(
+            builder.move_lump(&map_namespace, "THINGS", 2),
+            Err(WadError::MapLumpsNotReorderable(_))
+        ));
+
+        builder.remove_lump(&map_namespace, "MAP01").unwrap();
+        assert_eq!(builder.lump_names(), vec!["ENDOOM"]);
+    }
+
+    #[test]
+    fn replace_move_and_remove() {
+        let mut builder = WadBuilder::new(true);
+        for name in ["A", "B", "C"] {
+            builder
+                .add_lump(&LumpNamespace::Patch, name, vec![])
+                .unwrap();
+        }
+
+        builder
+            .replace_lump(&LumpNamespace::Patch, "b", vec![7])
+            .unwrap()
+            .move_lump(&LumpNamespace::Patch, "C", 0)
+            .unwrap();
+        builder.remove_lump(&LumpNamespace::Patch, "A").unwrap();
+
+        assert!(matches!(
+            builder.add_lump(&LumpNamespace::Patch, "B", vec![]),
+            Err(WadError::DuplicateLump(_))
+        ));
+        assert!(matches!(
+            builder.add_lump(&LumpNamespace::Global, "TOOLONGNAME", vec![]),
+            Err(WadError::InvalidLumpName(_))
+        ));
+
+        let wad = builder.build().unwrap();
+        assert!(wad.is_iwad);
+        assert_eq!(
+            patch_namespace_names(&wad),
+            vec!["P_START", "C", "B", "P_END"]
+        );
+        assert_eq!(
+            builder.get_lump(&LumpNamespace::Patch, "B"),
+            Some(&[7u8][..])
+        );
+    }
+}
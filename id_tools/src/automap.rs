@@ -0,0 +1,67 @@
+use std::fs;
+
+use anyhow::{bail, Context, Result};
+use id_game_config::{Game, GameConfig};
+use id_map_format::{Automap, AutomapBounds, AutomapOptions, Wad};
+
+/// Draws a map to a file, as SVG or PNG depending on its extension.
+///
+/// Things are classified with the detected game's config, falling back to
+/// DOOM's for PWADs.
+pub fn run(wad: &Wad, args: &[String]) -> Result<bool> {
+    let (flags, args): (Vec<&String>, Vec<&String>) =
+        args.iter().partition(|arg| arg.starts_with("--"));
+    let [map_name, path] = args[..] else {
+        bail!("expected a map name and an output path");
+    };
+
+    let mut options = AutomapOptions::default();
+    for flag in flags {
+        let (name, value) = flag.split_once('=').unwrap_or((flag, ""));
+        match name {
+            "--scale" => {
+                options.scale = value
+                    .parse()
+                    .ok()
+                    .filter(|scale: &f32| *scale > 0.0)
+                    .with_context(|| format!("invalid scale {value:?}"))?
+            }
+            "--bounds" => options.bounds = Some(parse_bounds(value)?),
+            "--no-things" => options.show_things = false,
+            "--hidden" => options.show_hidden = true,
+            _ => bail!("unknown flag {flag:?}"),
+        }
+    }
+
+    let map = wad.parse_map(&map_name.to_uppercase())?;
+    let config = GameConfig::from_game(Game::from_wad(wad).unwrap_or(Game::Doom))?;
+    let automap = Automap::new(&map, &config.automap_things(), &options);
+
+    let bytes = match path.rsplit_once('.').map(|(_, extension)| extension) {
+        Some(extension) if extension.eq_ignore_ascii_case("svg") => automap.to_svg().into_bytes(),
+        Some(extension) if extension.eq_ignore_ascii_case("png") => automap.to_png(),
+        _ => bail!("{path} should end in .svg or .png"),
+    };
+    fs::write(path, bytes).with_context(|| format!("failed to write {path}"))?;
+    println!("{path}\t{}x{}", automap.width, automap.height);
+
+    Ok(true)
+}
+
+fn parse_bounds(value: &str) -> Result<AutomapBounds> {
+    let numbers = value
+        .split(',')
+        .map(|number| number.trim().parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()
+        .ok();
+    let Some(&[x1, y1, x2, y2]) = numbers.as_deref() else {
+        bail!("invalid bounds {value:?}, expected x1,y1,x2,y2");
+    };
+
+    Ok(AutomapBounds {
+        min_x: x1.min(x2),
+        min_y: y1.min(y2),
+        max_x: x1.max(x2),
+        max_y: y1.max(y2),
+    })
+}
@@ -0,0 +1,49 @@
+use anyhow::{bail, Result};
+use id_map_format::{LumpChange, MapCounts, WadDiff};
+
+use crate::{open_wad, wadinfo::namespace_label};
+
+/// Prints what changed between two WADs. Like `diff`, this fails if there
+/// are any differences.
+pub fn run(args: &[String]) -> Result<bool> {
+    let [old_path, new_path] = args else {
+        bail!("expected an old and a new WAD path");
+    };
+    let diff = WadDiff::new(&open_wad(old_path)?, &open_wad(new_path)?);
+
+    for lump in &diff.lumps {
+        let change = match lump.change {
+            LumpChange::Added { size } => format!("added\t{size}"),
+            LumpChange::Removed { size } => format!("removed\t{size}"),
+            LumpChange::Changed { old_size, new_size } => {
+                format!("changed\t{old_size} -> {new_size}")
+            }
+        };
+        println!(
+            "{}\t{}\t{change}",
+            namespace_label(&lump.namespace),
+            lump.name
+        );
+    }
+
+    for map in &diff.maps {
+        println!(
+            "map\t{}\t{} -> {}",
+            map.name,
+            counts(map.old),
+            counts(map.new)
+        );
+    }
+
+    Ok(diff.is_empty())
+}
+
+fn counts(counts: Option<MapCounts>) -> String {
+    match counts {
+        Some(counts) => format!(
+            "things={} linedefs={} sidedefs={} vertices={} sectors={}",
+            counts.things, counts.linedefs, counts.sidedefs, counts.vertices, counts.sectors
+        ),
+        None => "none".to_string(),
+    }
+}
@@ -0,0 +1,58 @@
+use std::{fs, path::Path};
+
+use anyhow::{bail, Context, Result};
+use id_map_format::Wad;
+
+/// Writes every lump, or only the ones named in `lump_names`, to `dir`.
+///
+/// Files are prefixed with their directory index, so lumps that share a name
+/// (like every map's THINGS) don't overwrite each other, and sort in WAD
+/// order.
+pub fn run(wad: &Wad, dir: &str, lump_names: &[String]) -> Result<bool> {
+    let lump_names: Vec<String> = lump_names.iter().map(|name| name.to_uppercase()).collect();
+    let dir = Path::new(dir);
+    fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+
+    let mut extracted = 0;
+    for (index, lump) in wad.lumps_in_order.iter().enumerate() {
+        if !lump_names.is_empty() && !lump_names.contains(&lump.name) {
+            continue;
+        }
+
+        let path = dir.join(file_name(index, &lump.name));
+        fs::write(&path, lump.bytes())
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        println!("{}", path.display());
+        extracted += 1;
+    }
+
+    if extracted == 0 && !lump_names.is_empty() {
+        bail!("no lumps named {}", lump_names.join(", "));
+    }
+
+    Ok(true)
+}
+
+/// Lump names can contain characters that aren't safe in paths, like the
+/// backslash in Heretic's sprite names.
+fn file_name(index: usize, lump_name: &str) -> String {
+    let name: String = lump_name
+        .chars()
+        .map(|c| match c {
+            'A'..='Z' | 'a'..='z' | '0'..='9' | '_' | '-' | '[' | ']' => c,
+            _ => '_',
+        })
+        .collect();
+    format!("{index:05}_{name}.lmp")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_names() {
+        assert_eq!(file_name(3, "PLAYPAL"), "00003_PLAYPAL.lmp");
+        assert_eq!(file_name(1200, "VILE\\1"), "01200_VILE_1.lmp");
+    }
+}
@@ -0,0 +1,61 @@
+use anyhow::{bail, Result};
+use id_game_config::{Game, GameConfig, Linter, Severity};
+use id_map_format::Wad;
+
+use crate::open_wad;
+
+/// Prints a diagnostic per line. Fails if there are any errors, or any
+/// warnings with `--strict`.
+pub fn run(args: &[String]) -> Result<bool> {
+    let (flags, paths): (Vec<&String>, Vec<&String>) =
+        args.iter().partition(|arg| arg.starts_with("--"));
+    let mut map_name = None;
+    let mut strict = false;
+    for flag in flags {
+        let (name, value) = flag.split_once('=').unwrap_or((flag, ""));
+        match name {
+            "--map" => map_name = Some(value.to_uppercase()),
+            "--strict" => strict = true,
+            _ => bail!("unknown flag {flag:?}"),
+        }
+    }
+    if paths.is_empty() {
+        bail!("missing WAD paths");
+    }
+
+    let wads = paths
+        .iter()
+        .map(|path| open_wad(path))
+        .collect::<Result<Vec<Wad>>>()?;
+    // Thing types are only checked when the game is known.
+    let config = Game::from_wad(&wads[0])
+        .map(GameConfig::from_game)
+        .transpose()?;
+    let linter = Linter::new(&wads.iter().collect::<Vec<_>>(), config.as_ref());
+
+    let diagnostics = match map_name {
+        Some(map_name) => {
+            let Some(wad) = wads
+                .iter()
+                .rev()
+                .find(|wad| wad.map_names().contains(&map_name))
+            else {
+                bail!("no map named {map_name}");
+            };
+            linter.lint_map(&wad.parse_map(&map_name)?)
+        }
+        None => linter.lint(),
+    };
+
+    for diagnostic in &diagnostics {
+        println!("{diagnostic}");
+    }
+    let errors = diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.severity == Severity::Error)
+        .count();
+    let warnings = diagnostics.len() - errors;
+    eprintln!("{errors} errors, {warnings} warnings");
+
+    Ok(errors == 0 && (!strict || warnings == 0))
+}
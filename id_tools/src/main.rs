@@ -0,0 +1,113 @@
+//! Command line tools for inspecting WADs without opening a window.
+
+use std::process::ExitCode;
+
+use anyhow::{anyhow, bail, Result};
+use id_map_format::Wad;
+
+mod automap;
+mod diff;
+mod extract;
+mod lint;
+mod maps;
+mod merge;
+mod nodes;
+mod textures;
+mod wadinfo;
+
+const USAGE: &str = "\
+Usage: id-tools <command> <wad> [args...]
+
+Commands:
+  wadinfo <wad>                      Header, lump table, namespaces, and game
+  extract <wad> <dir> [lumps...]     Write lumps to files in <dir>
+  maps <wad>                         Maps with thing, line, and sector counts
+  textures <wad>                     TEXTURE1/TEXTURE2 and their patches
+  automap <wad> <map> <out> [flags]  Draw a map to an .svg or .png file
+  nodes <wad> <out> [flags]          Build nodes for the maps and write them to a
+                                     new PWAD
+  merge <iwad> [pwads...]            Where every resource comes from once loaded
+                                     together; --overrides for only replaced ones
+  diff <old> <new>                   Added, removed, and changed lumps and maps;
+                                     fails if the WADs differ
+  lint <iwad> [pwads...] [flags]     Broken references and geometry; fails on
+                                     errors
+
+Automap flags:
+  --scale=<pixels per unit>          Defaults to 0.25
+  --bounds=<x1>,<y1>,<x2>,<y2>       Only draw this part of the map
+  --no-things                        Leave out thing markers
+  --hidden                           Draw lines flagged as hidden
+
+Nodes flags:
+  --map=<map>                        Only build this map
+  --extended                         Extended (ZDoom) nodes instead of vanilla
+  --gl                               Extended GL nodes, with minisegs
+  --split-cost=<n>                   How much to avoid splitting lines; 8 by
+                                     default
+
+Lint flags:
+  --map=<map>                        Only lint this map
+  --strict                           Fail on warnings too";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(error) => {
+            eprintln!("error: {error:#}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Runs a command, returning whether everything it checked was readable.
+fn run(args: &[String]) -> Result<bool> {
+    let Some((command, args)) = args.split_first() else {
+        println!("{USAGE}");
+        return Ok(true);
+    };
+    if matches!(command.as_str(), "help" | "-h" | "--help") {
+        println!("{USAGE}");
+        return Ok(true);
+    }
+
+    // These take more than one WAD.
+    match command.as_str() {
+        "merge" => return merge::run(args),
+        "diff" => return diff::run(args),
+        "lint" => return lint::run(args),
+        _ => {}
+    }
+
+    let (path, args) = match args.split_first() {
+        Some((path, args)) => (Some(path), args),
+        None => (None, args),
+    };
+    // Unknown commands are rejected before the WAD is opened.
+    let wad = || match path {
+        Some(path) => open_wad(path),
+        None => bail!("missing WAD path\n\n{USAGE}"),
+    };
+
+    match command.as_str() {
+        "wadinfo" => wadinfo::run(&wad()?),
+        "extract" => {
+            let Some((dir, lump_names)) = args.split_first() else {
+                bail!("missing output directory\n\n{USAGE}");
+            };
+            extract::run(&wad()?, dir, lump_names)
+        }
+        "maps" => Ok(maps::run(&wad()?)),
+        "textures" => textures::run(&wad()?),
+        "automap" => automap::run(&wad()?, args),
+        "nodes" => nodes::run(&wad()?, args),
+        _ => bail!("unknown command {command:?}\n\n{USAGE}"),
+    }
+}
+
+fn open_wad(path: &str) -> Result<Wad> {
+    // WadError already includes its source in its message.
+    Wad::from_file(path).map_err(|error| anyhow!("{path}: {error}"))
+}
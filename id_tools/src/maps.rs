@@ -0,0 +1,34 @@
+use id_map_format::Wad;
+
+/// Prints one line per map. Maps that fail to parse are reported on stderr,
+/// and make the command fail.
+pub fn run(wad: &Wad) -> bool {
+    let mut ok = true;
+
+    println!("map\tthings\tlinedefs\tsidedefs\tvertices\tsectors\tnodes");
+    for map_name in wad.map_names() {
+        let map = match wad.parse_map(&map_name) {
+            Ok(map) => map,
+            Err(error) => {
+                eprintln!("error: {map_name}: {error}");
+                ok = false;
+                continue;
+            }
+        };
+
+        let nodes = match &map.nodes {
+            Some(nodes) => format!("{} ({:?})", nodes.len(), map.node_format),
+            None => "none".to_string(),
+        };
+        println!(
+            "{map_name}\t{}\t{}\t{}\t{}\t{}\t{nodes}",
+            map.things.len(),
+            map.linedefs.len(),
+            map.sidedefs.len(),
+            map.vertices.len(),
+            map.sectors.len(),
+        );
+    }
+
+    ok
+}
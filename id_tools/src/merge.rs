@@ -0,0 +1,51 @@
+use anyhow::{bail, Result};
+use id_map_format::{MergedWads, ResourceKey, Wad};
+
+use crate::{open_wad, wadinfo::namespace_label};
+
+/// Prints every resource of the WADs loaded in order, with the WAD it's used
+/// from and the WADs it replaces.
+pub fn run(args: &[String]) -> Result<bool> {
+    let (flags, paths): (Vec<&String>, Vec<&String>) =
+        args.iter().partition(|arg| arg.starts_with("--"));
+    let mut only_overrides = false;
+    for flag in flags {
+        match flag.as_str() {
+            "--overrides" => only_overrides = true,
+            _ => bail!("unknown flag {flag:?}"),
+        }
+    }
+    if paths.is_empty() {
+        bail!("missing WAD paths");
+    }
+
+    let wads = paths
+        .iter()
+        .map(|path| open_wad(path))
+        .collect::<Result<Vec<Wad>>>()?;
+    let merged = MergedWads::new(&wads.iter().collect::<Vec<_>>());
+
+    println!("namespace\tname\tsource\toverrides");
+    for (key, resource) in &merged.resources {
+        if only_overrides && resource.overridden.is_empty() {
+            continue;
+        }
+
+        let (namespace, name) = match key {
+            ResourceKey::Lump { namespace, name } => (namespace_label(namespace), name.as_str()),
+            ResourceKey::Map(map_name) => ("map".to_string(), map_name.as_str()),
+        };
+        let overridden: Vec<&str> = resource
+            .overridden
+            .iter()
+            .map(|source| paths[source.wad_index].as_str())
+            .collect();
+        println!(
+            "{namespace}\t{name}\t{}\t{}",
+            paths[resource.source.wad_index],
+            overridden.join(" ")
+        );
+    }
+
+    Ok(true)
+}
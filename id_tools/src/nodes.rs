@@ -0,0 +1,63 @@
+use std::fs;
+
+use anyhow::{bail, Context, Result};
+use id_map_format::{Blockmap, NodeBuilderOptions, NodeFormat, Reject, Wad, WadBuilder};
+
+/// Builds nodes for every map, or just `--map`, and writes the maps to a new
+/// PWAD. Maps without a BLOCKMAP or REJECT get those too.
+pub fn run(wad: &Wad, args: &[String]) -> Result<bool> {
+    let (flags, args): (Vec<&String>, Vec<&String>) =
+        args.iter().partition(|arg| arg.starts_with("--"));
+    let [path] = args[..] else {
+        bail!("expected an output path");
+    };
+
+    let mut options = NodeBuilderOptions::default();
+    let mut map_names = wad.map_names();
+    for flag in flags {
+        let (name, value) = flag.split_once('=').unwrap_or((flag, ""));
+        match name {
+            "--map" => map_names = vec![value.to_uppercase()],
+            "--extended" => options.format = NodeFormat::Extended { compressed: false },
+            "--gl" => {
+                options.format = NodeFormat::ExtendedGl {
+                    version: 2,
+                    compressed: false,
+                }
+            }
+            "--split-cost" => {
+                options.split_cost = value
+                    .parse()
+                    .with_context(|| format!("invalid split cost {value:?}"))?
+            }
+            _ => bail!("unknown flag {flag:?}"),
+        }
+    }
+
+    let mut builder = WadBuilder::new(false);
+    println!("map\tsegs\tsubsectors\tnodes");
+    for map_name in map_names {
+        let mut map = wad.parse_map(&map_name)?;
+        map.build_nodes(&options);
+        // Vanilla can't load a map without a BLOCKMAP, and reads past the
+        // end of an empty REJECT.
+        if map.blockmap.is_none() {
+            map.blockmap = Some(Blockmap::generate(&map));
+        }
+        if map.reject.bits.is_empty() {
+            map.reject = Reject::all_visible(map.sectors.len());
+        }
+        println!(
+            "{map_name}\t{}\t{}\t{}",
+            map.segs.as_ref().map_or(0, Vec::len),
+            map.subsectors.as_ref().map_or(0, Vec::len),
+            map.nodes.as_ref().map_or(0, Vec::len),
+        );
+        builder
+            .set_map_from(&map)
+            .with_context(|| format!("failed to write {map_name}"))?;
+    }
+    fs::write(path, builder.to_bytes()).with_context(|| format!("failed to write {path}"))?;
+
+    Ok(true)
+}
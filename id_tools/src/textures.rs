@@ -0,0 +1,48 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use id_map_format::Wad;
+
+/// Prints one line per texture, with its patches and their offsets. Patches
+/// that can't be read are reported on stderr, and make the command fail.
+pub fn run(wad: &Wad) -> Result<bool> {
+    let textures = wad.parse_textures(&wad.parse_patch_names()?)?;
+    let mut patch_readable = HashMap::new();
+    let mut ok = true;
+
+    println!("texture\tsize\tlump\tpatches");
+    for texture in textures.values() {
+        let patches: Vec<String> = texture
+            .patch_entry
+            .iter()
+            .map(|entry| format!("{}@{},{}", entry.patch_name, entry.x_offset, entry.y_offset))
+            .collect();
+        println!(
+            "{}\t{}x{}\t{}\t{}",
+            texture.name,
+            texture.width,
+            texture.height,
+            if texture.priority {
+                "TEXTURE1"
+            } else {
+                "TEXTURE2"
+            },
+            patches.join(" ")
+        );
+
+        for entry in &texture.patch_entry {
+            let readable = *patch_readable
+                .entry(entry.patch_name.clone())
+                .or_insert_with(|| wad.parse_patch(&entry.patch_name).is_ok());
+            if !readable {
+                eprintln!(
+                    "error: {}: missing or corrupt patch {}",
+                    texture.name, entry.patch_name
+                );
+                ok = false;
+            }
+        }
+    }
+
+    Ok(ok)
+}
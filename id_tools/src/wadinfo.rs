@@ -0,0 +1,55 @@
+use anyhow::Result;
+use id_game_config::Game;
+use id_map_format::{LumpNamespace, Wad};
+
+/// A short label for a namespace, like `map:E1M1`.
+pub fn namespace_label(namespace: &LumpNamespace) -> String {
+    match namespace {
+        LumpNamespace::Global => "global".to_string(),
+        LumpNamespace::Map(map_name) => format!("map:{map_name}"),
+        LumpNamespace::Patch => "patch".to_string(),
+        LumpNamespace::Sprite => "sprite".to_string(),
+        LumpNamespace::Flat => "flat".to_string(),
+    }
+}
+
+pub fn run(wad: &Wad) -> Result<bool> {
+    println!("type\t{}", if wad.is_iwad { "IWAD" } else { "PWAD" });
+    println!("lumps\t{}", wad.lumps_in_order.len());
+    println!("directory\t{}", wad.directory_offset);
+    println!(
+        "game\t{}",
+        Game::from_wad(wad).as_ref().map_or("unknown", Game::name)
+    );
+
+    // Maps are counted together, in the order their namespaces first appear.
+    let mut namespaces: Vec<(String, usize)> = Vec::new();
+    for lump in &wad.lumps_in_order {
+        let label = match &lump.namespace {
+            LumpNamespace::Map(_) => "map".to_string(),
+            namespace => namespace_label(namespace),
+        };
+        match namespaces.iter_mut().find(|(name, _)| *name == label) {
+            Some((_, count)) => *count += 1,
+            None => namespaces.push((label, 1)),
+        }
+    }
+    for (label, count) in &namespaces {
+        println!("namespace\t{label}\t{count}");
+    }
+    println!("maps\t{}", wad.map_names().len());
+
+    println!();
+    println!("index\tname\tnamespace\toffset\tsize");
+    for (index, lump) in wad.lumps_in_order.iter().enumerate() {
+        println!(
+            "{index}\t{}\t{}\t{}\t{}",
+            lump.name,
+            namespace_label(&lump.namespace),
+            lump.offset,
+            lump.size
+        );
+    }
+
+    Ok(true)
+}
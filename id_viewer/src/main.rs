@@ -6,7 +6,7 @@ use std::rc::Rc;
 use std::time::Duration;
 
 use id_core::world::World;
-use id_map_format::Wad;
+use id_map_format::{MapInfo, Wad};
 
 use id_core::renderer::{debug_window, overlay_window, WindowRunner};
 use id_core::renderer::{egui_window, main_user_context, main_window};
@@ -21,7 +21,7 @@ use sdl2_system::ToSystemEventExt;
 
 fn main() -> Result<(), String> {
     let sdl = sdl2::init()?;
-    let sdl_window = sdl
+    let mut sdl_window = sdl
         .video()?
         .window("DOOM", 800, 600)
         .position_centered()
@@ -30,17 +30,40 @@ fn main() -> Result<(), String> {
         .build()
         .map_err(|e| e.to_string())?;
 
-    // Read IWAD.
-    let wad = {
-        let bytes = include_bytes!("../../doom2.wad").to_vec();
-        Wad::new(bytes).expect("Failed to parse IWAD")
-    };
-
-    let world = World::new(wad, vec![], "MAP01").expect("Failed to create world");
+    // Flags can go anywhere; everything else is positional.
+    let (flags, args): (Vec<String>, Vec<String>) = std::env::args()
+        .skip(1)
+        .partition(|arg| arg.starts_with("--"));
+    let show_endoom = flags.iter().any(|flag| flag == "--endoom");
+
+    // Read IWAD: from the path given on the command line, otherwise the one
+    // embedded in the binary. Neither is copied.
+    let wad = match args.first() {
+        Some(path) => Wad::from_file(path),
+        None => {
+            let bytes: &'static [u8] = include_bytes!("../../doom2.wad");
+            Wad::from_bytes(bytes)
+        }
+    }
+    .expect("Failed to parse IWAD");
+
+    // Start on the map given after the IWAD, otherwise the first one in play order.
+    let map_order = MapInfo::builtin(&wad).map_order();
+    let map_name = args
+        .get(1)
+        .map(|name| name.to_uppercase())
+        .or_else(|| map_order.first().cloned())
+        .unwrap_or_else(|| "MAP01".to_string());
+
+    let world = World::new(wad, vec![], &map_name).expect("Failed to create world");
+    sdl_window
+        .set_title(&format!("DOOM - {}", world.level_title()))
+        .map_err(|e| e.to_string())?;
 
     // Create our high level window that will handle events, thinking, and drawing.
 
-    let user_context = main_user_context(Rc::new(RefCell::new(world)));
+    let world = Rc::new(RefCell::new(world));
+    let user_context = main_user_context(world.clone());
     let window = egui_window(overlay_window((main_window(), debug_window())));
 
     let drawable_size = sdl_window.drawable_size();
@@ -98,5 +121,13 @@ fn main() -> Result<(), String> {
         }
     }
 
+    // Like DOS, leave the quit screen in the terminal.
+    if show_endoom {
+        match world.borrow().endoom() {
+            Ok(endoom) => print!("{}", endoom.to_ansi()),
+            Err(e) => eprintln!("No ENDOOM to show: {}", e),
+        }
+    }
+
     Ok(())
 }